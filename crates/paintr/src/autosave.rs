@@ -0,0 +1,272 @@
+//! Autosave of unsaved documents in the background, and recovery of them after a crash
+use druid::{Data, ExtEventSink, Target};
+
+use crate::commands::{AUTOSAVE_TICK, SHOW_NOTIFICATION_ACTION};
+use paintr_core::CanvasData;
+use paintr_widgets::widgets::notif_bar::Notification;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime};
+
+const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(60);
+const DOCUMENT_EXTENSION: &str = "paintr";
+/// The original path of a recovery file is kept beside it with this extension
+const PATH_EXTENSION: &str = "path";
+/// Each session holds a lock beside its recovery file, with its PID and start time
+const LOCK_EXTENSION: &str = "lock";
+/// A lock which was not refreshed for this long is left by a crashed session, on platforms
+/// where the start time of a process is unknown
+#[cfg(not(target_os = "linux"))]
+const LOCK_TIMEOUT: Duration = Duration::from_secs(180);
+
+/// A document left by a previous session
+#[derive(Debug, Clone, PartialEq, Data)]
+pub struct RecoveredDocument {
+    /// The autosaved document
+    pub file: Arc<PathBuf>,
+    /// The path of the document when it was autosaved
+    pub path: Arc<PathBuf>,
+}
+
+impl RecoveredDocument {
+    /// Remove the files of the document from the recovery directory
+    pub fn discard(&self) {
+        remove(&self.file);
+        let _ = std::fs::remove_file(self.file.with_extension(LOCK_EXTENSION));
+    }
+}
+
+enum Message {
+    Write(CanvasData),
+    Remove,
+}
+
+/// Writes the document on a worker thread, one recovery file per process
+#[derive(Debug)]
+pub(crate) struct Autosave {
+    file: PathBuf,
+    sender: Option<mpsc::Sender<Message>>,
+    worker: Option<JoinHandle<()>>,
+    /// The canvas which was written last
+    last: Option<CanvasData>,
+}
+
+/// The directory of recovery files
+pub(crate) fn recovery_dir() -> Option<PathBuf> {
+    Some(dirs::data_dir()?.join("paintr/recovery"))
+}
+
+/// Documents autosaved by other sessions, which were not closed cleanly
+pub(crate) fn find_recovered(dir: &Path) -> Vec<RecoveredDocument> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(it) => it,
+        Err(_) => return Vec::new(),
+    };
+
+    let files: Vec<_> = entries.flatten().map(|it| it.path()).collect();
+    for lock in files.iter().filter(|it| has_extension(it, LOCK_EXTENSION)) {
+        // The lock of a crashed session without unsaved changes
+        if !is_locked(lock) && !lock.with_extension(DOCUMENT_EXTENSION).exists() {
+            let _ = std::fs::remove_file(lock);
+        }
+    }
+
+    let mut docs: Vec<_> = files
+        .into_iter()
+        .filter(|it| has_extension(it, DOCUMENT_EXTENSION))
+        .filter(|it| !is_locked(&it.with_extension(LOCK_EXTENSION)))
+        .map(|file| {
+            let path = std::fs::read_to_string(file.with_extension(PATH_EXTENSION))
+                .map(PathBuf::from)
+                .unwrap_or_else(|_| file.clone());
+            RecoveredDocument { file: Arc::new(file), path: Arc::new(path) }
+        })
+        .collect();
+    docs.sort_by(|a, b| a.file.cmp(&b.file));
+    docs
+}
+
+fn has_extension(file: &Path, extension: &str) -> bool {
+    file.extension().map_or(false, |it| it == extension)
+}
+
+fn recovery_file(dir: &Path) -> PathBuf {
+    dir.join(format!("{}.{}", std::process::id(), DOCUMENT_EXTENSION))
+}
+
+/// The start time of process `pid` in clock ticks after boot, which tells it apart from a
+/// later process with the same PID
+#[cfg(target_os = "linux")]
+fn process_start(pid: u32) -> Option<u64> {
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // The name in parentheses could contain spaces, the start time is the 22nd field
+    let fields = &stat[stat.rfind(')')? + 1..];
+    fields.split_whitespace().nth(19)?.parse().ok()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn process_start(_pid: u32) -> Option<u64> {
+    None
+}
+
+/// Create the lock of the current session, which must not exist yet
+fn create_lock(lock: &Path) -> std::io::Result<()> {
+    let pid = std::process::id();
+    let start = process_start(pid).unwrap_or_else(|| {
+        let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH);
+        now.map_or(0, |it| it.as_secs())
+    });
+
+    let open = || std::fs::OpenOptions::new().write(true).create_new(true).open(lock);
+    let mut file = match open() {
+        // Left by a crashed process with the same PID
+        Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => {
+            std::fs::remove_file(lock)?;
+            open()?
+        }
+        it => it?,
+    };
+    writeln!(file, "{} {}", pid, start)
+}
+
+/// Whether the session holding `lock` is still running
+fn is_locked(lock: &Path) -> bool {
+    let content = match std::fs::read_to_string(lock) {
+        Ok(it) => it,
+        Err(_) => return false,
+    };
+    let mut fields = content.split_whitespace().map(|it| it.parse::<u64>().ok());
+    match (fields.next().flatten(), fields.next().flatten()) {
+        (Some(pid), Some(start)) => is_running(lock, pid as u32, start),
+        _ => false,
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn is_running(_lock: &Path, pid: u32, start: u64) -> bool {
+    process_start(pid) == Some(start)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn is_running(lock: &Path, _pid: u32, _start: u64) -> bool {
+    let modified = std::fs::metadata(lock).and_then(|it| it.modified());
+    modified.ok().and_then(|it| it.elapsed().ok()).map_or(false, |it| it < LOCK_TIMEOUT)
+}
+
+#[cfg(target_os = "linux")]
+fn refresh_lock(_lock: &Path) -> std::io::Result<()> {
+    Ok(())
+}
+
+/// Rewrite the lock, such that it is not taken as left by a crashed session
+#[cfg(not(target_os = "linux"))]
+fn refresh_lock(lock: &Path) -> std::io::Result<()> {
+    let content = std::fs::read(lock)?;
+    std::fs::write(lock, content)
+}
+
+/// Show a warning from the worker thread
+fn warn(sink: &ExtEventSink, msg: String) {
+    let _ = sink.submit_command(SHOW_NOTIFICATION_ACTION, Notification::warning(msg), Target::Auto);
+}
+
+fn write(file: &Path, mut canvas: CanvasData) -> Result<(), Box<dyn std::error::Error>> {
+    // Write to another directory first, such that a crash does not leave a broken document
+    let dir = file.parent().ok_or("Invalid recovery file")?;
+    let tmp = dir.join("tmp").join(file.file_name().ok_or("Invalid recovery file")?);
+    std::fs::create_dir_all(dir.join("tmp"))?;
+
+    std::fs::write(
+        file.with_extension(PATH_EXTENSION),
+        canvas.path().to_string_lossy().as_bytes(),
+    )?;
+    canvas.save(&tmp)?;
+    std::fs::rename(&tmp, file)?;
+    Ok(())
+}
+
+fn remove(file: &Path) {
+    let _ = std::fs::remove_file(file);
+    let _ = std::fs::remove_file(file.with_extension(PATH_EXTENSION));
+}
+
+impl Autosave {
+    /// Lock the recovery file of this session and start the worker, failures are shown by `sink`
+    pub(crate) fn start(dir: PathBuf, sink: ExtEventSink) -> std::io::Result<Autosave> {
+        let file = recovery_file(&dir);
+        let lock = file.with_extension(LOCK_EXTENSION);
+        std::fs::create_dir_all(&dir)?;
+        create_lock(&lock)?;
+        let (sender, receiver) = mpsc::channel();
+
+        let worker_file = file.clone();
+        let worker = std::thread::spawn(move || {
+            let mut refreshed = Instant::now();
+            loop {
+                match receiver.recv_timeout(AUTOSAVE_INTERVAL) {
+                    Ok(Message::Write(canvas)) => {
+                        if let Err(err) = write(&worker_file, canvas) {
+                            warn(&sink, format!("Failed to autosave: {}", err));
+                        }
+                    }
+                    Ok(Message::Remove) => remove(&worker_file),
+                    Err(mpsc::RecvTimeoutError::Timeout) => (),
+                    Err(mpsc::RecvTimeoutError::Disconnected) => break,
+                }
+                if refreshed.elapsed() >= AUTOSAVE_INTERVAL {
+                    refreshed = Instant::now();
+                    if let Err(err) = refresh_lock(&lock) {
+                        warn(&sink, format!("Failed to refresh the autosave lock: {}", err));
+                    }
+                }
+            }
+        });
+
+        Ok(Autosave { file, sender: Some(sender), worker: Some(worker), last: None })
+    }
+
+    /// Submit `AUTOSAVE_TICK` periodically until the app is closed
+    pub(crate) fn start_timer(sink: ExtEventSink) {
+        std::thread::spawn(move || loop {
+            std::thread::sleep(AUTOSAVE_INTERVAL);
+            if sink.submit_command(AUTOSAVE_TICK, (), Target::Auto).is_err() {
+                break;
+            }
+        });
+    }
+
+    /// Write `canvas` if it has changed since the last time, or remove the recovery file
+    /// if there is nothing unsaved
+    pub(crate) fn update(&mut self, canvas: Option<&CanvasData>, dirty: bool) {
+        let msg = match canvas {
+            Some(canvas) if dirty => {
+                if self.last.as_ref().map_or(false, |it| it.same(canvas)) {
+                    return;
+                }
+                self.last = Some(canvas.clone());
+                Message::Write(canvas.clone())
+            }
+            _ if self.last.is_some() => {
+                self.last = None;
+                Message::Remove
+            }
+            _ => return,
+        };
+
+        if let Some(sender) = &self.sender {
+            let _ = sender.send(msg);
+        }
+    }
+
+    /// Wait for the pending writes, then remove the recovery file and the lock
+    pub(crate) fn finish(&mut self) {
+        self.sender = None;
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+        remove(&self.file);
+        let _ = std::fs::remove_file(self.file.with_extension(LOCK_EXTENSION));
+    }
+}
@@ -1,25 +1,113 @@
-use druid::{Command, FileDialogOptions, FileSpec, Selector, Target};
+use druid::{Command, FileDialogOptions, FileInfo, FileSpec, Selector, Target};
 
-use crate::dialogs::NewFileSettings;
-const IMAGE_FILE_TYPE: FileSpec = FileSpec::new("Images", &["bmp", "png", "gif", "jpg", "jpeg"]);
+use crate::autosave::RecoveredDocument;
+use crate::dialogs::{
+    ColorRangeSettings, ExportSettings, LayerProperties, ModifyKind, ModifySelectionSettings,
+    NewFileSettings, RecoverDocuments, SnapshotSettings, UnsavedChanges,
+};
+use paintr_core::BlendMode;
+use paintr_widgets::widgets::notif_bar::Notification;
+use std::{path::PathBuf, sync::Arc};
+const IMAGE_FILE_TYPE: FileSpec =
+    FileSpec::new("Images", &["bmp", "png", "gif", "jpg", "jpeg", "webp", "tif", "tiff", "tga"]);
+const DOCUMENT_FILE_TYPE: FileSpec = FileSpec::new("Paintr Document", &["paintr"]);
+const ORA_FILE_TYPE: FileSpec = FileSpec::new("OpenRaster", &["ora"]);
+const PSD_FILE_TYPE: FileSpec = FileSpec::new("Photoshop", &["psd"]);
 
 pub(crate) const FILE_EXIT_ACTION: Selector = Selector::new("menu-exit-action");
+pub(crate) const FILE_OPEN_ACTION: Selector = Selector::new("menu-open-action");
+/// Open a file, asking about unsaved changes first
+pub(crate) const FILE_OPEN_PATH_ACTION: Selector<PathBuf> = Selector::new("menu-open-path-action");
+pub(crate) const FILE_CLEAR_RECENT_ACTION: Selector = Selector::new("menu-clear-recent-action");
+pub(crate) const FILE_SAVE_ACTION: Selector = Selector::new("menu-save-action");
+/// The path chosen in the panel of `file_export_as_command`
+pub(crate) const FILE_EXPORT_AS: Selector<FileInfo> = Selector::new("file-export-as");
 pub(crate) const FILE_NEW_ACTION: Selector = Selector::new("menu-new-action");
 pub(crate) const FILE_NEW_CLIPBOARD_ACTION: Selector = Selector::new("menu-new-clipboard-action");
 
 pub(crate) const EDIT_UNDO_ACTION: Selector = Selector::new("edit-undo-action");
 pub(crate) const EDIT_REDO_ACTION: Selector = Selector::new("edit-redo-action");
+pub(crate) const EDIT_PREVIOUS_BRANCH_ACTION: Selector =
+    Selector::new("edit-previous-branch-action");
+pub(crate) const EDIT_NEXT_BRANCH_ACTION: Selector = Selector::new("edit-next-branch-action");
 pub(crate) const EDIT_COPY_ACTION: Selector = Selector::new("edit-copy-action");
 pub(crate) const EDIT_PASTE_ACTION: Selector = Selector::new("edit-paste-action");
+pub(crate) const EDIT_FILL_FOREGROUND_ACTION: Selector =
+    Selector::new("edit-fill-foreground-action");
+pub(crate) const EDIT_FILL_BACKGROUND_ACTION: Selector =
+    Selector::new("edit-fill-background-action");
+pub(crate) const EDIT_SWAP_COLORS_ACTION: Selector = Selector::new("edit-swap-colors-action");
+pub(crate) const EDIT_RESET_COLORS_ACTION: Selector = Selector::new("edit-reset-colors-action");
+pub(crate) const EDIT_SELECT_COLOR_RANGE_DIALOG_ACTION: Selector =
+    Selector::new("edit-select-color-range-dialog-action");
+
+pub(crate) const SELECT_ALL_ACTION: Selector = Selector::new("select-all-action");
+pub(crate) const SELECT_DESELECT_ACTION: Selector = Selector::new("select-deselect-action");
+pub(crate) const SELECT_INVERT_ACTION: Selector = Selector::new("select-invert-action");
+pub(crate) const SELECT_MODIFY_DIALOG_ACTION: Selector<ModifyKind> =
+    Selector::new("select-modify-dialog-action");
+
+pub(crate) const LAYER_NEW_ACTION: Selector = Selector::new("layer-new-action");
+pub(crate) const LAYER_DUPLICATE_ACTION: Selector = Selector::new("layer-duplicate-action");
+pub(crate) const LAYER_DELETE_ACTION: Selector = Selector::new("layer-delete-action");
+pub(crate) const LAYER_MERGE_DOWN_ACTION: Selector = Selector::new("layer-merge-down-action");
+pub(crate) const LAYER_RAISE_ACTION: Selector = Selector::new("layer-raise-action");
+pub(crate) const LAYER_LOWER_ACTION: Selector = Selector::new("layer-lower-action");
+pub(crate) const LAYER_SELECT_ABOVE_ACTION: Selector = Selector::new("layer-select-above-action");
+pub(crate) const LAYER_SELECT_BELOW_ACTION: Selector = Selector::new("layer-select-below-action");
+pub(crate) const LAYER_TOGGLE_VISIBLE_ACTION: Selector =
+    Selector::new("layer-toggle-visible-action");
+pub(crate) const LAYER_TOGGLE_LOCK_PIXELS_ACTION: Selector =
+    Selector::new("layer-toggle-lock-pixels-action");
+pub(crate) const LAYER_TOGGLE_LOCK_POSITION_ACTION: Selector =
+    Selector::new("layer-toggle-lock-position-action");
+pub(crate) const LAYER_PROPERTIES_DIALOG_ACTION: Selector =
+    Selector::new("layer-properties-dialog-action");
+
+pub(crate) const VIEW_TOGGLE_HISTORY_ACTION: Selector = Selector::new("view-toggle-history-action");
+
+pub(crate) const HISTORY_NEW_SNAPSHOT_DIALOG_ACTION: Selector =
+    Selector::new("history-new-snapshot-dialog-action");
+pub(crate) const HISTORY_NEW_DOCUMENT_ACTION: Selector =
+    Selector::new("history-new-document-action");
+
+/// Submitted periodically, such that the document could be autosaved
+pub(crate) const AUTOSAVE_TICK: Selector = Selector::new("autosave-tick");
+pub(crate) const RECOVER_DOCUMENTS_DIALOG_ACTION: Selector<Arc<Vec<RecoveredDocument>>> =
+    Selector::new("recover-documents-dialog-action");
+/// Show a notification from another thread
+pub(crate) const SHOW_NOTIFICATION_ACTION: Selector<Notification> =
+    Selector::new("show-notification-action");
 
 pub(crate) const ABOUT_TEST_ACTION: Selector = Selector::new("about-test-action");
 
 pub(crate) const NEW_IMAGE_ACTION: Selector<NewFileSettings> = Selector::new("new-image-action");
+pub(crate) const LAYER_PROPERTIES_ACTION: Selector<LayerProperties> =
+    Selector::new("layer-properties-action");
+pub(crate) const SELECT_COLOR_RANGE_ACTION: Selector<ColorRangeSettings> =
+    Selector::new("select-color-range-action");
+pub(crate) const SELECT_MODIFY_ACTION: Selector<ModifySelectionSettings> =
+    Selector::new("select-modify-action");
+pub(crate) const HISTORY_NEW_SNAPSHOT_ACTION: Selector<SnapshotSettings> =
+    Selector::new("history-new-snapshot-action");
+pub(crate) const EXPORT_IMAGE_ACTION: Selector<ExportSettings> =
+    Selector::new("export-image-action");
+pub(crate) const UNSAVED_CHANGES_ACTION: Selector<UnsavedChanges> =
+    Selector::new("unsaved-changes-action");
+pub(crate) const RECOVER_DOCUMENTS_ACTION: Selector<RecoverDocuments> =
+    Selector::new("recover-documents-action");
+pub(crate) const LAYER_BLEND_MODE_ACTION: Selector<BlendMode> =
+    Selector::new("layer-blend-mode-action");
 
 pub(crate) fn file_open_command() -> Command {
     Command::new(
         druid::commands::SHOW_OPEN_PANEL,
-        FileDialogOptions::new().allowed_types(vec![IMAGE_FILE_TYPE]),
+        FileDialogOptions::new().allowed_types(vec![
+            IMAGE_FILE_TYPE,
+            DOCUMENT_FILE_TYPE,
+            ORA_FILE_TYPE,
+            PSD_FILE_TYPE,
+        ]),
         Target::Auto,
     )
 }
@@ -27,7 +115,17 @@ pub(crate) fn file_open_command() -> Command {
 pub(crate) fn file_save_as_command() -> Command {
     Command::new(
         druid::commands::SHOW_SAVE_PANEL,
-        FileDialogOptions::new().allowed_types(vec![IMAGE_FILE_TYPE]),
+        FileDialogOptions::new().allowed_types(vec![DOCUMENT_FILE_TYPE, ORA_FILE_TYPE]),
+        Target::Auto,
+    )
+}
+
+pub(crate) fn file_export_as_command() -> Command {
+    Command::new(
+        druid::commands::SHOW_SAVE_PANEL,
+        FileDialogOptions::new()
+            .allowed_types(vec![IMAGE_FILE_TYPE])
+            .accept_command(FILE_EXPORT_AS),
         Target::Auto,
     )
 }
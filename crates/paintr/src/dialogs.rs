@@ -3,15 +3,27 @@ use druid::{
     Color, Command, Data, Env, Lens, Target, Widget,
 };
 use druid::{
-    widget::{Button, Flex, Label, LabelText, LensWrap, Parse, TextBox, WidgetExt},
+    widget::{
+        Button, Checkbox, CrossAxisAlignment, Flex, Label, LabelText, LensWrap, LineBreaking,
+        Parse, RadioGroup, Slider, TextBox, WidgetExt,
+    },
     TextAlignment,
 };
 
-use crate::commands::NEW_IMAGE_ACTION;
-use paintr_widgets::widgets::Modal;
+use crate::autosave::RecoveredDocument;
+use crate::commands::{
+    EXPORT_IMAGE_ACTION, HISTORY_NEW_SNAPSHOT_ACTION, LAYER_PROPERTIES_ACTION, NEW_IMAGE_ACTION,
+    RECOVER_DOCUMENTS_ACTION, SELECT_COLOR_RANGE_ACTION, SELECT_MODIFY_ACTION,
+    UNSAVED_CHANGES_ACTION,
+};
+use paintr_widgets::widgets::{ColorPicker, Modal};
 
 use image::GenericImageView;
-use paintr_core::get_image_from_clipboard;
+use paintr_core::{
+    get_image_from_clipboard, ExportFormat, ExportOptions, Layer, LayerId, PngCompression,
+    PngFilter, SelectionModify,
+};
+use std::{path::PathBuf, sync::Arc};
 
 #[derive(Eq, PartialEq, Clone, Debug, Data)]
 enum DialogState {
@@ -111,9 +123,478 @@ impl NewFileSettings {
     }
 }
 
-#[derive(Eq, PartialEq, Clone, Debug, Data)]
+#[derive(Data, Eq, PartialEq, Clone, Lens, Debug)]
+pub struct LayerProperties {
+    pub id: LayerId,
+    pub name: String,
+    /// Opacity in percent
+    pub opacity: Option<u32>,
+}
+
+impl LayerProperties {
+    fn widget(&self) -> impl Widget<Dialog<LayerProperties>> {
+        let ok_button =
+            Button::new(L!("Ok")).on_click(|_, data: &mut Dialog<LayerProperties>, _: &Env| {
+                match data.kind.opacity {
+                    Some(opacity) if opacity <= 100 => data.state = DialogState::Closed,
+                    _ => (),
+                }
+            });
+
+        let cancel_button =
+            Button::new(L!("Cancel")).on_click(|_, data: &mut Dialog<LayerProperties>, _: &Env| {
+                data.state = DialogState::Cancel;
+            });
+
+        let row_padding = 2.5;
+
+        Flex::column()
+            .with_child(
+                Flex::row()
+                    .with_child(make_label(L!("Name :")))
+                    .with_flex_child(
+                        TextBox::new()
+                            .padding(row_padding)
+                            .lens(dialog_lens!(LayerProperties, name)),
+                        1.0,
+                    )
+                    .padding((3.0, row_padding)),
+            )
+            .with_child(
+                Flex::row()
+                    .with_child(make_label(L!("Opacity (%) :")))
+                    .with_flex_child(
+                        Parse::new(TextBox::new().with_placeholder("100"))
+                            .padding(row_padding)
+                            .lens(dialog_lens!(LayerProperties, opacity)),
+                        1.0,
+                    )
+                    .padding((3.0, row_padding)),
+            )
+            .with_child(
+                Flex::row()
+                    .with_flex_child(ok_button.padding(5.0).center(), 1.0)
+                    .with_flex_child(cancel_button.padding(5.0).center(), 1.0)
+                    .padding((3.0, 5.0)),
+            )
+            .fix_width(300.0)
+            .fix_height(100.0)
+            .background(Color::grey(0.3))
+            .center()
+    }
+}
+
+#[derive(Data, PartialEq, Clone, Lens, Debug)]
+pub struct ColorRangeSettings {
+    pub color: Color,
+    /// Maximum difference of a channel to be fully selected, from 0 to 255
+    pub fuzziness: f64,
+    pub sample_merged: bool,
+}
+
+impl ColorRangeSettings {
+    fn widget(&self) -> impl Widget<Dialog<ColorRangeSettings>> {
+        let ok_button =
+            Button::new(L!("Ok")).on_click(|_, data: &mut Dialog<ColorRangeSettings>, _: &Env| {
+                data.state = DialogState::Closed;
+            });
+
+        let cancel_button = Button::new(L!("Cancel")).on_click(
+            |_, data: &mut Dialog<ColorRangeSettings>, _: &Env| {
+                data.state = DialogState::Cancel;
+            },
+        );
+
+        let row_padding = 2.5;
+
+        let fuzziness = Flex::row()
+            .with_flex_child(Slider::new().with_range(0.0, 255.0).expand_width(), 1.0)
+            .with_child(Label::dynamic(|v: &f64, _| format!("{:.0}", v)).fix_width(40.0))
+            .lens(dialog_lens!(ColorRangeSettings, fuzziness));
+
+        Flex::column()
+            .with_child(
+                ColorPicker::new()
+                    .padding(row_padding)
+                    .lens(dialog_lens!(ColorRangeSettings, color)),
+            )
+            .with_child(
+                Flex::row()
+                    .with_child(make_label(L!("Fuzziness :")))
+                    .with_flex_child(fuzziness.padding(row_padding), 1.0)
+                    .padding((3.0, row_padding)),
+            )
+            .with_child(
+                Checkbox::new(L!("magic-wand-sample-merged"))
+                    .lens(dialog_lens!(ColorRangeSettings, sample_merged))
+                    .padding((3.0, row_padding)),
+            )
+            .with_child(
+                Flex::row()
+                    .with_flex_child(ok_button.padding(5.0).center(), 1.0)
+                    .with_flex_child(cancel_button.padding(5.0).center(), 1.0)
+                    .padding((3.0, 5.0)),
+            )
+            .fix_width(300.0)
+            .fix_height(360.0)
+            .background(Color::grey(0.3))
+            .center()
+    }
+}
+
+#[derive(Data, Eq, PartialEq, Clone, Copy, Debug)]
+pub enum ModifyKind {
+    Grow,
+    Shrink,
+    Border,
+    Smooth,
+    Feather,
+}
+
+#[derive(Data, Eq, PartialEq, Clone, Lens, Debug)]
+pub struct ModifySelectionSettings {
+    pub kind: ModifyKind,
+    /// Amount in pixels
+    pub amount: Option<u32>,
+}
+
+impl ModifySelectionSettings {
+    pub fn modify(&self) -> Option<SelectionModify> {
+        let n = self.amount?;
+        Some(match self.kind {
+            ModifyKind::Grow => SelectionModify::Grow(n),
+            ModifyKind::Shrink => SelectionModify::Shrink(n),
+            ModifyKind::Border => SelectionModify::Border(n),
+            ModifyKind::Smooth => SelectionModify::Smooth(n),
+            ModifyKind::Feather => SelectionModify::Feather(n as f64),
+        })
+    }
+
+    fn widget(&self) -> impl Widget<Dialog<ModifySelectionSettings>> {
+        let ok_button = Button::new(L!("Ok")).on_click(
+            |_, data: &mut Dialog<ModifySelectionSettings>, _: &Env| {
+                if data.kind.amount.is_some() {
+                    data.state = DialogState::Closed;
+                }
+            },
+        );
+
+        let cancel_button = Button::new(L!("Cancel")).on_click(
+            |_, data: &mut Dialog<ModifySelectionSettings>, _: &Env| {
+                data.state = DialogState::Cancel;
+            },
+        );
+
+        let label = match self.kind {
+            ModifyKind::Grow => L!("Grow By (px) :"),
+            ModifyKind::Shrink => L!("Shrink By (px) :"),
+            ModifyKind::Border => L!("Width (px) :"),
+            ModifyKind::Smooth => L!("Radius (px) :"),
+            ModifyKind::Feather => L!("Feather Radius (px) :"),
+        };
+
+        let row_padding = 2.5;
+
+        Flex::column()
+            .with_child(
+                Flex::row()
+                    .with_child(make_label(label))
+                    .with_flex_child(
+                        Parse::new(TextBox::new().with_placeholder("1"))
+                            .padding(row_padding)
+                            .lens(dialog_lens!(ModifySelectionSettings, amount)),
+                        1.0,
+                    )
+                    .padding((3.0, row_padding)),
+            )
+            .with_child(
+                Flex::row()
+                    .with_flex_child(ok_button.padding(5.0).center(), 1.0)
+                    .with_flex_child(cancel_button.padding(5.0).center(), 1.0)
+                    .padding((3.0, 5.0)),
+            )
+            .fix_width(300.0)
+            .fix_height(75.0)
+            .background(Color::grey(0.3))
+            .center()
+    }
+}
+
+#[derive(Data, Eq, PartialEq, Clone, Lens, Debug)]
+pub struct SnapshotSettings {
+    pub name: String,
+}
+
+impl SnapshotSettings {
+    fn widget(&self) -> impl Widget<Dialog<SnapshotSettings>> {
+        let ok_button =
+            Button::new(L!("Ok")).on_click(|_, data: &mut Dialog<SnapshotSettings>, _: &Env| {
+                if !data.kind.name.trim().is_empty() {
+                    data.state = DialogState::Closed;
+                }
+            });
+
+        let cancel_button = Button::new(L!("Cancel")).on_click(
+            |_, data: &mut Dialog<SnapshotSettings>, _: &Env| {
+                data.state = DialogState::Cancel;
+            },
+        );
+
+        let row_padding = 2.5;
+
+        Flex::column()
+            .with_child(
+                Flex::row()
+                    .with_child(make_label(L!("Name :")))
+                    .with_flex_child(
+                        TextBox::new()
+                            .padding(row_padding)
+                            .lens(dialog_lens!(SnapshotSettings, name)),
+                        1.0,
+                    )
+                    .padding((3.0, row_padding)),
+            )
+            .with_child(
+                Flex::row()
+                    .with_flex_child(ok_button.padding(5.0).center(), 1.0)
+                    .with_flex_child(cancel_button.padding(5.0).center(), 1.0)
+                    .padding((3.0, 5.0)),
+            )
+            .fix_width(300.0)
+            .fix_height(75.0)
+            .background(Color::grey(0.3))
+            .center()
+    }
+}
+
+#[derive(Data, PartialEq, Clone, Lens, Debug)]
+pub struct ExportSettings {
+    pub path: Arc<PathBuf>,
+    pub options: ExportOptions,
+}
+
+impl ExportSettings {
+    fn widget(&self) -> impl Widget<Dialog<ExportSettings>> {
+        let ok_button =
+            Button::new(L!("Ok")).on_click(|_, data: &mut Dialog<ExportSettings>, _: &Env| {
+                data.state = DialogState::Closed;
+            });
+
+        let cancel_button =
+            Button::new(L!("Cancel")).on_click(|_, data: &mut Dialog<ExportSettings>, _: &Env| {
+                data.state = DialogState::Cancel;
+            });
+
+        let row_padding = 2.5;
+
+        let quality = Flex::row()
+            .with_child(make_label(L!("Quality :")))
+            .with_flex_child(
+                Flex::row()
+                    .with_flex_child(Slider::new().with_range(1.0, 100.0).expand_width(), 1.0)
+                    .with_child(Label::dynamic(|v: &f64, _| format!("{:.0}", v)).fix_width(40.0))
+                    .lens(dialog_lens!(ExportSettings, options).then(ExportOptions::quality))
+                    .padding(row_padding),
+                1.0,
+            )
+            .padding((3.0, row_padding));
+
+        let format = self.options.format;
+        let title = L!("export-options-title").with_arg("format", move |_, _| format.name().into());
+        let mut col = Flex::column().with_child(Label::new(title).padding((3.0, 5.0)));
+        let height = match self.options.format {
+            ExportFormat::Jpeg => {
+                col.add_child(quality);
+                col.add_child(make_label(L!("Background :")).padding((3.0, row_padding)));
+                col.add_child(
+                    ColorPicker::new().padding(row_padding).lens(
+                        dialog_lens!(ExportSettings, options).then(ExportOptions::background),
+                    ),
+                );
+                420.0
+            }
+            ExportFormat::Png => {
+                let compression = PngCompression::ALL.iter().map(|it| (it.name(), *it));
+                let filter = PngFilter::ALL.iter().map(|it| (it.name(), *it));
+                col.add_child(
+                    Flex::row()
+                        .with_child(make_label(L!("Compression :")))
+                        .with_flex_child(
+                            RadioGroup::new(compression).lens(
+                                dialog_lens!(ExportSettings, options)
+                                    .then(ExportOptions::png_compression),
+                            ),
+                            1.0,
+                        )
+                        .cross_axis_alignment(CrossAxisAlignment::Start)
+                        .padding((3.0, row_padding)),
+                );
+                col.add_child(
+                    Flex::row()
+                        .with_child(make_label(L!("Filter :")))
+                        .with_flex_child(
+                            RadioGroup::new(filter).lens(
+                                dialog_lens!(ExportSettings, options)
+                                    .then(ExportOptions::png_filter),
+                            ),
+                            1.0,
+                        )
+                        .cross_axis_alignment(CrossAxisAlignment::Start)
+                        .padding((3.0, row_padding)),
+                );
+                300.0
+            }
+            ExportFormat::WebP => {
+                col.add_child(quality);
+                col.add_child(
+                    Checkbox::new(L!("Lossless"))
+                        .lens(dialog_lens!(ExportSettings, options).then(ExportOptions::lossless))
+                        .padding((3.0, row_padding)),
+                );
+                130.0
+            }
+            _ => 75.0,
+        };
+
+        col.with_child(
+            Flex::row()
+                .with_flex_child(ok_button.padding(5.0).center(), 1.0)
+                .with_flex_child(cancel_button.padding(5.0).center(), 1.0)
+                .padding((3.0, 5.0)),
+        )
+        .fix_width(300.0)
+        .fix_height(height)
+        .background(Color::grey(0.3))
+        .center()
+    }
+}
+
+/// An action which discards the current document
+#[derive(Data, Eq, PartialEq, Clone, Debug)]
+pub enum DiscardAction {
+    Open,
+    OpenPath(Arc<PathBuf>),
+    New,
+    NewFromClipboard,
+    Exit,
+}
+
+#[derive(Data, Eq, PartialEq, Clone, Lens, Debug)]
+pub struct UnsavedChanges {
+    pub name: String,
+    pub action: DiscardAction,
+    /// Save the document before the action
+    pub save: bool,
+}
+
+impl UnsavedChanges {
+    fn widget(&self) -> impl Widget<Dialog<UnsavedChanges>> {
+        let save_button =
+            Button::new(L!("Save")).on_click(|_, data: &mut Dialog<UnsavedChanges>, _: &Env| {
+                data.kind.save = true;
+                data.state = DialogState::Closed;
+            });
+
+        let discard_button = Button::new(L!("Don't Save")).on_click(
+            |_, data: &mut Dialog<UnsavedChanges>, _: &Env| {
+                data.kind.save = false;
+                data.state = DialogState::Closed;
+            },
+        );
+
+        let cancel_button =
+            Button::new(L!("Cancel")).on_click(|_, data: &mut Dialog<UnsavedChanges>, _: &Env| {
+                data.state = DialogState::Cancel;
+            });
+
+        let message = format!("Save changes to \"{}\" before closing?", self.name);
+
+        Flex::column()
+            .with_child(
+                Label::new(message).with_line_break_mode(LineBreaking::WordWrap).padding(10.0),
+            )
+            .with_child(
+                Flex::row()
+                    .with_flex_child(save_button.padding(5.0).center(), 1.0)
+                    .with_flex_child(discard_button.padding(5.0).center(), 1.0)
+                    .with_flex_child(cancel_button.padding(5.0).center(), 1.0)
+                    .padding((3.0, 5.0)),
+            )
+            .fix_width(300.0)
+            .fix_height(100.0)
+            .background(Color::grey(0.3))
+            .center()
+    }
+}
+
+#[derive(Data, PartialEq, Clone, Lens, Debug)]
+pub struct RecoverDocuments {
+    pub documents: Arc<Vec<RecoveredDocument>>,
+    /// The document to open, all of them are discarded if it is `None`
+    pub open: Option<usize>,
+}
+
+impl RecoverDocuments {
+    fn widget(&self) -> impl Widget<Dialog<RecoverDocuments>> {
+        let discard_button = Button::new(L!("Discard All")).on_click(
+            |_, data: &mut Dialog<RecoverDocuments>, _: &Env| {
+                data.kind.open = None;
+                data.state = DialogState::Closed;
+            },
+        );
+
+        // Keep them for the next start
+        let cancel_button =
+            Button::new(L!("Later")).on_click(|_, data: &mut Dialog<RecoverDocuments>, _: &Env| {
+                data.state = DialogState::Cancel;
+            });
+
+        let row_padding = 2.5;
+
+        let mut col = Flex::column().with_child(
+            Label::new(L!("These documents were not saved when paintr was closed :"))
+                .with_line_break_mode(LineBreaking::WordWrap)
+                .padding((3.0, 5.0)),
+        );
+        for (index, doc) in self.documents.iter().enumerate() {
+            let name = doc.path.file_name().unwrap_or_default().to_string_lossy().to_string();
+            let open_button = Button::new(L!("Recover")).on_click(
+                move |_, data: &mut Dialog<RecoverDocuments>, _: &Env| {
+                    data.kind.open = Some(index);
+                    data.state = DialogState::Closed;
+                },
+            );
+            col.add_child(
+                Flex::row()
+                    .with_flex_child(Label::new(name).padding(row_padding).expand_width(), 1.0)
+                    .with_child(open_button.padding(row_padding))
+                    .padding((3.0, row_padding)),
+            );
+        }
+
+        col.with_child(
+            Flex::row()
+                .with_flex_child(discard_button.padding(5.0).center(), 1.0)
+                .with_flex_child(cancel_button.padding(5.0).center(), 1.0)
+                .padding((3.0, 5.0)),
+        )
+        .fix_width(300.0)
+        .fix_height(100.0 + 35.0 * self.documents.len() as f64)
+        .background(Color::grey(0.3))
+        .center()
+    }
+}
+
+#[derive(PartialEq, Clone, Debug, Data)]
 pub enum DialogData {
     NewFileSettings(Dialog<NewFileSettings>),
+    LayerProperties(Dialog<LayerProperties>),
+    ColorRangeSettings(Dialog<ColorRangeSettings>),
+    ModifySelectionSettings(Dialog<ModifySelectionSettings>),
+    SnapshotSettings(Dialog<SnapshotSettings>),
+    ExportSettings(Dialog<ExportSettings>),
+    UnsavedChanges(Dialog<UnsavedChanges>),
+    RecoverDocuments(Dialog<RecoverDocuments>),
 }
 
 impl Modal for DialogData {
@@ -122,35 +603,118 @@ impl Modal for DialogData {
             DialogData::NewFileSettings(it) if it.state == DialogState::Closed => {
                 Some(Command::new(NEW_IMAGE_ACTION, it.kind.clone(), Target::Auto))
             }
+            DialogData::LayerProperties(it) if it.state == DialogState::Closed => {
+                Some(Command::new(LAYER_PROPERTIES_ACTION, it.kind.clone(), Target::Auto))
+            }
+            DialogData::ColorRangeSettings(it) if it.state == DialogState::Closed => {
+                Some(Command::new(SELECT_COLOR_RANGE_ACTION, it.kind.clone(), Target::Auto))
+            }
+            DialogData::ModifySelectionSettings(it) if it.state == DialogState::Closed => {
+                Some(Command::new(SELECT_MODIFY_ACTION, it.kind.clone(), Target::Auto))
+            }
+            DialogData::SnapshotSettings(it) if it.state == DialogState::Closed => {
+                Some(Command::new(HISTORY_NEW_SNAPSHOT_ACTION, it.kind.clone(), Target::Auto))
+            }
+            DialogData::ExportSettings(it) if it.state == DialogState::Closed => {
+                Some(Command::new(EXPORT_IMAGE_ACTION, it.kind.clone(), Target::Auto))
+            }
+            DialogData::UnsavedChanges(it) if it.state == DialogState::Closed => {
+                Some(Command::new(UNSAVED_CHANGES_ACTION, it.kind.clone(), Target::Auto))
+            }
+            DialogData::RecoverDocuments(it) if it.state == DialogState::Closed => {
+                Some(Command::new(RECOVER_DOCUMENTS_ACTION, it.kind.clone(), Target::Auto))
+            }
             _ => None,
         }
     }
 }
 
+macro_rules! dialog_widget {
+    ($dialog:expr, $variant:ident) => {
+        match $dialog.state {
+            DialogState::Cancel | DialogState::Closed => None,
+            DialogState::Opened => {
+                let fallback = $dialog.clone();
+                let w = LensWrap::new(
+                    $dialog.kind.widget(),
+                    lens::Identity.map(
+                        move |x: &DialogData| match x {
+                            DialogData::$variant(it) => it.clone(),
+                            _ => fallback.clone(),
+                        },
+                        |x: &mut DialogData, y| {
+                            *x = DialogData::$variant(y);
+                        },
+                    ),
+                );
+
+                Some(Box::new(w) as Box<dyn Widget<DialogData>>)
+            }
+        }
+    };
+}
+
 impl DialogData {
     pub fn widget(&self) -> Option<Box<dyn Widget<DialogData>>> {
         match self {
-            DialogData::NewFileSettings(dialog) => match dialog.state {
-                DialogState::Cancel | DialogState::Closed => None,
-                DialogState::Opened => {
-                    let w = LensWrap::new(
-                        dialog.kind.widget(),
-                        lens::Identity.map(
-                            |x: &DialogData| match x {
-                                DialogData::NewFileSettings(it) => it.clone(),
-                            },
-                            |x: &mut DialogData, y: Dialog<NewFileSettings>| {
-                                *x = DialogData::NewFileSettings(y);
-                            },
-                        ),
-                    );
-
-                    Some(Box::new(w))
-                }
-            },
+            DialogData::NewFileSettings(dialog) => dialog_widget!(dialog, NewFileSettings),
+            DialogData::LayerProperties(dialog) => dialog_widget!(dialog, LayerProperties),
+            DialogData::ColorRangeSettings(dialog) => dialog_widget!(dialog, ColorRangeSettings),
+            DialogData::ModifySelectionSettings(dialog) => {
+                dialog_widget!(dialog, ModifySelectionSettings)
+            }
+            DialogData::SnapshotSettings(dialog) => dialog_widget!(dialog, SnapshotSettings),
+            DialogData::ExportSettings(dialog) => dialog_widget!(dialog, ExportSettings),
+            DialogData::UnsavedChanges(dialog) => dialog_widget!(dialog, UnsavedChanges),
+            DialogData::RecoverDocuments(dialog) => dialog_widget!(dialog, RecoverDocuments),
         }
     }
 
+    pub fn layer_properties(layer: &Layer) -> DialogData {
+        DialogData::LayerProperties(Dialog::new(LayerProperties {
+            id: layer.id(),
+            name: layer.name().to_owned(),
+            opacity: Some((layer.opacity() * 100.0).round() as u32),
+        }))
+    }
+
+    pub fn color_range_settings(color: &Color) -> DialogData {
+        DialogData::ColorRangeSettings(Dialog::new(ColorRangeSettings {
+            color: color.clone(),
+            fuzziness: 40.0,
+            sample_merged: false,
+        }))
+    }
+
+    pub fn modify_selection_settings(kind: ModifyKind) -> DialogData {
+        DialogData::ModifySelectionSettings(Dialog::new(ModifySelectionSettings {
+            kind,
+            amount: Some(1),
+        }))
+    }
+
+    /// Settings of the `index`-th snapshot, counting from 1
+    pub fn snapshot_settings(index: usize) -> DialogData {
+        DialogData::SnapshotSettings(Dialog::new(SnapshotSettings {
+            name: format!("Snapshot {}", index),
+        }))
+    }
+
+    pub fn export_settings(path: &std::path::Path, options: ExportOptions) -> DialogData {
+        DialogData::ExportSettings(Dialog::new(ExportSettings {
+            path: Arc::new(path.to_owned()),
+            options,
+        }))
+    }
+
+    pub fn unsaved_changes(name: String, action: DiscardAction) -> DialogData {
+        DialogData::UnsavedChanges(Dialog::new(UnsavedChanges { name, action, save: false }))
+    }
+
+    pub fn recover_documents(documents: Arc<Vec<RecoveredDocument>>) -> DialogData {
+        DialogData::RecoverDocuments(Dialog::new(RecoverDocuments { documents, open: None }))
+    }
+
     pub fn new_file_settings() -> DialogData {
         let mut nfs = NewFileSettings::default();
 
@@ -0,0 +1,27 @@
+//! The last used options of each export format, kept in the config directory as JSON
+use paintr_core::{ExportFormat, ExportOptions};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+fn export_options_path() -> Option<PathBuf> {
+    Some(dirs::config_dir()?.join("paintr/export_options.json"))
+}
+
+pub(crate) fn load() -> HashMap<ExportFormat, ExportOptions> {
+    let content = export_options_path().and_then(|it| std::fs::read(it).ok());
+    let options: Vec<ExportOptions> =
+        content.and_then(|it| serde_json::from_slice(&it).ok()).unwrap_or_default();
+    options.into_iter().map(|it| (it.format, it)).collect()
+}
+
+pub(crate) fn save(options: &HashMap<ExportFormat, ExportOptions>) -> std::io::Result<()> {
+    let path = export_options_path()
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "No config directory"))?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+
+    let mut options: Vec<_> = options.values().collect();
+    options.sort_by_key(|it| ExportFormat::ALL.iter().position(|f| *f == it.format));
+    std::fs::write(path, serde_json::to_vec_pretty(&options)?)
+}
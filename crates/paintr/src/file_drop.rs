@@ -0,0 +1,212 @@
+//! Open files dropped onto a window
+//!
+//! druid has no drag-and-drop event yet, the native windows are registered as drop targets
+//! through the platform instead. Only one document is opened at a time, the first dropped
+//! file is sent as `FILE_OPEN_PATH_ACTION`.
+use crate::commands;
+use druid::{ExtEventSink, Target};
+use std::cell::RefCell;
+use std::path::PathBuf;
+
+thread_local! {
+    /// Drops are handled on the main thread, which registered the windows
+    static SINK: RefCell<Option<ExtEventSink>> = RefCell::new(None);
+}
+
+/// Register the windows of the application as drop targets, windows which are already
+/// registered are skipped
+pub(crate) fn accept_files(sink: &ExtEventSink) {
+    SINK.with(|it| *it.borrow_mut() = Some(sink.clone()));
+    platform::register();
+}
+
+#[cfg_attr(
+    not(any(target_os = "linux", target_os = "windows", target_os = "macos")),
+    allow(unused)
+)]
+fn open_files(files: Vec<PathBuf>) {
+    let file = match files.into_iter().find(|it| it.is_file()) {
+        Some(it) => it,
+        None => return,
+    };
+    SINK.with(|sink| {
+        if let Some(sink) = sink.borrow().as_ref() {
+            let _ = sink.submit_command(commands::FILE_OPEN_PATH_ACTION, file, Target::Auto);
+        }
+    });
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use gtk::prelude::*;
+    use gtk::{ApplicationWindow, DestDefaults, TargetEntry, TargetFlags};
+
+    pub(super) fn register() {
+        let windows = gtk::Window::list_toplevels().into_iter();
+        for window in windows.filter_map(|it| it.downcast::<ApplicationWindow>().ok()) {
+            if window.drag_dest_get_target_list().is_some() {
+                continue;
+            }
+
+            let targets = [TargetEntry::new("text/uri-list", TargetFlags::OTHER_APP, 0)];
+            window.drag_dest_set(DestDefaults::ALL, &targets, gdk::DragAction::COPY);
+            window.connect_drag_data_received(|_, _, _, _, data, _, _| {
+                let uris = data.get_uris();
+                let files = uris.iter().filter_map(|it| glib::filename_from_uri(it).ok());
+                super::open_files(files.map(|(path, _)| path).collect());
+            });
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod platform {
+    use std::ffi::OsString;
+    use std::os::windows::ffi::OsStringExt;
+    use std::path::PathBuf;
+    use winapi::shared::basetsd::{DWORD_PTR, UINT_PTR};
+    use winapi::shared::minwindef::{BOOL, LPARAM, LRESULT, TRUE, UINT, WPARAM};
+    use winapi::shared::windef::HWND;
+    use winapi::um::commctrl::{DefSubclassProc, GetWindowSubclass, SetWindowSubclass};
+    use winapi::um::processthreadsapi::GetCurrentThreadId;
+    use winapi::um::shellapi::{DragAcceptFiles, DragFinish, DragQueryFileW, HDROP};
+    use winapi::um::winuser::{EnumThreadWindows, GetClassNameW, WM_DROPFILES};
+
+    /// The window class registered by druid
+    const CLASS_NAME: &str = "druid";
+    const SUBCLASS_ID: UINT_PTR = 1;
+
+    pub(super) fn register() {
+        unsafe { EnumThreadWindows(GetCurrentThreadId(), Some(register_window), 0) };
+    }
+
+    unsafe extern "system" fn register_window(hwnd: HWND, _: LPARAM) -> BOOL {
+        let mut name = [0u16; 16];
+        let len = GetClassNameW(hwnd, name.as_mut_ptr(), name.len() as i32).max(0) as usize;
+        let mut data = 0;
+        if OsString::from_wide(&name[..len]) == CLASS_NAME
+            && GetWindowSubclass(hwnd, Some(subclass_proc), SUBCLASS_ID, &mut data) == 0
+        {
+            SetWindowSubclass(hwnd, Some(subclass_proc), SUBCLASS_ID, 0);
+            DragAcceptFiles(hwnd, TRUE);
+        }
+        TRUE
+    }
+
+    unsafe extern "system" fn subclass_proc(
+        hwnd: HWND,
+        msg: UINT,
+        wparam: WPARAM,
+        lparam: LPARAM,
+        _: UINT_PTR,
+        _: DWORD_PTR,
+    ) -> LRESULT {
+        if msg != WM_DROPFILES {
+            return DefSubclassProc(hwnd, msg, wparam, lparam);
+        }
+
+        let drop = wparam as HDROP;
+        let count = DragQueryFileW(drop, 0xFFFF_FFFF, std::ptr::null_mut(), 0);
+        let files = (0..count).map(|i| {
+            let len = DragQueryFileW(drop, i, std::ptr::null_mut(), 0) as usize;
+            let mut buf = vec![0u16; len + 1];
+            DragQueryFileW(drop, i, buf.as_mut_ptr(), buf.len() as UINT);
+            PathBuf::from(OsString::from_wide(&buf[..len]))
+        });
+        let files = files.collect();
+        DragFinish(drop);
+        super::open_files(files);
+        0
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use cocoa::appkit::NSApp;
+    use cocoa::base::{id, nil, BOOL, NO, YES};
+    use cocoa::foundation::{NSArray, NSString, NSUInteger};
+    use objc::runtime::{class_addMethod, Class, Imp, Object, Sel};
+    use objc::{class, msg_send, sel, sel_impl};
+    use std::ffi::CStr;
+    use std::path::PathBuf;
+
+    /// The view class of druid, which covers the content of its windows
+    const VIEW_CLASS: &str = "DruidView";
+    const DRAG_OPERATION_COPY: NSUInteger = 1;
+
+    /// Type encodings of the dragging methods, `BOOL` is a `bool` on arm64
+    const DRAGGING_ENTERED_TYPES: &[u8] = b"Q@:@\0";
+    #[cfg(target_arch = "aarch64")]
+    const PERFORM_DRAG_TYPES: &[u8] = b"B@:@\0";
+    #[cfg(not(target_arch = "aarch64"))]
+    const PERFORM_DRAG_TYPES: &[u8] = b"c@:@\0";
+
+    pub(super) fn register() {
+        unsafe {
+            let types =
+                NSArray::arrayWithObject(nil, NSString::alloc(nil).init_str("public.file-url"));
+            let windows: id = msg_send![NSApp(), windows];
+            for i in 0..windows.count() {
+                let content: id = msg_send![windows.objectAtIndex(i), contentView];
+                let views: id = msg_send![content, subviews];
+                for j in 0..views.count() {
+                    let view = views.objectAtIndex(j);
+                    let class = (*view).class();
+                    if class.name() != VIEW_CLASS {
+                        continue;
+                    }
+
+                    // Methods which are already added are kept
+                    let class = class as *const Class as *mut Class;
+                    let entered: extern "C" fn(&Object, Sel, id) -> NSUInteger = dragging_entered;
+                    let entered: Imp = std::mem::transmute(entered);
+                    class_addMethod(
+                        class,
+                        sel!(draggingEntered:),
+                        entered,
+                        DRAGGING_ENTERED_TYPES.as_ptr() as _,
+                    );
+                    let perform: extern "C" fn(&Object, Sel, id) -> BOOL = perform_drag_operation;
+                    let perform: Imp = std::mem::transmute(perform);
+                    class_addMethod(
+                        class,
+                        sel!(performDragOperation:),
+                        perform,
+                        PERFORM_DRAG_TYPES.as_ptr() as _,
+                    );
+                    let () = msg_send![view, registerForDraggedTypes: types];
+                }
+            }
+        }
+    }
+
+    extern "C" fn dragging_entered(_: &Object, _: Sel, _: id) -> NSUInteger {
+        DRAG_OPERATION_COPY
+    }
+
+    extern "C" fn perform_drag_operation(_: &Object, _: Sel, sender: id) -> BOOL {
+        unsafe {
+            let pasteboard: id = msg_send![sender, draggingPasteboard];
+            let classes = NSArray::arrayWithObject(nil, class!(NSURL) as *const Class as id);
+            let urls: id = msg_send![pasteboard, readObjectsForClasses: classes options: nil];
+            if urls == nil {
+                return NO;
+            }
+
+            let files = (0..urls.count()).filter_map(|i| {
+                let path: id = msg_send![urls.objectAtIndex(i), path];
+                if path == nil {
+                    return None;
+                }
+                Some(PathBuf::from(CStr::from_ptr(path.UTF8String()).to_string_lossy().as_ref()))
+            });
+            super::open_files(files.collect());
+            YES
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "windows", target_os = "macos")))]
+mod platform {
+    pub(super) fn register() {}
+}
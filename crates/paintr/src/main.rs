@@ -4,40 +4,82 @@ macro_rules! L {
     };
 }
 
+mod autosave;
 mod commands;
 mod dialogs;
+mod export_options;
+mod file_drop;
 mod menu;
+mod recent;
 mod tools;
 mod ui;
 
 use druid::{
-    theme, AppDelegate, AppLauncher, Application, Color, Command, Data, DelegateCtx, Env, Handled,
-    Lens, LocalizedString, Target, WindowDesc, WindowId,
+    theme, AppDelegate, AppLauncher, Application, Color, Command, Data, DelegateCtx, Env,
+    ExtEventSink, Handled, Lens, LocalizedString, Target, WindowDesc, WindowId,
 };
 use paintr_core::{
-    actions::Paste, get_image_from_clipboard, put_image_to_clipboard, CanvasData, CopyMode,
-    EditKind, UndoHistory,
+    actions::{
+        DeleteLayer, Deselect, DuplicateLayer, Fill, InsertLayer, InvertSelection, MergeLayerDown,
+        ModifySelection, Paste, RenameLayer, ReorderLayer, Select, SelectAll, SetActiveLayer,
+        SetLayerBlendMode, SetLayerLock, SetLayerOpacity, SetLayerVisible,
+    },
+    get_image_from_clipboard, put_image_to_clipboard, BrushSettings, CanvasData, CopyMode,
+    EditKind, ExportFormat, ExportOptions, Layer, LayerId, LayerLock, MagicWandSettings,
+    UndoHistory,
 };
-use paintr_widgets::{theme_ext, widgets, EditorState};
+use paintr_widgets::{theme_ext, widgets, EditorState, Viewport};
 
 use std::{
+    collections::HashMap,
     path::{self, PathBuf},
     sync::Arc,
 };
 
-use dialogs::DialogData;
+use autosave::{Autosave, RecoveredDocument};
+use dialogs::{DialogData, DiscardAction};
 use tools::ToolKind;
 use ui::ui_builder;
 use widgets::notif_bar::Notification;
 
 fn main() {
+    // Only one document is opened at a time, the other files are put into the recent files
+    let files: Vec<PathBuf> = std::env::args_os().skip(1).map(PathBuf::from).collect();
+    let others: Vec<&PathBuf> = files.iter().skip(1).filter(|it| it.is_file()).collect();
+    let mut recent_files = recent::load();
+    for path in others.iter().rev() {
+        recent::push(&mut recent_files, path);
+    }
+    let mut notifications = Vec::new();
+    if !others.is_empty() {
+        notifications.push(Notification::info(format!(
+            "Only one file is opened at a time, the other {} are in Open Recent",
+            others.len()
+        )));
+        if let Err(err) = recent::save(&recent_files) {
+            notifications
+                .push(Notification::warning(format!("Failed to save recent files: {}", err)));
+        }
+    }
+
     let app_state = AppState {
         notifications: Arc::new(Vec::new()),
+        recent_files: Arc::new(recent_files),
+        pending_action: None,
         modal: None,
+        color_target: ColorTarget::Foreground,
+        show_history: true,
+        export_options: Arc::new(export_options::load()),
         editor: EditorState {
             canvas: None,
             history: UndoHistory::new(),
             tool: ToolKind::Select,
+            view: Viewport::default(),
+            brush: BrushSettings::default(),
+            anti_alias: true,
+            wand: MagicWandSettings::default(),
+            foreground: Color::BLACK,
+            background: Color::WHITE,
             is_editing: false,
             cursor: None,
         },
@@ -50,12 +92,22 @@ fn main() {
 
     let user_l10n = find_user_l10n();
 
-    let launcher = AppLauncher::with_window(main_window)
-        .delegate(Delegate::default())
-        .configure_env(|mut env, _| {
-            env.set(theme::WINDOW_BACKGROUND_COLOR, Color::rgb8(0, 0x77, 0x88));
-            theme_ext::init(&mut env);
-        });
+    let launcher = AppLauncher::with_window(main_window);
+    let sink = launcher.get_external_handle();
+    let autosave = match autosave::recovery_dir().map(|dir| Autosave::start(dir, sink.clone())) {
+        Some(Err(err)) => {
+            let msg = Notification::warning(format!("Autosave is disabled: {}", err));
+            let _ = sink.submit_command(commands::SHOW_NOTIFICATION_ACTION, msg, Target::Auto);
+            None
+        }
+        it => it.and_then(Result::ok),
+    };
+
+    let delegate = Delegate { autosave, sink: Some(sink.clone()), ..Delegate::default() };
+    let launcher = launcher.delegate(delegate).configure_env(|mut env, _| {
+        env.set(theme::WINDOW_BACKGROUND_COLOR, Color::rgb8(0, 0x77, 0x88));
+        theme_ext::init(&mut env);
+    });
 
     let launcher = match user_l10n {
         Some(basedir) => launcher.localization_resources(
@@ -65,12 +117,36 @@ fn main() {
         None => launcher,
     };
 
+    let recovered = autosave::recovery_dir().map(|dir| autosave::find_recovered(&dir));
+    if let Some(recovered) = recovered.filter(|it| !it.is_empty()) {
+        let _ = launcher.get_external_handle().submit_command(
+            commands::RECOVER_DOCUMENTS_DIALOG_ACTION,
+            Arc::new(recovered),
+            Target::Auto,
+        );
+    }
+    Autosave::start_timer(launcher.get_external_handle());
+
+    if let Some(path) = files.into_iter().next() {
+        let _ = launcher.get_external_handle().submit_command(
+            commands::FILE_OPEN_PATH_ACTION,
+            path,
+            Target::Auto,
+        );
+    }
+    for notification in notifications {
+        let _ = sink.submit_command(commands::SHOW_NOTIFICATION_ACTION, notification, Target::Auto);
+    }
+
     launcher.launch(app_state).expect("launch failed");
 }
 
-#[derive(Default, Debug)]
+#[derive(Default)]
 struct Delegate {
     windows: Vec<WindowId>,
+    autosave: Option<Autosave>,
+    /// Files dropped onto the windows are opened through it
+    sink: Option<ExtEventSink>,
 }
 
 type Error = Box<dyn std::error::Error>;
@@ -79,9 +155,23 @@ type Error = Box<dyn std::error::Error>;
 struct AppState {
     notifications: Arc<Vec<Notification>>,
     modal: Option<DialogData>,
+    color_target: ColorTarget,
+    show_history: bool,
+    recent_files: Arc<Vec<PathBuf>>,
+    /// The action waiting for the document to be saved with Save As
+    pending_action: Option<DiscardAction>,
+    /// The last used options of each export format
+    export_options: Arc<HashMap<ExportFormat, ExportOptions>>,
     editor: EditorState<ToolKind>,
 }
 
+/// Which color is edited by the color picker
+#[derive(Debug, Clone, Copy, PartialEq, Data)]
+enum ColorTarget {
+    Foreground,
+    Background,
+}
+
 const NEW_FILE_NAME: &str = "Untitled";
 
 fn to_rgba(img: image::DynamicImage) -> image::DynamicImage {
@@ -96,16 +186,21 @@ impl AppState {
         Arc::make_mut(&mut self.notifications).push(n);
     }
 
-    fn do_open_image(&mut self, path: &std::path::Path) -> Result<(), Error> {
-        let img = image::open(path)?;
-        self.editor.canvas = Some(CanvasData::new(path, to_rgba(img)));
-        Ok(())
+    /// Open the image, returns a warning if it was partially imported
+    fn do_open_image(&mut self, path: &std::path::Path) -> Result<Option<String>, Error> {
+        let (canvas, warning) = CanvasData::open(path)?;
+        self.editor.canvas = Some(canvas);
+        self.editor.view = Viewport::default();
+        self.editor.history = UndoHistory::new();
+        Ok(warning)
     }
 
     fn do_new_image_from_clipboard(&mut self) -> Result<(), Error> {
         let img = get_image_from_clipboard()?
             .ok_or_else(|| "Clipboard is empty / non-image".to_string())?;
         self.editor.canvas = Some(CanvasData::new(NEW_FILE_NAME, to_rgba(img)));
+        self.editor.view = Viewport::default();
+        self.editor.history = UndoHistory::new();
         Ok(())
     }
 
@@ -114,19 +209,108 @@ impl AppState {
             info.width.expect("It must be valid after dialog closed."),
             info.height.expect("It must be valid after dialog closed."),
         );
-        // Fill with white color
-        let img = image::ImageBuffer::from_fn(w, h, |_, _| {
-            image::Rgba([0xff_u8, 0xff_u8, 0xff_u8, 0xff_u8])
-        });
+        // Fill with background color
+        let (r, g, b, a) = self.editor.background.as_rgba8();
+        let img = image::ImageBuffer::from_pixel(w, h, image::Rgba([r, g, b, a]));
 
         self.editor.canvas =
             Some(CanvasData::new(NEW_FILE_NAME, image::DynamicImage::ImageRgba8(img)));
+        self.editor.view = Viewport::default();
+        self.editor.history = UndoHistory::new();
+        Ok(())
+    }
+
+    /// Start a new document from the current state of the history
+    fn do_new_image_from_state(&mut self) -> Result<(), Error> {
+        let mut canvas = self.editor.canvas.clone().ok_or_else(|| "No image was found.")?;
+        canvas.set_path(NEW_FILE_NAME);
+        self.editor.canvas = Some(canvas);
+        self.editor.history = UndoHistory::new();
+        self.editor.history.mark_unsaved();
+        self.editor.view = Viewport::default();
         Ok(())
     }
 
     fn do_save_as_image(&mut self, path: &std::path::Path) -> Result<(), Error> {
         let canvas = self.editor.canvas.as_mut().ok_or_else(|| "No image was found.")?;
         canvas.save(path)?;
+        self.editor.mark_saved();
+        Ok(())
+    }
+
+    /// Move `path` to the front of the recent files
+    fn add_recent_file(&mut self, path: &path::Path) {
+        let files = Arc::make_mut(&mut self.recent_files);
+        recent::push(files, path);
+        if let Err(err) = recent::save(files) {
+            self.show_notification(Notification::warning(format!(
+                "Failed to save recent files: {}",
+                err
+            )));
+        }
+    }
+
+    /// Open an autosaved document, which is unsaved until it is saved to its path
+    fn do_recover_document(&mut self, doc: &RecoveredDocument) -> Result<(), Error> {
+        let (mut canvas, _) = CanvasData::open(&doc.file)?;
+        canvas.set_path(doc.path.as_ref());
+        self.editor.canvas = Some(canvas);
+        self.editor.view = Viewport::default();
+        self.editor.history = UndoHistory::new();
+        self.editor.history.mark_unsaved();
+        Ok(())
+    }
+
+    /// Save to the current path with its format, returns false if it cannot be saved there
+    /// without losing layers or the floating selection
+    fn do_save_image(&mut self) -> Result<bool, Error> {
+        let canvas = self.editor.canvas.as_ref().ok_or_else(|| "No image was found.")?;
+        let path = canvas.path().to_owned();
+        if path == path::Path::new(NEW_FILE_NAME) || !canvas.can_save(&path) {
+            if ExportFormat::from_path(&path).is_some() {
+                self.show_notification(Notification::info(format!(
+                    "{} cannot keep the layers or the floating selection, save it as a document",
+                    path.display()
+                )));
+            }
+            return Ok(false);
+        }
+
+        // A flat image is saved with the options it was opened or last saved with
+        let canvas = self.editor.canvas.as_mut().ok_or_else(|| "No image was found.")?;
+        canvas.save(&path)?;
+        self.editor.mark_saved();
+        Ok(true)
+    }
+
+    /// Ask whether to save the changes before `action`, returns false if nothing is unsaved
+    fn confirm_discard(&mut self, action: DiscardAction) -> bool {
+        if !self.editor.is_dirty() {
+            return false;
+        }
+        self.modal = Some(DialogData::unsaved_changes(self.image_file_name(), action));
+        true
+    }
+
+    /// The last used options of `format`
+    fn export_options(&self, format: ExportFormat) -> ExportOptions {
+        self.export_options.get(&format).cloned().unwrap_or_else(|| ExportOptions::new(format))
+    }
+
+    /// Write a flattened copy, the document keeps its path and unsaved changes
+    fn do_export_image(&mut self, settings: &dialogs::ExportSettings) -> Result<(), Error> {
+        let canvas = self.editor.canvas.as_ref().ok_or_else(|| "No image was found.")?;
+        canvas.export(&settings.path, &settings.options)?;
+
+        let options = settings.options.clone();
+        let formats = Arc::make_mut(&mut self.export_options);
+        formats.insert(options.format, options);
+        if let Err(err) = export_options::save(formats) {
+            self.show_notification(Notification::warning(format!(
+                "Failed to save export options: {}",
+                err
+            )));
+        }
         Ok(())
     }
 
@@ -154,6 +338,76 @@ impl AppState {
         Ok(self.editor.do_edit(Paste::new(img), EditKind::NonMergeable))
     }
 
+    fn active_layer(&self) -> Option<(LayerId, usize)> {
+        let canvas = self.editor.canvas.as_ref()?;
+        let id = canvas.active_layer();
+        let index = canvas.layers().iter().position(|it| it.id() == id)?;
+        Some((id, index))
+    }
+
+    fn current_layer(&self) -> Option<&Layer> {
+        let canvas = self.editor.canvas.as_ref()?;
+        canvas.layer(canvas.active_layer())
+    }
+
+    fn do_toggle_layer_lock(&mut self, lock: LayerLock) -> bool {
+        let edit = match self.current_layer() {
+            Some(layer) => SetLayerLock::new(layer.id(), lock, !layer.is_locked(lock)),
+            None => return false,
+        };
+        self.editor.do_edit(edit, EditKind::NonMergeable)
+    }
+
+    fn do_update_layer_properties(&mut self, props: &dialogs::LayerProperties) -> bool {
+        let layer = match self.editor.canvas.as_ref().and_then(|it| it.layer(props.id)) {
+            Some(it) => it,
+            None => return false,
+        };
+        let rename = match layer.name() != props.name {
+            true => Some(RenameLayer::new(props.id, props.name.clone())),
+            false => None,
+        };
+        let opacity = props
+            .opacity
+            .map(|it| it as f64 / 100.0)
+            .filter(|it| (it - layer.opacity()).abs() > f64::EPSILON)
+            .map(|it| SetLayerOpacity::new(props.id, it));
+
+        let mut changed = false;
+        if let Some(edit) = rename {
+            changed |= self.editor.do_edit(edit, EditKind::NonMergeable);
+        }
+        if let Some(edit) = opacity {
+            changed |= self.editor.do_edit(edit, EditKind::NonMergeable);
+        }
+        changed
+    }
+
+    fn do_reorder_layer(&mut self, up: bool) -> bool {
+        let (id, index) = match self.active_layer() {
+            Some(it) => it,
+            None => return false,
+        };
+        let count = self.editor.canvas.as_ref().map_or(0, |it| it.layers().len());
+
+        let index = match up {
+            true if index + 1 < count => index + 1,
+            false if index > 0 => index - 1,
+            _ => return false,
+        };
+        self.editor.do_edit(ReorderLayer::new(id, index), EditKind::NonMergeable)
+    }
+
+    fn do_select_layer(&mut self, above: bool) -> Option<String> {
+        let (_, index) = self.active_layer()?;
+        let index = if above { index + 1 } else { index.checked_sub(1)? };
+
+        let layer = self.editor.canvas.as_ref()?.layers().get(index)?;
+        let (id, name) = (layer.id(), layer.name().to_owned());
+        self.editor.do_edit(SetActiveLayer::new(id), EditKind::NonMergeable);
+        Some(name)
+    }
+
     fn image_file_name(&self) -> String {
         match &self.editor.canvas {
             None => NEW_FILE_NAME.to_owned(),
@@ -162,7 +416,13 @@ impl AppState {
     }
 
     fn status(&self) -> Option<String> {
-        Some(self.editor.canvas.as_ref()?.selection()?.description())
+        let canvas = self.editor.canvas.as_ref()?;
+        let zoom = format!("{:.0}%", self.editor.view.zoom() * 100.0);
+
+        match canvas.selection() {
+            Some(sel) => Some(format!("{}  {}", sel.description(), zoom)),
+            None => Some(zoom),
+        }
     }
 }
 
@@ -175,43 +435,132 @@ impl Delegate {
     ) -> Result<Handled, Error> {
         match cmd {
             _ if cmd.is(commands::FILE_EXIT_ACTION) => {
-                ctx.submit_command(druid::commands::QUIT_APP);
+                if !data.confirm_discard(DiscardAction::Exit) {
+                    self.do_discard_action(data, ctx, DiscardAction::Exit)?;
+                }
+            }
+            _ if cmd.is(druid::commands::CLOSE_WINDOW) => {
+                // Returning `Handled::Yes` keeps the window open
+                if !data.confirm_discard(DiscardAction::Exit) {
+                    return Ok(Handled::No);
+                }
+            }
+            _ if cmd.is(commands::FILE_OPEN_ACTION) => {
+                if !data.confirm_discard(DiscardAction::Open) {
+                    self.do_discard_action(data, ctx, DiscardAction::Open)?;
+                }
             }
             _ if cmd.is(commands::FILE_NEW_ACTION) => {
-                data.modal = Some(DialogData::new_file_settings());
-                self.update_menu(data, ctx);
+                if !data.confirm_discard(DiscardAction::New) {
+                    self.do_discard_action(data, ctx, DiscardAction::New)?;
+                }
             }
             _ if cmd.is(commands::FILE_NEW_CLIPBOARD_ACTION) => {
-                data.do_new_image_from_clipboard()?;
-                data.show_notification(Notification::info("New file created"));
-                self.update_menu(data, ctx);
+                if !data.confirm_discard(DiscardAction::NewFromClipboard) {
+                    self.do_discard_action(data, ctx, DiscardAction::NewFromClipboard)?;
+                }
+            }
+            _ if cmd.is(commands::UNSAVED_CHANGES_ACTION) => {
+                let info = cmd.get_unchecked(commands::UNSAVED_CHANGES_ACTION);
+                if info.save && !data.do_save_image()? {
+                    // No path to save to, the action runs after choosing one
+                    data.pending_action = Some(info.action.clone());
+                    ctx.submit_command(commands::file_save_as_command());
+                    return Ok(Handled::Yes);
+                }
+                self.do_discard_action(data, ctx, info.action.clone())?;
             }
             _ if cmd.is(druid::commands::OPEN_FILE) => {
                 let info = cmd.get_unchecked(druid::commands::OPEN_FILE);
-                data.do_open_image(info.path())?;
-                data.show_notification(Notification::info(format!(
-                    "{} opened",
-                    data.image_file_name()
-                )));
+                self.open_image(data, ctx, info.path())?;
+            }
+            _ if cmd.is(commands::FILE_OPEN_PATH_ACTION) => {
+                let path = cmd.get_unchecked(commands::FILE_OPEN_PATH_ACTION);
+                let action = DiscardAction::OpenPath(Arc::new(path.clone()));
+                if !data.confirm_discard(action.clone()) {
+                    self.do_discard_action(data, ctx, action)?;
+                }
+            }
+            _ if cmd.is(commands::FILE_CLEAR_RECENT_ACTION) => {
+                data.recent_files = Arc::new(Vec::new());
+                recent::save(&data.recent_files)?;
                 self.update_menu(data, ctx);
             }
+            _ if cmd.is(commands::FILE_SAVE_ACTION) => {
+                if data.do_save_image()? {
+                    data.show_notification(Notification::info(format!(
+                        "{} saved",
+                        data.image_file_name()
+                    )));
+                    self.update_autosave(data);
+                } else {
+                    ctx.submit_command(commands::file_save_as_command());
+                }
+            }
             _ if cmd.is(druid::commands::SAVE_FILE_AS) => {
-                let info = cmd.get_unchecked(druid::commands::SAVE_FILE_AS);
-                data.do_save_as_image(info.path())?;
+                let path = cmd.get_unchecked(druid::commands::SAVE_FILE_AS).path();
+                // The action is dropped if the document cannot be saved
+                let action = data.pending_action.take();
+                data.do_save_as_image(path)?;
                 data.show_notification(Notification::info(format!(
                     "{} saved",
                     data.image_file_name()
                 )));
+                data.add_recent_file(path);
+                self.update_autosave(data);
                 self.update_menu(data, ctx);
+                if let Some(action) = action {
+                    self.do_discard_action(data, ctx, action)?;
+                }
+            }
+            _ if cmd.is(druid::commands::SAVE_PANEL_CANCELLED) => {
+                data.pending_action = None;
+            }
+            _ if cmd.is(commands::FILE_EXPORT_AS) => {
+                let path = cmd.get_unchecked(commands::FILE_EXPORT_AS).path();
+                let format =
+                    ExportFormat::from_path(path).ok_or_else(|| "Unknown image format.")?;
+                let options = data.export_options(format);
+                if format.has_options() {
+                    data.modal = Some(DialogData::export_settings(path, options));
+                } else {
+                    let settings =
+                        dialogs::ExportSettings { path: Arc::new(path.to_owned()), options };
+                    data.do_export_image(&settings)?;
+                    data.show_notification(Notification::info(format!(
+                        "{} exported",
+                        path.display()
+                    )));
+                }
+            }
+            _ if cmd.is(commands::EXPORT_IMAGE_ACTION) => {
+                let settings = cmd.get_unchecked(commands::EXPORT_IMAGE_ACTION);
+                data.do_export_image(settings)?;
+                data.show_notification(Notification::info(format!(
+                    "{} exported",
+                    settings.path.display()
+                )));
             }
             _ if cmd.is(commands::EDIT_UNDO_ACTION) => {
                 if let Some(desc) = data.editor.do_undo() {
                     data.show_notification(Notification::info(format!("Undo {}", desc)));
+                    self.update_menu(data, ctx);
                 }
             }
             _ if cmd.is(commands::EDIT_REDO_ACTION) => {
                 if let Some(desc) = data.editor.do_redo() {
                     data.show_notification(Notification::info(format!("Redo {}", desc)));
+                    self.update_menu(data, ctx);
+                }
+            }
+            _ if cmd.is(commands::EDIT_PREVIOUS_BRANCH_ACTION) => {
+                if let Some(desc) = data.editor.do_switch_branch(false) {
+                    data.show_notification(Notification::info(format!("Switch to {}", desc)));
+                }
+            }
+            _ if cmd.is(commands::EDIT_NEXT_BRANCH_ACTION) => {
+                if let Some(desc) = data.editor.do_switch_branch(true) {
+                    data.show_notification(Notification::info(format!("Switch to {}", desc)));
                 }
             }
             _ if cmd.is(commands::EDIT_COPY_ACTION) => {
@@ -224,12 +573,184 @@ impl Delegate {
                     data.show_notification(Notification::info("Pasted"));
                 }
             }
+            _ if cmd.is(commands::EDIT_FILL_FOREGROUND_ACTION) => {
+                let color = data.editor.foreground.clone();
+                data.editor.do_edit(Fill::new(color), EditKind::NonMergeable);
+            }
+            _ if cmd.is(commands::EDIT_FILL_BACKGROUND_ACTION) => {
+                let color = data.editor.background.clone();
+                data.editor.do_edit(Fill::new(color), EditKind::NonMergeable);
+            }
+            _ if cmd.is(commands::EDIT_SELECT_COLOR_RANGE_DIALOG_ACTION) => {
+                if data.editor.canvas.is_some() {
+                    data.modal = Some(DialogData::color_range_settings(&data.editor.foreground));
+                }
+            }
+            _ if cmd.is(commands::SELECT_COLOR_RANGE_ACTION) => {
+                let settings = cmd.get_unchecked(commands::SELECT_COLOR_RANGE_ACTION);
+                let (color, merged) = (&settings.color, settings.sample_merged);
+                let sel = data
+                    .editor
+                    .canvas
+                    .as_ref()
+                    .and_then(|canvas| canvas.color_range(color, settings.fuzziness, merged));
+                if let Some(sel) = sel {
+                    data.editor.do_edit(Select::new(sel), EditKind::NonMergeable);
+                }
+            }
+            _ if cmd.is(commands::SELECT_ALL_ACTION) => {
+                data.editor.do_edit(SelectAll::new(), EditKind::NonMergeable);
+            }
+            _ if cmd.is(commands::SELECT_DESELECT_ACTION) => {
+                data.editor.do_edit(Deselect::new(), EditKind::NonMergeable);
+            }
+            _ if cmd.is(commands::SELECT_INVERT_ACTION) => {
+                data.editor.do_edit(InvertSelection::new(), EditKind::NonMergeable);
+            }
+            _ if cmd.is(commands::SELECT_MODIFY_DIALOG_ACTION) => {
+                let kind = *cmd.get_unchecked(commands::SELECT_MODIFY_DIALOG_ACTION);
+                if data.editor.canvas.as_ref().and_then(|it| it.selection()).is_some() {
+                    data.modal = Some(DialogData::modify_selection_settings(kind));
+                }
+            }
+            _ if cmd.is(commands::SELECT_MODIFY_ACTION) => {
+                let settings = cmd.get_unchecked(commands::SELECT_MODIFY_ACTION);
+                if let Some(modify) = settings.modify() {
+                    data.editor.do_edit(ModifySelection::new(modify), EditKind::NonMergeable);
+                }
+            }
+            _ if cmd.is(commands::EDIT_SWAP_COLORS_ACTION) => {
+                data.editor.swap_colors();
+            }
+            _ if cmd.is(commands::EDIT_RESET_COLORS_ACTION) => {
+                data.editor.reset_colors();
+            }
+            _ if cmd.is(commands::LAYER_NEW_ACTION) => {
+                data.editor.do_edit(InsertLayer::new(), EditKind::NonMergeable);
+            }
+            _ if cmd.is(commands::LAYER_DUPLICATE_ACTION) => {
+                if let Some((id, _)) = data.active_layer() {
+                    data.editor.do_edit(DuplicateLayer::new(id), EditKind::NonMergeable);
+                }
+            }
+            _ if cmd.is(commands::LAYER_DELETE_ACTION) => {
+                if let Some((id, _)) = data.active_layer() {
+                    data.editor.do_edit(DeleteLayer::new(id), EditKind::NonMergeable);
+                }
+            }
+            _ if cmd.is(commands::LAYER_MERGE_DOWN_ACTION) => {
+                if let Some((id, index)) = data.active_layer() {
+                    if index > 0 {
+                        data.editor.do_edit(MergeLayerDown::new(id), EditKind::NonMergeable);
+                    }
+                }
+            }
+            _ if cmd.is(commands::LAYER_RAISE_ACTION) => {
+                data.do_reorder_layer(true);
+            }
+            _ if cmd.is(commands::LAYER_LOWER_ACTION) => {
+                data.do_reorder_layer(false);
+            }
+            _ if cmd.is(commands::LAYER_SELECT_ABOVE_ACTION) => {
+                if let Some(name) = data.do_select_layer(true) {
+                    data.show_notification(Notification::info(format!("{} selected", name)));
+                    self.update_menu(data, ctx);
+                }
+            }
+            _ if cmd.is(commands::LAYER_SELECT_BELOW_ACTION) => {
+                if let Some(name) = data.do_select_layer(false) {
+                    data.show_notification(Notification::info(format!("{} selected", name)));
+                    self.update_menu(data, ctx);
+                }
+            }
+            _ if cmd.is(commands::LAYER_TOGGLE_VISIBLE_ACTION) => {
+                if let Some(layer) = data.current_layer() {
+                    let edit = SetLayerVisible::new(layer.id(), !layer.is_visible());
+                    data.editor.do_edit(edit, EditKind::NonMergeable);
+                    self.update_menu(data, ctx);
+                }
+            }
+            _ if cmd.is(commands::LAYER_TOGGLE_LOCK_PIXELS_ACTION) => {
+                if data.do_toggle_layer_lock(LayerLock::Pixels) {
+                    self.update_menu(data, ctx);
+                }
+            }
+            _ if cmd.is(commands::LAYER_TOGGLE_LOCK_POSITION_ACTION) => {
+                if data.do_toggle_layer_lock(LayerLock::Position) {
+                    self.update_menu(data, ctx);
+                }
+            }
+            _ if cmd.is(commands::LAYER_BLEND_MODE_ACTION) => {
+                let mode = *cmd.get_unchecked(commands::LAYER_BLEND_MODE_ACTION);
+                if let Some((id, _)) = data.active_layer() {
+                    data.editor.do_edit(SetLayerBlendMode::new(id, mode), EditKind::NonMergeable);
+                    self.update_menu(data, ctx);
+                }
+            }
+            _ if cmd.is(commands::LAYER_PROPERTIES_DIALOG_ACTION) => {
+                if let Some(layer) = data.current_layer() {
+                    data.modal = Some(DialogData::layer_properties(layer));
+                }
+            }
+            _ if cmd.is(commands::LAYER_PROPERTIES_ACTION) => {
+                let props = cmd.get_unchecked(commands::LAYER_PROPERTIES_ACTION);
+                data.do_update_layer_properties(props);
+            }
             _ if cmd.is(commands::NEW_IMAGE_ACTION) => {
                 let info = cmd.get_unchecked(commands::NEW_IMAGE_ACTION);
                 data.do_new_image(info)?;
                 data.show_notification(Notification::info("New file created"));
                 self.update_menu(data, ctx);
             }
+            _ if cmd.is(commands::VIEW_TOGGLE_HISTORY_ACTION) => {
+                data.show_history = !data.show_history;
+                self.update_menu(data, ctx);
+            }
+            _ if cmd.is(commands::HISTORY_NEW_SNAPSHOT_DIALOG_ACTION) => {
+                if data.editor.canvas.is_some() {
+                    let index = data.editor.history.snapshots().count() + 1;
+                    data.modal = Some(DialogData::snapshot_settings(index));
+                }
+            }
+            _ if cmd.is(commands::HISTORY_NEW_SNAPSHOT_ACTION) => {
+                let name = cmd.get_unchecked(commands::HISTORY_NEW_SNAPSHOT_ACTION).name.trim();
+                if data.editor.do_take_snapshot(name) {
+                    data.show_notification(Notification::info(format!("{} created", name)));
+                }
+            }
+            _ if cmd.is(commands::HISTORY_NEW_DOCUMENT_ACTION) => {
+                data.do_new_image_from_state()?;
+                data.show_notification(Notification::info("New file created"));
+                self.update_menu(data, ctx);
+            }
+            _ if cmd.is(commands::AUTOSAVE_TICK) => {
+                self.update_autosave(data);
+            }
+            _ if cmd.is(commands::SHOW_NOTIFICATION_ACTION) => {
+                let notification = cmd.get_unchecked(commands::SHOW_NOTIFICATION_ACTION);
+                data.show_notification(notification.clone());
+            }
+            _ if cmd.is(commands::RECOVER_DOCUMENTS_DIALOG_ACTION) => {
+                let documents = cmd.get_unchecked(commands::RECOVER_DOCUMENTS_DIALOG_ACTION);
+                data.modal = Some(DialogData::recover_documents(documents.clone()));
+            }
+            _ if cmd.is(commands::RECOVER_DOCUMENTS_ACTION) => {
+                let info = cmd.get_unchecked(commands::RECOVER_DOCUMENTS_ACTION);
+                match info.open.and_then(|index| info.documents.get(index)) {
+                    Some(doc) => {
+                        data.do_recover_document(doc)?;
+                        // It is written again by this session
+                        doc.discard();
+                        self.update_autosave(data);
+                        data.show_notification(Notification::info(format!(
+                            "{} recovered",
+                            data.image_file_name()
+                        )));
+                        self.update_menu(data, ctx);
+                    }
+                    None => info.documents.iter().for_each(|doc| doc.discard()),
+                }
+            }
             _ if cmd.is(commands::ABOUT_TEST_ACTION) => {
                 data.show_notification(Notification::info("Test"));
             }
@@ -239,6 +760,55 @@ impl Delegate {
         Ok(Handled::Yes)
     }
 
+    /// Run `action` without asking about unsaved changes
+    fn do_discard_action(
+        &mut self,
+        data: &mut AppState,
+        ctx: &mut DelegateCtx,
+        action: DiscardAction,
+    ) -> Result<(), Error> {
+        match action {
+            DiscardAction::Open => ctx.submit_command(commands::file_open_command()),
+            DiscardAction::OpenPath(path) => return self.open_image(data, ctx, &path),
+            DiscardAction::New => data.modal = Some(DialogData::new_file_settings()),
+            DiscardAction::NewFromClipboard => {
+                data.do_new_image_from_clipboard()?;
+                data.show_notification(Notification::info("New file created"));
+            }
+            DiscardAction::Exit => {
+                if let Some(autosave) = &mut self.autosave {
+                    autosave.finish();
+                }
+                ctx.submit_command(druid::commands::QUIT_APP)
+            }
+        }
+        self.update_menu(data, ctx);
+        Ok(())
+    }
+
+    fn open_image(
+        &mut self,
+        data: &mut AppState,
+        ctx: &mut DelegateCtx,
+        path: &path::Path,
+    ) -> Result<(), Error> {
+        let warning = data.do_open_image(path)?;
+        data.show_notification(Notification::info(format!("{} opened", data.image_file_name())));
+        if let Some(warning) = warning {
+            data.show_notification(Notification::warning(warning));
+        }
+        data.add_recent_file(path);
+        self.update_menu(data, ctx);
+        Ok(())
+    }
+
+    /// Autosave the document in the background if it has unsaved changes
+    fn update_autosave(&mut self, data: &AppState) {
+        if let Some(autosave) = &mut self.autosave {
+            autosave.update(data.editor.canvas.as_ref(), data.editor.is_dirty());
+        }
+    }
+
     fn update_menu(&self, data: &AppState, ctx: &mut DelegateCtx) {
         let menu = menu::make_menu(data);
 
@@ -276,6 +846,9 @@ impl AppDelegate<AppState> for Delegate {
         _ctx: &mut DelegateCtx,
     ) {
         self.windows.push(id);
+        if let Some(sink) = &self.sink {
+            file_drop::accept_files(sink);
+        }
     }
 
     fn window_removed(
@@ -288,6 +861,9 @@ impl AppDelegate<AppState> for Delegate {
         if let Some(pos) = self.windows.iter().position(|x| *x == id) {
             self.windows.remove(pos);
         }
+        if let Some(autosave) = &mut self.autosave {
+            autosave.finish();
+        }
 
         // FIXME: Use commands::QUIT_APP
         // It do not works right now, maybe a druid bug
@@ -1,9 +1,18 @@
 use crate::commands;
+use crate::dialogs::ModifyKind;
+use crate::widgets::{Zoom, ZOOM_ACTION};
 use crate::AppState;
-use druid::{KbKey, MenuDesc, MenuItem, RawMods};
+use druid::{Command, KbKey, LocalizedString, MenuDesc, MenuItem, RawMods, Target};
+use paintr_core::{BlendMode, LayerLock};
 
 pub(crate) fn make_menu(app: &AppState) -> MenuDesc<AppState> {
-    MenuDesc::empty().append(file_menu(app)).append(edit_menu(app)).append(about_menu(app))
+    MenuDesc::empty()
+        .append(file_menu(app))
+        .append(edit_menu(app))
+        .append(select_menu(app))
+        .append(layer_menu(app))
+        .append(view_menu(app))
+        .append(about_menu(app))
 }
 
 fn file_menu(app: &AppState) -> MenuDesc<AppState> {
@@ -12,19 +21,157 @@ fn file_menu(app: &AppState) -> MenuDesc<AppState> {
         .append(new_from_clipboard())
         .append_separator()
         .append(open())
+        .append(open_recent_menu(app))
         .append_separator()
         .append(save().disabled_if(|| app.editor.canvas.is_none()))
+        .append(save_as().disabled_if(|| app.editor.canvas.is_none()))
+        .append(export_as().disabled_if(|| app.editor.canvas.is_none()))
         .append_separator()
         .append(exit())
 }
 
-fn edit_menu(_app: &AppState) -> MenuDesc<AppState> {
+fn open_recent_menu(app: &AppState) -> MenuDesc<AppState> {
+    let menu =
+        app.recent_files.iter().fold(MenuDesc::new(L!("menu-file-open-recent")), |menu, path| {
+            let cmd = Command::new(commands::FILE_OPEN_PATH_ACTION, path.clone(), Target::Auto);
+            let name = path.to_string_lossy().to_string();
+            menu.append(MenuItem::new(
+                L!("menu-file-recent-item").with_arg("path", move |_, _| name.clone().into()),
+                cmd,
+            ))
+        });
+
+    menu.append_separator().append(clear_recent().disabled_if(|| app.recent_files.is_empty()))
+}
+
+fn edit_menu(app: &AppState) -> MenuDesc<AppState> {
+    let no_canvas = app.editor.canvas.is_none();
+
     MenuDesc::new(L!("menu-edit-menu"))
         .append(undo())
         .append(redo())
+        .append(previous_branch().disabled_if(|| no_canvas))
+        .append(next_branch().disabled_if(|| no_canvas))
         .append_separator()
         .append(copy())
         .append(paste())
+        .append_separator()
+        .append(fill_foreground().disabled_if(|| no_canvas))
+        .append(fill_background().disabled_if(|| no_canvas))
+        .append_separator()
+        .append(select_color_range().disabled_if(|| no_canvas))
+        .append_separator()
+        .append(swap_colors())
+        .append(reset_colors())
+}
+
+fn select_menu(app: &AppState) -> MenuDesc<AppState> {
+    let no_canvas = app.editor.canvas.is_none();
+
+    MenuDesc::new(L!("menu-select-menu"))
+        .append(select_all().disabled_if(|| no_canvas))
+        .append(deselect().disabled_if(|| no_canvas))
+        .append(invert_selection().disabled_if(|| no_canvas))
+        .append_separator()
+        .append(modify_selection(ModifyKind::Grow).disabled_if(|| no_canvas))
+        .append(modify_selection(ModifyKind::Shrink).disabled_if(|| no_canvas))
+        .append(modify_selection(ModifyKind::Border).disabled_if(|| no_canvas))
+        .append(modify_selection(ModifyKind::Smooth).disabled_if(|| no_canvas))
+        .append(modify_selection(ModifyKind::Feather).disabled_if(|| no_canvas))
+}
+
+fn modify_selection(kind: ModifyKind) -> MenuItem<AppState> {
+    let title = match kind {
+        ModifyKind::Grow => L!("menu-select-grow"),
+        ModifyKind::Shrink => L!("menu-select-shrink"),
+        ModifyKind::Border => L!("menu-select-border"),
+        ModifyKind::Smooth => L!("menu-select-smooth"),
+        ModifyKind::Feather => L!("menu-select-feather"),
+    };
+    let item = MenuItem::new(
+        title,
+        Command::new(commands::SELECT_MODIFY_DIALOG_ACTION, kind, Target::Auto),
+    );
+
+    match kind {
+        ModifyKind::Feather => item.hotkey(RawMods::Shift, KbKey::F6),
+        _ => item,
+    }
+}
+
+fn layer_menu(app: &AppState) -> MenuDesc<AppState> {
+    let no_canvas = app.editor.canvas.is_none();
+    let layer = app.current_layer();
+    let hidden = layer.map_or(false, |it| !it.is_visible());
+    let lock_pixels = layer.map_or(false, |it| it.is_locked(LayerLock::Pixels));
+    let lock_position = layer.map_or(false, |it| it.is_locked(LayerLock::Position));
+
+    MenuDesc::new(L!("menu-layer-menu"))
+        .append(new_layer().disabled_if(|| no_canvas))
+        .append(duplicate_layer().disabled_if(|| no_canvas))
+        .append(delete_layer().disabled_if(|| no_canvas))
+        .append_separator()
+        .append(merge_layer_down().disabled_if(|| no_canvas))
+        .append_separator()
+        .append(raise_layer().disabled_if(|| no_canvas))
+        .append(lower_layer().disabled_if(|| no_canvas))
+        .append_separator()
+        .append(select_layer_above().disabled_if(|| no_canvas))
+        .append(select_layer_below().disabled_if(|| no_canvas))
+        .append_separator()
+        .append(hide_layer().disabled_if(|| no_canvas).selected_if(|| hidden))
+        .append(lock_layer_pixels().disabled_if(|| no_canvas).selected_if(|| lock_pixels))
+        .append(lock_layer_position().disabled_if(|| no_canvas).selected_if(|| lock_position))
+        .append_separator()
+        .append(blend_mode_menu(layer.map(|it| it.blend_mode())))
+        .append(layer_properties().disabled_if(|| no_canvas))
+}
+
+fn blend_mode_menu(current: Option<BlendMode>) -> MenuDesc<AppState> {
+    BlendMode::ALL.iter().fold(MenuDesc::new(L!("menu-layer-blend-mode")), |menu, mode| {
+        let mode = *mode;
+        let cmd = Command::new(commands::LAYER_BLEND_MODE_ACTION, mode, Target::Auto);
+        menu.append(
+            MenuItem::new(blend_mode_title(mode), cmd)
+                .disabled_if(|| current.is_none())
+                .selected_if(|| current == Some(mode)),
+        )
+    })
+}
+
+fn blend_mode_title(mode: BlendMode) -> LocalizedString<AppState> {
+    match mode {
+        BlendMode::Normal => L!("menu-blend-normal"),
+        BlendMode::Multiply => L!("menu-blend-multiply"),
+        BlendMode::Screen => L!("menu-blend-screen"),
+        BlendMode::Overlay => L!("menu-blend-overlay"),
+        BlendMode::Darken => L!("menu-blend-darken"),
+        BlendMode::Lighten => L!("menu-blend-lighten"),
+        BlendMode::ColorDodge => L!("menu-blend-color-dodge"),
+        BlendMode::ColorBurn => L!("menu-blend-color-burn"),
+        BlendMode::HardLight => L!("menu-blend-hard-light"),
+        BlendMode::SoftLight => L!("menu-blend-soft-light"),
+        BlendMode::Difference => L!("menu-blend-difference"),
+        BlendMode::Exclusion => L!("menu-blend-exclusion"),
+        BlendMode::Hue => L!("menu-blend-hue"),
+        BlendMode::Saturation => L!("menu-blend-saturation"),
+        BlendMode::Color => L!("menu-blend-color"),
+        BlendMode::Luminosity => L!("menu-blend-luminosity"),
+    }
+}
+
+fn view_menu(app: &AppState) -> MenuDesc<AppState> {
+    let no_canvas = app.editor.canvas.is_none();
+
+    MenuDesc::new(L!("menu-view-menu"))
+        .append(zoom_in().disabled_if(|| no_canvas))
+        .append(zoom_out().disabled_if(|| no_canvas))
+        .append_separator()
+        .append(zoom_fit().disabled_if(|| no_canvas))
+        .append(zoom_actual().disabled_if(|| no_canvas))
+        .append(zoom_selection().disabled_if(|| no_canvas))
+        .append_separator()
+        .append(toggle_history().selected_if(|| app.show_history))
 }
 
 fn about_menu(_app: &AppState) -> MenuDesc<AppState> {
@@ -43,15 +190,49 @@ macro_rules! register_menu_items {
 
 register_menu_items! {
     // files
-    open => ("menu-file-open", commands::file_open_command(), Ctrl, KbKey::Character("O".to_string())),
+    open => ("menu-file-open", commands::FILE_OPEN_ACTION, Ctrl, KbKey::Character("O".to_string())),
     new => ("menu-file-new", commands::FILE_NEW_ACTION, Ctrl, KbKey::Character("N".to_string())),
     new_from_clipboard => ("menu-file-new-clipboard", commands::FILE_NEW_CLIPBOARD_ACTION),
-    save => ("menu-file-save-as", commands::file_save_as_command(), CtrlShift, KbKey::Character("S".to_string())),
+    clear_recent => ("menu-file-clear-recent", commands::FILE_CLEAR_RECENT_ACTION),
+    save => ("menu-file-save", commands::FILE_SAVE_ACTION, Ctrl, KbKey::Character("S".to_string())),
+    save_as => ("menu-file-save-as", commands::file_save_as_command(), CtrlShift, KbKey::Character("S".to_string())),
+    export_as => ("menu-file-export-as", commands::file_export_as_command(), CtrlShift, KbKey::Character("E".to_string())),
     // edit
     undo => ("menu-edit-undo", commands::EDIT_UNDO_ACTION, Ctrl, KbKey::Character("Z".to_string())),
     redo => ("menu-edit-redo", commands::EDIT_REDO_ACTION, CtrlShift, KbKey::Character("Z".to_string())),
+    previous_branch => ("menu-edit-previous-branch", commands::EDIT_PREVIOUS_BRANCH_ACTION),
+    next_branch => ("menu-edit-next-branch", commands::EDIT_NEXT_BRANCH_ACTION),
     copy => ("menu-edit-copy", commands::EDIT_COPY_ACTION, Ctrl, KbKey::Character("C".to_string())),
     paste => ("menu-edit-paste", commands::EDIT_PASTE_ACTION, Ctrl, KbKey::Character("V".to_string())),
+    fill_foreground => ("menu-edit-fill-foreground", commands::EDIT_FILL_FOREGROUND_ACTION, Alt, KbKey::Backspace),
+    fill_background => ("menu-edit-fill-background", commands::EDIT_FILL_BACKGROUND_ACTION, Ctrl, KbKey::Backspace),
+    select_color_range => ("menu-edit-select-color-range", commands::EDIT_SELECT_COLOR_RANGE_DIALOG_ACTION),
+    swap_colors => ("menu-edit-swap-colors", commands::EDIT_SWAP_COLORS_ACTION),
+    reset_colors => ("menu-edit-reset-colors", commands::EDIT_RESET_COLORS_ACTION),
+    // select
+    select_all => ("menu-select-all", commands::SELECT_ALL_ACTION, Ctrl, KbKey::Character("A".to_string())),
+    deselect => ("menu-select-deselect", commands::SELECT_DESELECT_ACTION, Ctrl, KbKey::Character("D".to_string())),
+    invert_selection => ("menu-select-invert", commands::SELECT_INVERT_ACTION, CtrlShift, KbKey::Character("I".to_string())),
+    // layer
+    new_layer => ("menu-layer-new", commands::LAYER_NEW_ACTION, CtrlShift, KbKey::Character("N".to_string())),
+    duplicate_layer => ("menu-layer-duplicate", commands::LAYER_DUPLICATE_ACTION, Ctrl, KbKey::Character("J".to_string())),
+    delete_layer => ("menu-layer-delete", commands::LAYER_DELETE_ACTION),
+    merge_layer_down => ("menu-layer-merge-down", commands::LAYER_MERGE_DOWN_ACTION, Ctrl, KbKey::Character("E".to_string())),
+    raise_layer => ("menu-layer-raise", commands::LAYER_RAISE_ACTION, Ctrl, KbKey::Character("]".to_string())),
+    lower_layer => ("menu-layer-lower", commands::LAYER_LOWER_ACTION, Ctrl, KbKey::Character("[".to_string())),
+    select_layer_above => ("menu-layer-select-above", commands::LAYER_SELECT_ABOVE_ACTION, Alt, KbKey::Character("]".to_string())),
+    select_layer_below => ("menu-layer-select-below", commands::LAYER_SELECT_BELOW_ACTION, Alt, KbKey::Character("[".to_string())),
+    hide_layer => ("menu-layer-hide", commands::LAYER_TOGGLE_VISIBLE_ACTION),
+    lock_layer_pixels => ("menu-layer-lock-pixels", commands::LAYER_TOGGLE_LOCK_PIXELS_ACTION),
+    lock_layer_position => ("menu-layer-lock-position", commands::LAYER_TOGGLE_LOCK_POSITION_ACTION),
+    layer_properties => ("menu-layer-properties", commands::LAYER_PROPERTIES_DIALOG_ACTION),
+    // view
+    zoom_in => ("menu-view-zoom-in", Command::new(ZOOM_ACTION, Zoom::In, Target::Auto), Ctrl, KbKey::Character("=".to_string())),
+    zoom_out => ("menu-view-zoom-out", Command::new(ZOOM_ACTION, Zoom::Out, Target::Auto), Ctrl, KbKey::Character("-".to_string())),
+    zoom_fit => ("menu-view-zoom-fit", Command::new(ZOOM_ACTION, Zoom::FitToWindow, Target::Auto), Ctrl, KbKey::Character("0".to_string())),
+    zoom_actual => ("menu-view-zoom-actual", Command::new(ZOOM_ACTION, Zoom::ActualSize, Target::Auto), Ctrl, KbKey::Character("1".to_string())),
+    zoom_selection => ("menu-view-zoom-selection", Command::new(ZOOM_ACTION, Zoom::Selection, Target::Auto)),
+    toggle_history => ("menu-view-history", commands::VIEW_TOGGLE_HISTORY_ACTION, None, KbKey::F9),
 
     // about
     about => ("menu-about-test", commands::ABOUT_TEST_ACTION)
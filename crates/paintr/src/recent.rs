@@ -0,0 +1,38 @@
+//! Recently opened files, kept in the config directory one path per line
+use std::path::{Path, PathBuf};
+
+const MAX_RECENT_FILES: usize = 10;
+
+fn recent_files_path() -> Option<PathBuf> {
+    Some(dirs::config_dir()?.join("paintr/recent_files.txt"))
+}
+
+pub(crate) fn load() -> Vec<PathBuf> {
+    let content = recent_files_path().and_then(|it| std::fs::read_to_string(it).ok());
+    content
+        .unwrap_or_default()
+        .lines()
+        .filter(|it| !it.trim().is_empty())
+        .take(MAX_RECENT_FILES)
+        .map(PathBuf::from)
+        .collect()
+}
+
+pub(crate) fn save(files: &[PathBuf]) -> std::io::Result<()> {
+    let path = recent_files_path()
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "No config directory"))?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+
+    let content: Vec<_> = files.iter().map(|it| it.to_string_lossy()).collect();
+    std::fs::write(path, content.join("\n"))
+}
+
+/// Move `path` to the front of `files`
+pub(crate) fn push(files: &mut Vec<PathBuf>, path: &Path) {
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_owned());
+    files.retain(|it| *it != path);
+    files.insert(0, path);
+    files.truncate(MAX_RECENT_FILES);
+}
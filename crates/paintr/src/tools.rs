@@ -1,4 +1,6 @@
 mod brush_tool;
+mod lasso_tool;
+mod magic_wand_tool;
 mod move_tool;
 mod select_tool;
 
@@ -6,9 +8,13 @@ use crate::widgets::Tool;
 use crate::widgets::ToolCtx;
 use crate::EditorState;
 use brush_tool::BrushTool;
-use druid::{Data, Event, EventCtx};
+use druid::{Data, Event, EventCtx, Modifiers};
+use lasso_tool::{LassoTool, PolygonalLassoTool};
+use magic_wand_tool::MagicWandTool;
 use move_tool::MoveTool;
-use select_tool::SelectTool;
+use paintr_core::actions::Select;
+use paintr_core::{CanvasData, EditKind, Selection, SelectionMode};
+use select_tool::{EllipseSelectTool, SelectTool};
 
 macro_rules! register_tool {
     ($($e:ident => $tool:path),* $(,)?) => {
@@ -36,5 +42,43 @@ macro_rules! register_tool {
 register_tool! {
     Move => MoveTool,
     Select => SelectTool,
+    EllipseSelect => EllipseSelectTool,
+    Lasso => LassoTool,
+    PolygonalLasso => PolygonalLassoTool,
+    MagicWand => MagicWandTool,
     Brush => BrushTool,
 }
+
+/// Combines the selection made by a tool with the one before the tool started
+#[derive(Debug, Clone, Data)]
+pub(crate) struct SelectionCombiner {
+    base: Option<Selection>,
+    mode: SelectionMode,
+}
+
+impl SelectionCombiner {
+    /// Shift to add, Alt to subtract and both to intersect
+    pub(crate) fn new(canvas: &CanvasData, mods: &Modifiers) -> SelectionCombiner {
+        let mode = match (mods.shift(), mods.alt()) {
+            (true, true) => SelectionMode::Intersect,
+            (true, false) => SelectionMode::Add,
+            (false, true) => SelectionMode::Subtract,
+            (false, false) => SelectionMode::Replace,
+        };
+        SelectionCombiner { base: canvas.selection().cloned(), mode }
+    }
+
+    pub(crate) fn mode(&self) -> SelectionMode {
+        self.mode
+    }
+
+    pub(crate) fn select(
+        &self,
+        editor: &mut EditorState<ToolKind>,
+        sel: impl Into<Selection>,
+        kind: EditKind,
+    ) -> bool {
+        let sel = self.mode.combine(self.base.as_ref(), sel.into());
+        editor.do_edit(Select::new(sel), kind)
+    }
+}
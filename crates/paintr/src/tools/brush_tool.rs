@@ -1,7 +1,7 @@
-use std::{any::Any, sync::Arc};
+use std::any::Any;
 
-use druid::{Cursor, Data, Event, EventCtx, MouseButton, Point};
-use paintr_core::{actions::DrawBrush, EditKind};
+use druid::{Color, Cursor, Data, Event, EventCtx, MouseButton, Point};
+use paintr_core::{actions::DrawBrush, BrushSettings, EditKind};
 
 use crate::tools::ToolKind;
 use crate::widgets::{Tool, ToolCtx};
@@ -14,12 +14,17 @@ pub(crate) struct BrushTool;
 pub(crate) struct BrushToolCtx {
     origin: Point,
     cursor: Option<Cursor>,
-    drawn: Arc<Vec<Point>>,
+    brush: BrushSettings,
+    color: Color,
 }
 
 impl BrushToolCtx {
     fn from_point<T>(editor: &mut EditorState<T>, pt: Point) -> Option<Self> {
         let canvas = editor.canvas.as_mut()?;
+        if !canvas.layer(canvas.active_layer())?.is_editable() {
+            return None;
+        }
+
         let mut origin = canvas.position();
 
         if let Some(sel) = canvas.selection() {
@@ -28,7 +33,12 @@ impl BrushToolCtx {
             }
         }
 
-        Some(Self { origin, cursor: None, drawn: Arc::new(vec![]) })
+        // Keep the settings for the whole stroke
+        let (brush, color) = (editor.brush, editor.foreground.clone());
+        editor
+            .do_edit(DrawBrush::new(vec![pt.to_vec2()], brush, color.clone()), EditKind::Mergeable);
+
+        Some(Self { origin, cursor: None, brush, color })
     }
 
     fn draw<T>(&mut self, editor: &mut EditorState<T>, pt: Point, kind: EditKind) -> Option<()> {
@@ -37,7 +47,7 @@ impl BrushToolCtx {
         if editor.canvas.is_none() {
             return None;
         }
-        editor.do_edit(DrawBrush::new(vec![target]), kind);
+        editor.do_edit(DrawBrush::continued(vec![target], self.brush, self.color.clone()), kind);
 
         Some(())
     }
@@ -64,6 +74,9 @@ impl Tool for BrushTool {
                 if me.button == MouseButton::Left {
                     ctx.set_active(true);
                     *tool_ctx = BrushToolCtx::from_point(data, me.pos);
+                    if tool_ctx.is_some() {
+                        ctx.request_paint();
+                    }
                 }
             }
             Event::MouseMove(me) => {
@@ -0,0 +1,152 @@
+use std::any::Any;
+
+use druid::kurbo::BezPath;
+use druid::{Cursor, Event, EventCtx, KbKey, Modifiers, MouseButton, Point};
+use paintr_core::{CanvasData, EditKind};
+
+use crate::tools::{SelectionCombiner, ToolKind};
+use crate::widgets::{Tool, ToolCtx};
+use crate::EditorState;
+
+#[derive(Debug)]
+pub(crate) struct LassoTool;
+
+#[derive(Debug)]
+pub(crate) struct PolygonalLassoTool;
+
+#[derive(Debug, Clone)]
+pub(crate) struct LassoToolCtx {
+    points: Vec<Point>,
+    combiner: SelectionCombiner,
+}
+
+impl LassoToolCtx {
+    fn new(canvas: &Option<CanvasData>, pt: Point, mods: &Modifiers) -> Option<LassoToolCtx> {
+        let combiner = SelectionCombiner::new(canvas.as_ref()?, mods);
+        Some(LassoToolCtx { points: vec![pt], combiner })
+    }
+
+    /// Select the closed path of all points, with `cursor` as the last point if any
+    fn select(
+        &self,
+        editor: &mut EditorState<ToolKind>,
+        cursor: Option<Point>,
+        kind: EditKind,
+    ) -> bool {
+        let mut path = BezPath::new();
+        let mut points = self.points.iter().chain(cursor.as_ref());
+        if let Some(first) = points.next() {
+            path.move_to(*first);
+        }
+        for pt in points {
+            path.line_to(*pt);
+        }
+        path.close_path();
+
+        self.combiner.select(editor, path, kind)
+    }
+}
+
+fn set_cursor(ctx: &mut EventCtx, data: &mut EditorState<ToolKind>) {
+    if data.cursor != Some(Cursor::Crosshair) {
+        data.cursor = Some(Cursor::Crosshair);
+        ctx.set_cursor(&Cursor::Crosshair);
+    }
+}
+
+impl Tool for LassoTool {
+    type Context = LassoToolCtx;
+    type Kind = ToolKind;
+
+    fn event(
+        &self,
+        ctx: &mut EventCtx,
+        event: &Event,
+        data: &mut EditorState<ToolKind>,
+        tool_ctx: &mut Option<LassoToolCtx>,
+    ) {
+        set_cursor(ctx, data);
+
+        match event {
+            Event::MouseDown(me) => {
+                if me.button == MouseButton::Left {
+                    ctx.set_active(true);
+                    *tool_ctx = LassoToolCtx::new(&data.canvas, me.pos, &me.mods);
+                }
+            }
+            Event::MouseMove(me) => {
+                if let Some(tool_ctx) = tool_ctx.as_mut() {
+                    tool_ctx.points.push(me.pos);
+                    if tool_ctx.select(data, None, EditKind::Mergeable) {
+                        ctx.request_paint();
+                    }
+                }
+            }
+            Event::MouseUp(me) => {
+                if me.button == MouseButton::Left {
+                    if let Some(tool_ctx) = tool_ctx.take() {
+                        if tool_ctx.select(data, Some(me.pos), EditKind::NonMergeable) {
+                            ctx.request_paint();
+                        }
+                    }
+                    ctx.set_active(false);
+                }
+            }
+            _ => (),
+        };
+    }
+}
+
+impl Tool for PolygonalLassoTool {
+    type Context = LassoToolCtx;
+    type Kind = ToolKind;
+
+    fn event(
+        &self,
+        ctx: &mut EventCtx,
+        event: &Event,
+        data: &mut EditorState<ToolKind>,
+        tool_ctx: &mut Option<LassoToolCtx>,
+    ) {
+        set_cursor(ctx, data);
+
+        match event {
+            Event::MouseDown(me) if me.button == MouseButton::Left => match tool_ctx.as_mut() {
+                None => *tool_ctx = LassoToolCtx::new(&data.canvas, me.pos, &me.mods),
+                // Close the path on double click
+                Some(_) if me.count > 1 => {
+                    if let Some(tool_ctx) = tool_ctx.take() {
+                        tool_ctx.select(data, None, EditKind::NonMergeable);
+                        ctx.request_paint();
+                    }
+                }
+                Some(it) => it.points.push(me.pos),
+            },
+            Event::MouseMove(me) => {
+                if let Some(tool_ctx) = tool_ctx.as_ref() {
+                    if tool_ctx.select(data, Some(me.pos), EditKind::Mergeable) {
+                        ctx.request_paint();
+                    }
+                }
+            }
+            Event::KeyDown(key) if key.key == KbKey::Enter => {
+                if let Some(tool_ctx) = tool_ctx.take() {
+                    tool_ctx.select(data, None, EditKind::NonMergeable);
+                    ctx.request_paint();
+                }
+            }
+            Event::KeyDown(key) if key.key == KbKey::Escape => {
+                if tool_ctx.take().is_some() && data.do_cancel().is_some() {
+                    ctx.request_paint();
+                }
+            }
+            _ => (),
+        };
+    }
+}
+
+impl ToolCtx for LassoToolCtx {
+    fn into_any(self: Box<Self>) -> Box<dyn Any> {
+        self
+    }
+}
@@ -0,0 +1,52 @@
+use std::any::Any;
+
+use druid::{Cursor, Event, EventCtx, MouseButton};
+use paintr_core::EditKind;
+
+use crate::tools::{SelectionCombiner, ToolKind};
+use crate::widgets::{Tool, ToolCtx};
+use crate::EditorState;
+
+#[derive(Debug)]
+pub(crate) struct MagicWandTool;
+
+#[derive(Debug, Clone)]
+pub(crate) struct MagicWandToolCtx;
+
+impl Tool for MagicWandTool {
+    type Context = MagicWandToolCtx;
+    type Kind = ToolKind;
+
+    fn event(
+        &self,
+        ctx: &mut EventCtx,
+        event: &Event,
+        data: &mut EditorState<ToolKind>,
+        _tool_ctx: &mut Option<MagicWandToolCtx>,
+    ) {
+        if data.cursor != Some(Cursor::Crosshair) {
+            data.cursor = Some(Cursor::Crosshair);
+            ctx.set_cursor(&Cursor::Crosshair);
+        }
+
+        if let Event::MouseDown(me) = event {
+            if me.button == MouseButton::Left {
+                let wand = data.canvas.as_ref().and_then(|canvas| {
+                    let sel = canvas.magic_wand(me.pos, &data.wand)?;
+                    Some((SelectionCombiner::new(canvas, &me.mods), sel))
+                });
+                if let Some((combiner, sel)) = wand {
+                    if combiner.select(data, sel, EditKind::NonMergeable) {
+                        ctx.request_paint();
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl ToolCtx for MagicWandToolCtx {
+    fn into_any(self: Box<Self>) -> Box<dyn Any> {
+        self
+    }
+}
@@ -2,8 +2,8 @@ use std::any::Any;
 
 use druid::{Cursor, Data, Event, EventCtx, MouseButton, Point};
 use paintr_core::{
-    actions::{MoveCanvas, MoveSelection},
-    EditKind,
+    actions::{MoveLayer, MoveSelection},
+    EditKind, LayerId, LayerLock,
 };
 
 use crate::tools::ToolKind;
@@ -16,7 +16,7 @@ pub(crate) struct MoveTool;
 #[derive(Debug, Clone, Data, Eq, PartialEq)]
 enum MoveKind {
     Selection,
-    WholeCanvas,
+    Layer(LayerId),
 }
 
 #[derive(Debug, Clone, Data)]
@@ -31,8 +31,9 @@ pub(crate) struct MoveToolCtx {
 impl MoveToolCtx {
     fn from_point<T>(editor: &mut EditorState<T>, pt: Point) -> Option<Self> {
         let canvas = editor.canvas.as_mut()?;
-        let mut origin = canvas.position();
-        let mut kind = MoveKind::WholeCanvas;
+        let layer = canvas.active_layer();
+        let mut origin = canvas.layer(layer)?.position();
+        let mut kind = MoveKind::Layer(layer);
 
         if let Some(sel) = canvas.selection() {
             if sel.contains(pt) {
@@ -41,6 +42,18 @@ impl MoveToolCtx {
             }
         }
 
+        let movable = match kind {
+            MoveKind::Selection => match canvas.selection_layer() {
+                Some(id) => !canvas.layer(id)?.is_locked(LayerLock::Position),
+                // The selection will be cut out from the active layer
+                None => canvas.layer(layer)?.is_editable(),
+            },
+            MoveKind::Layer(id) => !canvas.layer(id)?.is_locked(LayerLock::Position),
+        };
+        if !movable {
+            return None;
+        }
+
         Some(Self { kind, down: pt, origin, curr: origin, cursor: None })
     }
 
@@ -56,12 +69,12 @@ impl MoveToolCtx {
                 self.curr = editor.canvas.as_ref()?.selection()?.position();
                 assert_eq!(self.curr, target.to_point());
             }
-            MoveKind::WholeCanvas => {
+            MoveKind::Layer(id) => {
                 if editor.canvas.is_none() {
                     return None;
                 }
-                editor.do_edit(MoveCanvas::new(target - self.curr.to_vec2()), kind);
-                self.curr = editor.canvas.as_ref()?.position();
+                editor.do_edit(MoveLayer::new(id, target - self.curr.to_vec2()), kind);
+                self.curr = editor.canvas.as_ref()?.layer(id)?.position();
                 assert_eq!(self.curr, target.to_point());
             }
         }
@@ -1,46 +1,159 @@
 use std::any::Any;
 
-use crate::tools::ToolKind;
+use crate::tools::{SelectionCombiner, ToolKind};
 use crate::widgets::{Tool, ToolCtx};
 use crate::EditorState;
 
-use druid::{Cursor, Data, Event, EventCtx, MouseButton, Point, Rect};
-use paintr_core::{CanvasData, Selection};
+use druid::{Cursor, Data, Event, EventCtx, Modifiers, MouseButton, Point, Rect, Vec2};
+use paintr_core::{
+    actions::MoveSelectionOutline, CanvasData, EditKind, Ellipse, Selection, SelectionMode,
+};
 
 #[derive(Debug)]
 pub(crate) struct SelectTool;
 
+#[derive(Debug)]
+pub(crate) struct EllipseSelectTool;
+
+#[derive(Debug, Clone, Copy, Data)]
+pub(crate) enum Marquee {
+    Rect,
+    Ellipse { anti_alias: bool },
+}
+
+/// The modifiers held at mouse-down, which choose how the selection is combined
+///
+/// They do not constrain the shape until they are released and pressed again.
+#[derive(Debug, Clone, Copy, Data)]
+pub(crate) struct LatchedMods {
+    shift: bool,
+    alt: bool,
+}
+
+impl LatchedMods {
+    fn new(mods: &Modifiers) -> LatchedMods {
+        LatchedMods { shift: mods.shift(), alt: mods.alt() }
+    }
+
+    /// Release the modifiers which are no longer held, and return the ones free to use
+    fn update(&mut self, mods: &Modifiers) -> (bool, bool) {
+        self.shift &= mods.shift();
+        self.alt &= mods.alt();
+        (mods.shift() && !self.shift, mods.alt() && !self.alt)
+    }
+}
+
+impl Marquee {
+    /// Shift to draw a square or a circle, Alt to draw from the center
+    ///
+    /// The modifiers which were latched to combine the selection are ignored.
+    fn selection(
+        self,
+        down: Point,
+        pt: Point,
+        latched: &mut LatchedMods,
+        mods: &Modifiers,
+    ) -> Selection {
+        let (shift, alt) = latched.update(mods);
+        let mut delta = pt - down;
+        if shift {
+            let side = delta.x.abs().max(delta.y.abs());
+            delta = Vec2::new(side.copysign(delta.x), side.copysign(delta.y));
+        }
+
+        let rect = match alt {
+            true => Rect::from_points(down - delta, down + delta),
+            false => Rect::from_points(down, down + delta),
+        };
+        match self {
+            Marquee::Rect => rect.into(),
+            Marquee::Ellipse { anti_alias } => Ellipse::new(rect, anti_alias).into(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Data)]
 pub(crate) enum SelectToolCtx {
-    New { down: Point },
-    Move { down: Point, old: Selection },
+    New { down: Point, marquee: Marquee, combiner: SelectionCombiner, latched: LatchedMods },
+    Move { last: Point },
 }
 
 impl SelectToolCtx {
-    fn from_point(canvas: &Option<CanvasData>, pt: Point) -> Option<SelectToolCtx> {
-        if let Some(sel) = canvas.as_ref()?.selection() {
-            if sel.contains(pt) {
-                return Some(SelectToolCtx::Move { down: pt, old: sel.clone() });
+    fn from_point(
+        canvas: &Option<CanvasData>,
+        pt: Point,
+        mods: &Modifiers,
+        marquee: Marquee,
+    ) -> Option<SelectToolCtx> {
+        let canvas = canvas.as_ref()?;
+        let combiner = SelectionCombiner::new(canvas, mods);
+
+        if let Some(sel) = canvas.selection() {
+            if combiner.mode() == SelectionMode::Replace && sel.contains(pt) {
+                return Some(SelectToolCtx::Move { last: pt });
             }
         }
-        Some(SelectToolCtx::New { down: pt })
+        Some(SelectToolCtx::New { down: pt, marquee, combiner, latched: LatchedMods::new(mods) })
     }
 
-    fn moved(&mut self, canvas: &mut Option<CanvasData>, pt: Point) -> Option<()> {
-        let canvas = canvas.as_mut()?;
-
+    fn moved(
+        &mut self,
+        editor: &mut EditorState<ToolKind>,
+        pt: Point,
+        mods: &Modifiers,
+        kind: EditKind,
+    ) -> bool {
         match self {
-            SelectToolCtx::New { down } => {
-                canvas.select(Rect::from_points(*down, pt));
+            SelectToolCtx::New { down, marquee, combiner, latched } => {
+                combiner.select(editor, marquee.selection(*down, pt, latched, mods), kind)
             }
-            SelectToolCtx::Move { down, old } => {
-                let offset = pt.to_vec2() - down.to_vec2();
-                canvas.select(old.transform(offset));
+            SelectToolCtx::Move { last } => {
+                let offset = pt - *last;
+                *last = pt;
+                editor.do_edit(MoveSelectionOutline::new(offset), kind)
             }
         }
+    }
+}
 
-        Some(())
+fn marquee_event(
+    ctx: &mut EventCtx,
+    event: &Event,
+    data: &mut EditorState<ToolKind>,
+    tool_ctx: &mut Option<SelectToolCtx>,
+    marquee: Marquee,
+) {
+    if data.cursor != Some(Cursor::Crosshair) {
+        data.cursor = Some(Cursor::Crosshair);
+        ctx.set_cursor(&Cursor::Crosshair);
     }
+
+    match event {
+        Event::MouseDown(me) => {
+            if me.button == MouseButton::Left {
+                ctx.set_active(true);
+                *tool_ctx = SelectToolCtx::from_point(&data.canvas, me.pos, &me.mods, marquee);
+            }
+        }
+        Event::MouseMove(me) => {
+            if let Some(tool_ctx) = tool_ctx.as_mut() {
+                if tool_ctx.moved(data, me.pos, &me.mods, EditKind::Mergeable) {
+                    ctx.request_paint();
+                }
+            }
+        }
+        Event::MouseUp(me) => {
+            if me.button == MouseButton::Left {
+                if let Some(mut tool_ctx) = tool_ctx.take() {
+                    if tool_ctx.moved(data, me.pos, &me.mods, EditKind::NonMergeable) {
+                        ctx.request_paint();
+                    }
+                }
+                ctx.set_active(false);
+            }
+        }
+        _ => (),
+    };
 }
 
 impl Tool for SelectTool {
@@ -54,37 +167,23 @@ impl Tool for SelectTool {
         data: &mut EditorState<ToolKind>,
         tool_ctx: &mut Option<SelectToolCtx>,
     ) {
-        if data.cursor != Some(Cursor::Crosshair) {
-            data.cursor = Some(Cursor::Crosshair);
-            ctx.set_cursor(&Cursor::Crosshair);
-        }
+        marquee_event(ctx, event, data, tool_ctx, Marquee::Rect);
+    }
+}
 
-        match event {
-            Event::MouseDown(me) => {
-                if me.button == MouseButton::Left {
-                    ctx.set_active(true);
-                    *tool_ctx = SelectToolCtx::from_point(&mut data.canvas, me.pos);
-                }
-            }
-            Event::MouseMove(me) => {
-                if let Some(tool_ctx) = tool_ctx.as_mut() {
-                    if tool_ctx.moved(&mut data.canvas, me.pos).is_some() {
-                        ctx.request_paint();
-                    }
-                }
-            }
-            Event::MouseUp(me) => {
-                if me.button == MouseButton::Left {
-                    if let Some(mut tool_ctx) = tool_ctx.take() {
-                        if tool_ctx.moved(&mut data.canvas, me.pos).is_some() {
-                            ctx.request_paint();
-                        }
-                    }
-                    ctx.set_active(false);
-                }
-            }
-            _ => (),
-        };
+impl Tool for EllipseSelectTool {
+    type Context = SelectToolCtx;
+    type Kind = ToolKind;
+
+    fn event(
+        &self,
+        ctx: &mut EventCtx,
+        event: &Event,
+        data: &mut EditorState<ToolKind>,
+        tool_ctx: &mut Option<SelectToolCtx>,
+    ) {
+        let marquee = Marquee::Ellipse { anti_alias: data.anti_alias };
+        marquee_event(ctx, event, data, tool_ctx, marquee);
     }
 }
 
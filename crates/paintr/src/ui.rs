@@ -1,11 +1,19 @@
-use druid::{theme, Color, Env, Widget};
+use druid::{theme, Color, Env, Lens, LocalizedString, RenderContext, Widget};
 use druid::{
-    widget::{Align, Either, Flex, Label, Padding, Scroll, Svg, WidgetExt},
+    widget::{
+        self, Align, Button, Checkbox, Either, Flex, Label, Padding, Scroll, SizedBox, Slider, Svg,
+        WidgetExt,
+    },
     TextAlignment,
 };
+use paintr_core::{BrushSettings, MagicWandSettings};
 
-use crate::widgets::{notif_bar::NotificationContainer, Editor, ModalContainer, Named, RadioGroup};
-use crate::{AppState, EditorState, ToolKind};
+use crate::commands;
+use crate::widgets::{
+    notif_bar::NotificationContainer, ColorPicker, Editor, HistoryPanel, ModalContainer, Named,
+    RadioGroup,
+};
+use crate::{AppState, ColorTarget, EditorState, ToolKind};
 
 fn canvas() -> impl Widget<AppState> {
     let editor_lens = AppState::editor;
@@ -15,10 +23,12 @@ fn canvas() -> impl Widget<AppState> {
         Align::centered(Padding::new(10.0, Label::new(L!("paintr-front-page-welcome")))),
         Align::centered(Padding::new(
             10.0,
-            Named::new(
-                Scroll::new(Editor::new().lens(editor_lens)),
-                |data: &AppState, _env: &_| data.image_file_name(),
-            ),
+            Named::new(Editor::new().lens(editor_lens), |data: &AppState, _env: &_| {
+                match data.editor.is_dirty() {
+                    true => format!("{}*", data.image_file_name()),
+                    false => data.image_file_name(),
+                }
+            }),
         )),
     )
 }
@@ -28,6 +38,10 @@ fn toolbar() -> impl Widget<AppState> {
 
     let move_tool_icon = include_str!("assets/icons/move_tool.svg");
     let rect_marquee_tool_icon = include_str!("assets/icons/rect_marquee_tool.svg");
+    let ellipse_marquee_tool_icon = include_str!("assets/icons/ellipse_marquee_tool.svg");
+    let lasso_tool_icon = include_str!("assets/icons/lasso_tool.svg");
+    let polygonal_lasso_tool_icon = include_str!("assets/icons/polygonal_lasso_tool.svg");
+    let magic_wand_tool_icon = include_str!("assets/icons/magic_wand_tool.svg");
     let pen_tool_icon = include_str!("assets/icons/pen_tool.svg");
 
     let buttons: Vec<(Box<dyn Widget<_>>, _)> = vec![
@@ -47,6 +61,38 @@ fn toolbar() -> impl Widget<AppState> {
             ),
             ToolKind::Select,
         ),
+        (
+            Box::new(
+                Svg::new(ellipse_marquee_tool_icon.parse().unwrap())
+                    .fix_width(button_size)
+                    .fix_height(button_size),
+            ),
+            ToolKind::EllipseSelect,
+        ),
+        (
+            Box::new(
+                Svg::new(lasso_tool_icon.parse().unwrap())
+                    .fix_width(button_size)
+                    .fix_height(button_size),
+            ),
+            ToolKind::Lasso,
+        ),
+        (
+            Box::new(
+                Svg::new(polygonal_lasso_tool_icon.parse().unwrap())
+                    .fix_width(button_size)
+                    .fix_height(button_size),
+            ),
+            ToolKind::PolygonalLasso,
+        ),
+        (
+            Box::new(
+                Svg::new(magic_wand_tool_icon.parse().unwrap())
+                    .fix_width(button_size)
+                    .fix_height(button_size),
+            ),
+            ToolKind::MagicWand,
+        ),
         (
             Box::new(
                 Svg::new(pen_tool_icon.parse().unwrap())
@@ -60,8 +106,149 @@ fn toolbar() -> impl Widget<AppState> {
     RadioGroup::new(buttons).lens(EditorState::<ToolKind>::tool).lens(AppState::editor).padding(5.0)
 }
 
+fn brush_option(
+    label: LocalizedString<BrushSettings>,
+    min: f64,
+    max: f64,
+    lens: impl Lens<BrushSettings, f64> + 'static,
+    format: fn(f64) -> String,
+) -> impl Widget<BrushSettings> {
+    let value = Flex::row()
+        .with_child(Slider::new().with_range(min, max).fix_width(100.0))
+        .with_child(Label::dynamic(move |v: &f64, _| format(*v)).fix_width(50.0))
+        .lens(lens);
+
+    Flex::row().with_child(Label::new(label)).with_child(value).padding((5.0, 0.0))
+}
+
+fn brush_options() -> impl Widget<AppState> {
+    let pixels = |v: f64| format!("{:.0}px", v);
+    let percent = |v: f64| format!("{:.0}%", v);
+    let ratio = |v: f64| format!("{:.0}%", v * 100.0);
+
+    Flex::row()
+        .with_child(brush_option(L!("brush-size"), 1.0, 200.0, BrushSettings::diameter, pixels))
+        .with_child(brush_option(L!("brush-hardness"), 0.0, 1.0, BrushSettings::hardness, ratio))
+        .with_child(brush_option(L!("brush-spacing"), 1.0, 200.0, BrushSettings::spacing, percent))
+        .with_child(brush_option(L!("brush-flow"), 0.0, 1.0, BrushSettings::flow, ratio))
+        .with_child(brush_option(L!("brush-opacity"), 0.0, 1.0, BrushSettings::opacity, ratio))
+        .lens(EditorState::<ToolKind>::brush)
+        .lens(AppState::editor)
+        .padding(5.0)
+}
+
+fn ellipse_select_options() -> impl Widget<AppState> {
+    Checkbox::new(L!("selection-anti-alias"))
+        .lens(EditorState::<ToolKind>::anti_alias)
+        .lens(AppState::editor)
+        .padding(5.0)
+}
+
+fn magic_wand_options() -> impl Widget<AppState> {
+    let tolerance = Flex::row()
+        .with_child(Slider::new().with_range(0.0, 255.0).fix_width(100.0))
+        .with_child(Label::dynamic(|v: &f64, _| format!("{:.0}", v)).fix_width(50.0))
+        .lens(MagicWandSettings::tolerance);
+
+    Flex::row()
+        .with_child(Label::new(L!("magic-wand-tolerance")))
+        .with_child(tolerance)
+        .with_child(Checkbox::new(L!("magic-wand-contiguous")).lens(MagicWandSettings::contiguous))
+        .with_spacer(5.0)
+        .with_child(
+            Checkbox::new(L!("magic-wand-sample-merged")).lens(MagicWandSettings::sample_merged),
+        )
+        .lens(EditorState::<ToolKind>::wand)
+        .lens(AppState::editor)
+        .padding(5.0)
+}
+
+fn tool_options() -> impl Widget<AppState> {
+    Either::new(
+        |data: &AppState, _| data.editor.tool == ToolKind::Brush,
+        brush_options(),
+        Either::new(
+            |data: &AppState, _| data.editor.tool == ToolKind::EllipseSelect,
+            ellipse_select_options(),
+            Either::new(
+                |data: &AppState, _| data.editor.tool == ToolKind::MagicWand,
+                magic_wand_options(),
+                SizedBox::empty(),
+            ),
+        ),
+    )
+}
+
+fn color_swatch(target: ColorTarget) -> impl Widget<AppState> {
+    widget::Painter::new(move |ctx, data: &AppState, _| {
+        let color = match target {
+            ColorTarget::Foreground => &data.editor.foreground,
+            ColorTarget::Background => &data.editor.background,
+        };
+        let border = match data.color_target == target {
+            true => Color::WHITE,
+            false => Color::BLACK,
+        };
+
+        let rect = ctx.size().to_rect();
+        ctx.fill(rect, color);
+        ctx.stroke(rect.inset(-1.0), &border, 2.0);
+    })
+    .fix_size(24.0, 24.0)
+    .on_click(move |_, data: &mut AppState, _| data.color_target = target)
+}
+
+fn color_panel() -> impl Widget<AppState> {
+    let swatches = Flex::row()
+        .with_child(color_swatch(ColorTarget::Foreground))
+        .with_spacer(5.0)
+        .with_child(color_swatch(ColorTarget::Background))
+        .with_spacer(5.0)
+        .with_child(
+            Button::new(L!("color-swap"))
+                .on_click(|ctx, _, _| ctx.submit_command(commands::EDIT_SWAP_COLORS_ACTION)),
+        )
+        .with_child(
+            Button::new(L!("color-reset"))
+                .on_click(|ctx, _, _| ctx.submit_command(commands::EDIT_RESET_COLORS_ACTION)),
+        );
+
+    let picker = Either::new(
+        |data: &AppState, _| data.color_target == ColorTarget::Foreground,
+        ColorPicker::new().lens(EditorState::<ToolKind>::foreground).lens(AppState::editor),
+        ColorPicker::new().lens(EditorState::<ToolKind>::background).lens(AppState::editor),
+    );
+
+    Flex::column().with_child(swatches.padding(5.0)).with_child(picker)
+}
+
+fn history_panel() -> impl Widget<AppState> {
+    let buttons = Flex::row()
+        .with_child(
+            Button::new(L!("history-new-snapshot")).on_click(|ctx, _, _| {
+                ctx.submit_command(commands::HISTORY_NEW_SNAPSHOT_DIALOG_ACTION)
+            }),
+        )
+        .with_spacer(5.0)
+        .with_child(
+            Button::new(L!("history-new-document"))
+                .on_click(|ctx, _, _| ctx.submit_command(commands::HISTORY_NEW_DOCUMENT_ACTION)),
+        );
+
+    let panel = Flex::column()
+        .with_child(Label::new(L!("history-title")).padding(5.0))
+        .with_flex_child(Scroll::new(HistoryPanel::new()).vertical().lens(AppState::editor), 1.0)
+        .with_child(buttons.padding(5.0))
+        .fix_width(240.0);
+
+    Either::new(|data: &AppState, _| data.show_history, panel, SizedBox::empty())
+}
+
 pub(crate) fn ui_builder() -> impl Widget<AppState> {
-    let content = Flex::row().with_child(toolbar()).with_flex_child(canvas(), 1.0);
+    let side_panel = Flex::column().with_child(color_panel()).with_flex_child(history_panel(), 1.0);
+    let content =
+        Flex::row().with_child(toolbar()).with_flex_child(canvas(), 1.0).with_child(side_panel);
+    let content = Flex::column().with_child(tool_options()).with_flex_child(content, 1.0);
 
     let container = ModalContainer::new(
         NotificationContainer::new(content, AppState::notifications),
@@ -1,6 +1,8 @@
 use super::canvas::CanvasData;
 use super::edit::{Edit, EditDesc};
-use druid::Vec2;
+use super::layer::{LayerId, LayerLock};
+use crate::{image_utils, BlendMode, BrushSettings, Selection, SelectionModify};
+use druid::{Color, Vec2};
 use std::any::Any;
 use std::sync::Arc;
 
@@ -30,6 +32,10 @@ impl Edit<CanvasData> for Paste {
     fn description(&self) -> EditDesc {
         EditDesc::new("Paste")
     }
+
+    fn size(&self) -> usize {
+        self.img.as_bytes().len()
+    }
 }
 
 /// Move the whole canvas
@@ -100,27 +106,126 @@ impl Edit<CanvasData> for MoveSelection {
 #[derive(Debug)]
 pub struct DrawBrush {
     pos: Vec<Vec2>,
+    brush: BrushSettings,
+    color: Color,
+    new_stroke: bool,
 }
 
 impl DrawBrush {
-    pub fn new(pos: Vec<Vec2>) -> Self {
-        DrawBrush { pos }
+    /// Start a new stroke
+    pub fn new(pos: Vec<Vec2>, brush: BrushSettings, color: Color) -> Self {
+        DrawBrush { pos, brush, color, new_stroke: true }
+    }
+
+    /// Continue the stroke of the last `DrawBrush`
+    pub fn continued(pos: Vec<Vec2>, brush: BrushSettings, color: Color) -> Self {
+        DrawBrush { pos, brush, color, new_stroke: false }
     }
 }
 
 #[must_use]
 impl Edit<CanvasData> for DrawBrush {
     fn apply(&self, data: &mut CanvasData) {
-        data.draw_with_brush(&self.pos);
+        let color = image_utils::color_to_rgba(&self.color);
+        data.draw_with_brush(&self.pos, &self.brush, color, self.new_stroke);
     }
 
     fn description(&self) -> EditDesc {
         EditDesc::new("Draw Brush")
     }
 
+    fn merge(&self, other: &mut dyn Any) -> bool {
+        match other.downcast_mut::<Self>() {
+            Some(other)
+                if !other.new_stroke && other.brush == self.brush && other.color == self.color =>
+            {
+                let mut pos = self.pos.clone();
+                pos.append(&mut other.pos);
+                other.pos = pos;
+                other.new_stroke = self.new_stroke;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Fill the selection, or the active layer without a selection
+#[derive(Debug)]
+pub struct Fill {
+    color: Color,
+}
+
+impl Fill {
+    pub fn new(color: Color) -> Fill {
+        Fill { color }
+    }
+}
+
+#[must_use]
+impl Edit<CanvasData> for Fill {
+    fn apply(&self, data: &mut CanvasData) {
+        data.fill(image_utils::color_to_rgba(&self.color));
+    }
+
+    fn description(&self) -> EditDesc {
+        EditDesc::new("Fill")
+    }
+}
+
+/// Replace the selection
+#[derive(Debug)]
+pub struct Select {
+    selection: Selection,
+}
+
+impl Select {
+    pub fn new(selection: impl Into<Selection>) -> Select {
+        Select { selection: selection.into() }
+    }
+}
+
+#[must_use]
+impl Edit<CanvasData> for Select {
+    fn apply(&self, data: &mut CanvasData) {
+        data.select(self.selection.clone());
+    }
+
+    fn description(&self) -> EditDesc {
+        EditDesc::new("Select")
+    }
+
+    fn merge(&self, other: &mut dyn Any) -> bool {
+        // The newer selection replaces this one
+        other.downcast_mut::<Self>().is_some()
+    }
+}
+
+/// Move the outline of the selection, without the pixels inside
+#[derive(Debug)]
+pub struct MoveSelectionOutline {
+    offset: Vec2,
+}
+
+impl MoveSelectionOutline {
+    pub fn new(offset: Vec2) -> MoveSelectionOutline {
+        MoveSelectionOutline { offset }
+    }
+}
+
+#[must_use]
+impl Edit<CanvasData> for MoveSelectionOutline {
+    fn apply(&self, data: &mut CanvasData) {
+        data.move_selection_outline(self.offset);
+    }
+
+    fn description(&self) -> EditDesc {
+        EditDesc::new("Move Selection Outline")
+    }
+
     fn merge(&self, other: &mut dyn Any) -> bool {
         if let Some(other) = other.downcast_mut::<Self>() {
-            other.pos.append(&mut self.pos.clone());
+            other.offset += self.offset;
             true
         } else {
             false
@@ -128,14 +233,407 @@ impl Edit<CanvasData> for DrawBrush {
     }
 }
 
+/// Select the whole canvas
+#[derive(Debug, Default)]
+pub struct SelectAll;
+
+impl SelectAll {
+    pub fn new() -> SelectAll {
+        SelectAll
+    }
+}
+
+#[must_use]
+impl Edit<CanvasData> for SelectAll {
+    fn apply(&self, data: &mut CanvasData) {
+        data.select_all();
+    }
+
+    fn description(&self) -> EditDesc {
+        EditDesc::new("Select All")
+    }
+}
+
+/// Remove the selection
+#[derive(Debug, Default)]
+pub struct Deselect;
+
+impl Deselect {
+    pub fn new() -> Deselect {
+        Deselect
+    }
+}
+
+#[must_use]
+impl Edit<CanvasData> for Deselect {
+    fn apply(&self, data: &mut CanvasData) {
+        data.deselect();
+    }
+
+    fn description(&self) -> EditDesc {
+        EditDesc::new("Deselect")
+    }
+}
+
+/// Select the pixels outside the selection
+#[derive(Debug, Default)]
+pub struct InvertSelection;
+
+impl InvertSelection {
+    pub fn new() -> InvertSelection {
+        InvertSelection
+    }
+}
+
+#[must_use]
+impl Edit<CanvasData> for InvertSelection {
+    fn apply(&self, data: &mut CanvasData) {
+        data.invert_selection();
+    }
+
+    fn description(&self) -> EditDesc {
+        EditDesc::new("Invert Selection")
+    }
+}
+
+/// Grow, shrink, border, smooth or feather the selection
+#[derive(Debug)]
+pub struct ModifySelection {
+    modify: SelectionModify,
+}
+
+impl ModifySelection {
+    pub fn new(modify: SelectionModify) -> ModifySelection {
+        ModifySelection { modify }
+    }
+}
+
+#[must_use]
+impl Edit<CanvasData> for ModifySelection {
+    fn apply(&self, data: &mut CanvasData) {
+        data.modify_selection(self.modify);
+    }
+
+    fn description(&self) -> EditDesc {
+        let desc = match self.modify {
+            SelectionModify::Grow(_) => "Grow Selection",
+            SelectionModify::Shrink(_) => "Shrink Selection",
+            SelectionModify::Border(_) => "Border Selection",
+            SelectionModify::Smooth(_) => "Smooth Selection",
+            SelectionModify::Feather(_) => "Feather Selection",
+        };
+        EditDesc::new(desc)
+    }
+}
+
+/// Insert a new empty layer above the active layer
+#[derive(Debug, Default)]
+pub struct InsertLayer;
+
+impl InsertLayer {
+    pub fn new() -> InsertLayer {
+        InsertLayer
+    }
+}
+
+#[must_use]
+impl Edit<CanvasData> for InsertLayer {
+    fn apply(&self, data: &mut CanvasData) {
+        data.insert_layer();
+    }
+
+    fn description(&self) -> EditDesc {
+        EditDesc::new("New Layer")
+    }
+}
+
+/// Delete a layer
+#[derive(Debug)]
+pub struct DeleteLayer {
+    id: LayerId,
+}
+
+impl DeleteLayer {
+    pub fn new(id: LayerId) -> DeleteLayer {
+        DeleteLayer { id }
+    }
+}
+
+#[must_use]
+impl Edit<CanvasData> for DeleteLayer {
+    fn apply(&self, data: &mut CanvasData) {
+        data.delete_layer(self.id);
+    }
+
+    fn description(&self) -> EditDesc {
+        EditDesc::new("Delete Layer")
+    }
+}
+
+/// Move a layer to another position of the layer stack
+#[derive(Debug)]
+pub struct ReorderLayer {
+    id: LayerId,
+    index: usize,
+}
+
+impl ReorderLayer {
+    pub fn new(id: LayerId, index: usize) -> ReorderLayer {
+        ReorderLayer { id, index }
+    }
+}
+
+#[must_use]
+impl Edit<CanvasData> for ReorderLayer {
+    fn apply(&self, data: &mut CanvasData) {
+        data.reorder_layer(self.id, self.index);
+    }
+
+    fn description(&self) -> EditDesc {
+        EditDesc::new("Reorder Layer")
+    }
+}
+
+/// Duplicate a layer
+#[derive(Debug)]
+pub struct DuplicateLayer {
+    id: LayerId,
+}
+
+impl DuplicateLayer {
+    pub fn new(id: LayerId) -> DuplicateLayer {
+        DuplicateLayer { id }
+    }
+}
+
+#[must_use]
+impl Edit<CanvasData> for DuplicateLayer {
+    fn apply(&self, data: &mut CanvasData) {
+        data.duplicate_layer(self.id);
+    }
+
+    fn description(&self) -> EditDesc {
+        EditDesc::new("Duplicate Layer")
+    }
+}
+
+/// Merge a layer into the layer below it
+#[derive(Debug)]
+pub struct MergeLayerDown {
+    id: LayerId,
+}
+
+impl MergeLayerDown {
+    pub fn new(id: LayerId) -> MergeLayerDown {
+        MergeLayerDown { id }
+    }
+}
+
+#[must_use]
+impl Edit<CanvasData> for MergeLayerDown {
+    fn apply(&self, data: &mut CanvasData) {
+        data.merge_layer_down(self.id);
+    }
+
+    fn description(&self) -> EditDesc {
+        EditDesc::new("Merge Down")
+    }
+}
+
+/// Rename a layer
+#[derive(Debug)]
+pub struct RenameLayer {
+    id: LayerId,
+    name: String,
+}
+
+impl RenameLayer {
+    pub fn new(id: LayerId, name: impl Into<String>) -> RenameLayer {
+        RenameLayer { id, name: name.into() }
+    }
+}
+
+#[must_use]
+impl Edit<CanvasData> for RenameLayer {
+    fn apply(&self, data: &mut CanvasData) {
+        data.rename_layer(self.id, &self.name);
+    }
+
+    fn description(&self) -> EditDesc {
+        EditDesc::new("Rename Layer")
+    }
+}
+
+/// Move a layer
+#[derive(Debug)]
+pub struct MoveLayer {
+    id: LayerId,
+    offset: Vec2,
+}
+
+impl MoveLayer {
+    pub fn new(id: LayerId, offset: Vec2) -> MoveLayer {
+        MoveLayer { id, offset }
+    }
+}
+
+#[must_use]
+impl Edit<CanvasData> for MoveLayer {
+    fn apply(&self, data: &mut CanvasData) {
+        data.move_layer(self.id, self.offset);
+    }
+
+    fn description(&self) -> EditDesc {
+        EditDesc::new("Move Layer")
+    }
+
+    fn merge(&self, other: &mut dyn Any) -> bool {
+        match other.downcast_mut::<Self>() {
+            Some(other) if other.id == self.id => {
+                other.offset += self.offset;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Change the opacity of a layer
+#[derive(Debug)]
+pub struct SetLayerOpacity {
+    id: LayerId,
+    opacity: f64,
+}
+
+impl SetLayerOpacity {
+    pub fn new(id: LayerId, opacity: f64) -> SetLayerOpacity {
+        SetLayerOpacity { id, opacity }
+    }
+}
+
+#[must_use]
+impl Edit<CanvasData> for SetLayerOpacity {
+    fn apply(&self, data: &mut CanvasData) {
+        data.set_layer_opacity(self.id, self.opacity);
+    }
+
+    fn description(&self) -> EditDesc {
+        EditDesc::new("Layer Opacity")
+    }
+
+    fn merge(&self, other: &mut dyn Any) -> bool {
+        match other.downcast_mut::<Self>() {
+            Some(other) => other.id == self.id,
+            None => false,
+        }
+    }
+}
+
+/// Change the blend mode of a layer
+#[derive(Debug)]
+pub struct SetLayerBlendMode {
+    id: LayerId,
+    mode: BlendMode,
+}
+
+impl SetLayerBlendMode {
+    pub fn new(id: LayerId, mode: BlendMode) -> SetLayerBlendMode {
+        SetLayerBlendMode { id, mode }
+    }
+}
+
+#[must_use]
+impl Edit<CanvasData> for SetLayerBlendMode {
+    fn apply(&self, data: &mut CanvasData) {
+        data.set_layer_blend_mode(self.id, self.mode);
+    }
+
+    fn description(&self) -> EditDesc {
+        EditDesc::new("Blend Mode")
+    }
+}
+
+/// Show or hide a layer
+#[derive(Debug)]
+pub struct SetLayerVisible {
+    id: LayerId,
+    visible: bool,
+}
+
+impl SetLayerVisible {
+    pub fn new(id: LayerId, visible: bool) -> SetLayerVisible {
+        SetLayerVisible { id, visible }
+    }
+}
+
+#[must_use]
+impl Edit<CanvasData> for SetLayerVisible {
+    fn apply(&self, data: &mut CanvasData) {
+        data.set_layer_visible(self.id, self.visible);
+    }
+
+    fn description(&self) -> EditDesc {
+        EditDesc::new(if self.visible { "Show Layer" } else { "Hide Layer" })
+    }
+}
+
+/// Lock or unlock a layer
+#[derive(Debug)]
+pub struct SetLayerLock {
+    id: LayerId,
+    lock: LayerLock,
+    locked: bool,
+}
+
+impl SetLayerLock {
+    pub fn new(id: LayerId, lock: LayerLock, locked: bool) -> SetLayerLock {
+        SetLayerLock { id, lock, locked }
+    }
+}
+
+#[must_use]
+impl Edit<CanvasData> for SetLayerLock {
+    fn apply(&self, data: &mut CanvasData) {
+        data.set_layer_lock(self.id, self.lock, self.locked);
+    }
+
+    fn description(&self) -> EditDesc {
+        EditDesc::new(if self.locked { "Lock Layer" } else { "Unlock Layer" })
+    }
+}
+
+/// Make a layer the target of drawing, pasting and moving
+#[derive(Debug)]
+pub struct SetActiveLayer {
+    id: LayerId,
+}
+
+impl SetActiveLayer {
+    pub fn new(id: LayerId) -> SetActiveLayer {
+        SetActiveLayer { id }
+    }
+}
+
+#[must_use]
+impl Edit<CanvasData> for SetActiveLayer {
+    fn apply(&self, data: &mut CanvasData) {
+        data.set_active_layer(self.id);
+    }
+
+    fn description(&self) -> EditDesc {
+        EditDesc::new("Select Layer")
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
     use crate::image_utils::{
-        colors::{BLACK, TRANSPARENT, WHITE},
+        colors::{BLACK, TRANSPARENT, WHITE, YELLOW},
         make_color_img,
     };
     use crate::test_utils::canvas_fixture;
+    use crate::{CopyMode, EditKind, UndoHistory};
     use druid::{Point, Rect};
     use image::{DynamicImage, GenericImageView};
 
@@ -190,16 +688,397 @@ mod test {
     #[test]
     fn move_selection_should_works_in_multiple_planes() {
         let mut canvas = canvas_fixture(16, 16, BLACK);
+        let background = canvas.active_layer();
 
         canvas.select(Rect::from_origin_size(Point::ZERO, (4.0, 4.0)));
         let action = MoveSelection::new(Vec2::new(4.0, 4.0));
         action.execute(&mut canvas);
         assert_eq!(canvas.merged().get_pixel(2, 2), TRANSPARENT);
+        assert_ne!(canvas.active_layer(), background);
 
+        canvas.set_active_layer(background);
         canvas.select(Rect::from_origin_size(Point::ZERO, (8.0, 8.0)));
         let action = MoveSelection::new(Vec2::new(8.0, 8.0));
         action.execute(&mut canvas);
-        assert_eq!(canvas.merged().get_pixel(6, 6), TRANSPARENT);
+        assert_eq!(canvas.merged().get_pixel(6, 6), BLACK);
+        assert_eq!(canvas.merged().get_pixel(14, 14), BLACK);
+        assert_eq!(canvas.layers().len(), 3);
+    }
+
+    #[test]
+    fn move_selection_should_only_cut_active_layer() {
+        let mut canvas = canvas_fixture(16, 16, BLACK);
+        Paste::new(make_color_img(8, 8, WHITE)).execute(&mut canvas);
+        let pasted = canvas.active_layer();
+        canvas.set_active_layer(canvas.layers()[0].id());
+
+        canvas.select(Rect::from_origin_size(Point::ZERO, (4.0, 4.0)));
+        MoveSelection::new(Vec2::new(12.0, 12.0)).execute(&mut canvas);
+
+        let img = canvas.merged();
+        assert_eq!(img.get_pixel(2, 2), WHITE);
+        assert_eq!(img.get_pixel(14, 14), BLACK);
+        assert_eq!(canvas.layer(pasted).unwrap().image().get_pixel(2, 2), WHITE);
+    }
+
+    #[test]
+    fn layer_actions_should_works() {
+        let mut canvas = canvas_fixture(16, 16, BLACK);
+        let background = canvas.active_layer();
+
+        InsertLayer::new().execute(&mut canvas);
+        let layer = canvas.active_layer();
+        assert_eq!(canvas.layers().len(), 2);
+        assert_eq!(canvas.merged().get_pixel(2, 2), BLACK);
+
+        RenameLayer::new(layer, "Foo").execute(&mut canvas);
+        assert_eq!(canvas.layer(layer).unwrap().name(), "Foo");
+
+        DuplicateLayer::new(layer).execute(&mut canvas);
+        assert_eq!(canvas.layers().len(), 3);
+        assert_eq!(canvas.layers()[2].name(), "Foo copy");
+
+        ReorderLayer::new(background, 2).execute(&mut canvas);
+        assert_eq!(canvas.layers()[2].id(), background);
+
+        SetActiveLayer::new(background).execute(&mut canvas);
+        assert_eq!(canvas.active_layer(), background);
+
+        DeleteLayer::new(background).execute(&mut canvas);
+        assert_eq!(canvas.layers().len(), 2);
+        assert_eq!(canvas.merged().get_pixel(2, 2), TRANSPARENT);
+    }
+
+    #[test]
+    fn merge_layer_down_should_works() {
+        let mut canvas = canvas_fixture(16, 16, BLACK);
+        let background = canvas.active_layer();
+
+        Paste::new(make_color_img(4, 4, WHITE)).execute(&mut canvas);
+        let pasted = canvas.active_layer();
+        MoveLayer::new(pasted, Vec2::new(4.0, 4.0)).execute(&mut canvas);
+
+        let old = MergeLayerDown::new(pasted).execute(&mut canvas);
+        assert_eq!(old.layers().len(), 2);
+        assert_eq!(canvas.layers().len(), 1);
+        assert_eq!(canvas.active_layer(), background);
+
+        let img = canvas.merged();
+        assert_eq!(img.get_pixel(2, 2), BLACK);
+        assert_eq!(img.get_pixel(6, 6), WHITE);
+    }
+
+    #[test]
+    fn draw_brush_should_draw_on_active_layer() {
+        let mut canvas = canvas_fixture(16, 16, BLACK);
+        let background = canvas.active_layer();
+        InsertLayer::new().execute(&mut canvas);
+        let layer = canvas.active_layer();
+
+        DrawBrush::new(vec![Vec2::new(8.0, 8.0)], BrushSettings::default(), yellow())
+            .execute(&mut canvas);
+
+        let img = canvas.layer(layer).unwrap().image();
+        assert_eq!(img.get_pixel(8, 8), YELLOW);
+        let img = canvas.layer(background).unwrap().image();
+        assert_eq!(img.get_pixel(8, 8), BLACK);
+    }
+
+    fn yellow() -> Color {
+        let [r, g, b, a] = YELLOW.0;
+        Color::rgba8(r, g, b, a)
+    }
+
+    fn draw_stroke(
+        history: &mut UndoHistory<CanvasData>,
+        canvas: &mut CanvasData,
+        brush: BrushSettings,
+        pts: &[Vec2],
+    ) {
+        let (last, pts) = pts.split_last().unwrap();
+        let (first, pts) = pts.split_first().unwrap();
+        history.edit(canvas, DrawBrush::new(vec![*first], brush, yellow()), EditKind::Mergeable);
+        for p in pts {
+            history.edit(
+                canvas,
+                DrawBrush::continued(vec![*p], brush, yellow()),
+                EditKind::Mergeable,
+            );
+        }
+        history.edit(
+            canvas,
+            DrawBrush::continued(vec![*last], brush, yellow()),
+            EditKind::NonMergeable,
+        );
+    }
+
+    #[test]
+    fn draw_brush_should_undo_and_redo() {
+        let mut canvas = canvas_fixture(16, 16, BLACK);
+        let mut history = UndoHistory::new();
+        let before = canvas.merged();
+
+        let brush = BrushSettings { hardness: 0.5, spacing: 40.0, flow: 0.5, ..Default::default() };
+        let stroke = [Vec2::new(4.0, 4.0), Vec2::new(8.0, 8.0), Vec2::new(12.0, 12.0)];
+        draw_stroke(&mut history, &mut canvas, brush, &stroke);
+        let drawn = canvas.merged();
+        for p in stroke.iter() {
+            assert_ne!(drawn.get_pixel(p.x as u32, p.y as u32), BLACK);
+        }
+
+        // A stroke is undone as a whole
+        assert!(history.undo(&mut canvas).is_some());
+        assert_eq!(canvas.merged().to_bytes(), before.to_bytes());
+        assert!(history.undo(&mut canvas).is_none());
+
+        assert!(history.redo(&mut canvas).is_some());
+        assert_eq!(canvas.merged().to_bytes(), drawn.to_bytes());
+    }
+
+    #[test]
+    fn draw_brush_undo_should_keep_previous_strokes() {
+        let mut canvas = canvas_fixture(16, 16, BLACK);
+        let mut history = UndoHistory::new();
+        let before = canvas.merged();
+
+        let brush = BrushSettings::default();
+        draw_stroke(&mut history, &mut canvas, brush, &[Vec2::new(4.0, 4.0), Vec2::new(5.0, 5.0)]);
+        let first = canvas.merged();
+        let pts = [Vec2::new(12.0, 12.0), Vec2::new(11.0, 11.0)];
+        draw_stroke(&mut history, &mut canvas, brush, &pts);
+        let second = canvas.merged();
+        assert_eq!(first.get_pixel(12, 12), BLACK);
+        assert_eq!(second.get_pixel(12, 12), YELLOW);
+
+        history.undo(&mut canvas);
+        assert_eq!(canvas.merged().to_bytes(), first.to_bytes());
+        history.undo(&mut canvas);
+        assert_eq!(canvas.merged().to_bytes(), before.to_bytes());
+
+        history.redo(&mut canvas);
+        assert_eq!(canvas.merged().to_bytes(), first.to_bytes());
+        history.redo(&mut canvas);
+        assert_eq!(canvas.merged().to_bytes(), second.to_bytes());
+    }
+
+    #[test]
+    fn draw_brush_should_keep_images_taken_during_stroke() {
+        let mut canvas = canvas_fixture(16, 16, BLACK);
+        let layer = canvas.active_layer();
+        let brush = BrushSettings::default();
+
+        DrawBrush::new(vec![Vec2::new(4.0, 4.0)], brush, yellow()).execute(&mut canvas);
+        let img = canvas.layer(layer).unwrap().image();
+        DrawBrush::continued(vec![Vec2::new(12.0, 12.0)], brush, yellow()).execute(&mut canvas);
+
+        assert_eq!(img.get_pixel(12, 12), BLACK);
+        assert_eq!(canvas.layer(layer).unwrap().image().get_pixel(12, 12), YELLOW);
+    }
+
+    #[test]
+    fn draw_brush_should_undo_after_saving_during_stroke() {
+        let mut canvas = canvas_fixture(16, 16, BLACK);
+        let mut history = UndoHistory::new();
+        let before = canvas.merged();
+        let brush = BrushSettings::default();
+
+        history.edit(
+            &mut canvas,
+            DrawBrush::new(vec![Vec2::new(4.0, 4.0)], brush, yellow()),
+            EditKind::Mergeable,
+        );
+        history.mark_saved(&canvas);
+        let saved = canvas.merged();
+        history.edit(
+            &mut canvas,
+            DrawBrush::continued(vec![Vec2::new(12.0, 12.0)], brush, yellow()),
+            EditKind::NonMergeable,
+        );
+        assert_eq!(canvas.merged().get_pixel(12, 12), YELLOW);
+
+        history.undo(&mut canvas);
+        assert_eq!(canvas.merged().to_bytes(), saved.to_bytes());
+        assert_eq!(canvas.merged().get_pixel(12, 12), BLACK);
+        history.undo(&mut canvas);
+        assert_eq!(canvas.merged().to_bytes(), before.to_bytes());
+    }
+
+    #[test]
+    fn fill_should_works() {
+        let mut canvas = canvas_fixture(16, 16, BLACK);
+        let old = Fill::new(yellow()).execute(&mut canvas);
+        assert_eq!(canvas.merged().get_pixel(0, 0), YELLOW);
+        assert_eq!(canvas.merged().get_pixel(15, 15), YELLOW);
+        assert_eq!(old.merged().get_pixel(0, 0), BLACK);
+    }
+
+    #[test]
+    fn fill_should_respect_selection() {
+        let mut canvas = canvas_fixture(16, 16, BLACK);
+        canvas.select(Rect::from_origin_size((4.0, 4.0), (4.0, 4.0)));
+        Fill::new(yellow()).execute(&mut canvas);
+
+        let merged = canvas.merged();
+        assert_eq!(merged.get_pixel(4, 4), YELLOW);
+        assert_eq!(merged.get_pixel(7, 7), YELLOW);
+        assert_eq!(merged.get_pixel(3, 3), BLACK);
+        assert_eq!(merged.get_pixel(8, 8), BLACK);
+
+        // Locked layers are not filled
+        let background = canvas.active_layer();
+        SetLayerLock::new(background, LayerLock::Pixels, true).execute(&mut canvas);
+        canvas.select(Rect::from_origin_size((0.0, 0.0), (16.0, 16.0)));
+        Fill::new(yellow()).execute(&mut canvas);
+        assert_eq!(canvas.merged().get_pixel(0, 0), BLACK);
+    }
+
+    #[test]
+    fn select_actions_should_undo_and_redo() {
+        let mut canvas = canvas_fixture(16, 16, BLACK);
+        let mut history = UndoHistory::new();
+
+        history.edit(&mut canvas, SelectAll::new(), EditKind::NonMergeable);
+        assert_eq!(canvas.selection().unwrap().area(), 256.0);
+
+        history.edit(&mut canvas, Deselect::new(), EditKind::NonMergeable);
+        assert!(canvas.selection().is_none());
+
+        canvas.select(Rect::new(4.0, 4.0, 8.0, 8.0));
+        history.edit(&mut canvas, InvertSelection::new(), EditKind::NonMergeable);
+        let sel = canvas.selection().unwrap();
+        assert_eq!(sel.area(), 256.0 - 16.0);
+        assert!(!sel.contains(Point::new(5.0, 5.0)));
+        assert!(sel.contains(Point::new(1.0, 1.0)));
+
+        history.undo(&mut canvas);
+        assert_eq!(canvas.selection().unwrap().area(), 16.0);
+
+        let grow = ModifySelection::new(SelectionModify::Grow(1));
+        history.edit(&mut canvas, grow, EditKind::NonMergeable);
+        assert_eq!(canvas.selection().unwrap().bounds(), Rect::new(3.0, 3.0, 9.0, 9.0));
+
+        // Before the deselect
+        history.undo(&mut canvas);
+        history.undo(&mut canvas);
+        assert_eq!(canvas.selection().unwrap().area(), 256.0);
+        history.redo(&mut canvas);
+        assert!(canvas.selection().is_none());
+    }
+
+    #[test]
+    fn select_drag_should_merge_into_one_edit() {
+        let mut canvas = canvas_fixture(16, 16, BLACK);
+        let mut history = UndoHistory::new();
+        canvas.select(Rect::new(0.0, 0.0, 2.0, 2.0));
+
+        for i in 4..8 {
+            let rect = Rect::new(4.0, 4.0, i as f64, i as f64);
+            history.edit(&mut canvas, Select::new(rect), EditKind::Mergeable);
+        }
+        history.edit(
+            &mut canvas,
+            Select::new(Rect::new(4.0, 4.0, 8.0, 8.0)),
+            EditKind::NonMergeable,
+        );
+        for _ in 0..3 {
+            let edit = MoveSelectionOutline::new(Vec2::new(1.0, 1.0));
+            history.edit(&mut canvas, edit, EditKind::Mergeable);
+        }
+        let edit = MoveSelectionOutline::new(Vec2::new(1.0, 1.0));
+        history.edit(&mut canvas, edit, EditKind::NonMergeable);
+
+        let sel = canvas.selection().unwrap();
+        assert_eq!(sel.bounds(), Rect::new(8.0, 8.0, 12.0, 12.0));
+        // Only the outline is moved
+        assert_eq!(canvas.layers().len(), 1);
+        assert_eq!(canvas.merged().get_pixel(8, 8), BLACK);
+
+        history.undo(&mut canvas);
+        assert_eq!(canvas.selection().unwrap().bounds(), Rect::new(4.0, 4.0, 8.0, 8.0));
+        history.undo(&mut canvas);
+        assert_eq!(canvas.selection().unwrap().bounds(), Rect::new(0.0, 0.0, 2.0, 2.0));
+        history.redo(&mut canvas);
+        assert_eq!(canvas.selection().unwrap().bounds(), Rect::new(4.0, 4.0, 8.0, 8.0));
+    }
+
+    #[test]
+    fn feathered_selection_should_copy_with_alpha() {
+        let mut canvas = canvas_fixture(16, 16, BLACK);
+        canvas.select(Rect::new(4.0, 4.0, 12.0, 12.0));
+        ModifySelection::new(SelectionModify::Feather(4.0)).execute(&mut canvas);
+
+        let sel = canvas.selection().unwrap();
+        let img = sel.copy(canvas.merged(), CopyMode::Expand).unwrap();
+        let pos = sel.position();
+        let alpha = |x: f64, y: f64| img.get_pixel((x - pos.x) as u32, (y - pos.y) as u32).0[3];
+        assert!(alpha(8.0, 8.0) > 0xe0);
+        assert!(alpha(4.0, 8.0) > 0 && alpha(4.0, 8.0) < 0xff);
+        assert!(alpha(2.0, 8.0) < alpha(4.0, 8.0));
+    }
+
+    #[test]
+    fn layer_opacity_and_visibility_should_works() {
+        let mut canvas = canvas_fixture(16, 16, BLACK);
+        let background = canvas.active_layer();
+        Paste::new(make_color_img(4, 4, WHITE)).execute(&mut canvas);
+        let pasted = canvas.active_layer();
+
+        SetLayerOpacity::new(pasted, 0.5).execute(&mut canvas);
+        assert_eq!(canvas.layer(pasted).unwrap().opacity(), 0.5);
+        let pixel = canvas.merged().get_pixel(2, 2);
+        assert_ne!(pixel, WHITE);
+        assert_ne!(pixel, BLACK);
+
+        let old = SetLayerVisible::new(pasted, false).execute(&mut canvas);
+        assert_eq!(canvas.merged().get_pixel(2, 2), BLACK);
+        assert_eq!(old.merged().get_pixel(2, 2), pixel);
+
+        SetLayerVisible::new(background, false).execute(&mut canvas);
+        assert_eq!(canvas.merged().get_pixel(2, 2), TRANSPARENT);
+    }
+
+    #[test]
+    fn layer_blend_mode_should_works() {
+        let mut canvas = canvas_fixture(16, 16, YELLOW);
+        let background = canvas.active_layer();
+        Paste::new(make_color_img(4, 4, YELLOW)).execute(&mut canvas);
+        let pasted = canvas.active_layer();
+
+        let old = SetLayerBlendMode::new(pasted, BlendMode::Difference).execute(&mut canvas);
+        assert_eq!(canvas.layer(pasted).unwrap().blend_mode(), BlendMode::Difference);
+        assert_eq!(canvas.merged().get_pixel(2, 2), BLACK);
+        assert_eq!(old.merged().get_pixel(2, 2), YELLOW);
+
+        MergeLayerDown::new(pasted).execute(&mut canvas);
+        let layer = canvas.layer(background).unwrap();
+        assert_eq!(layer.blend_mode(), BlendMode::Normal);
+        assert_eq!(canvas.merged().get_pixel(2, 2), BLACK);
+        assert_eq!(canvas.merged().get_pixel(8, 8), YELLOW);
+    }
+
+    #[test]
+    fn layer_lock_should_works() {
+        let mut canvas = canvas_fixture(16, 16, BLACK);
+        let background = canvas.active_layer();
+
+        SetLayerLock::new(background, LayerLock::Position, true).execute(&mut canvas);
+        MoveLayer::new(background, Vec2::new(4.0, 4.0)).execute(&mut canvas);
+        assert_eq!(canvas.layer(background).unwrap().position(), Point::ZERO);
+
+        SetLayerLock::new(background, LayerLock::Pixels, true).execute(&mut canvas);
+        DrawBrush::new(vec![Vec2::new(8.0, 8.0)], BrushSettings::default(), yellow())
+            .execute(&mut canvas);
+        assert_eq!(canvas.merged().get_pixel(8, 8), BLACK);
+
+        canvas.select(Rect::from_origin_size(Point::ZERO, (4.0, 4.0)));
+        MoveSelection::new(Vec2::new(4.0, 4.0)).execute(&mut canvas);
+        assert_eq!(canvas.merged().get_pixel(2, 2), BLACK);
+        assert_eq!(canvas.layers().len(), 1);
+
+        SetLayerLock::new(background, LayerLock::Pixels, false).execute(&mut canvas);
+        DrawBrush::new(vec![Vec2::new(8.0, 8.0)], BrushSettings::default(), yellow())
+            .execute(&mut canvas);
+        assert_eq!(canvas.merged().get_pixel(8, 8), YELLOW);
     }
 
     #[allow(unused)]
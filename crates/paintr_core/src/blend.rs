@@ -0,0 +1,289 @@
+use druid::Data;
+use image::Rgba;
+use serde::{Deserialize, Serialize};
+
+/// How the colors of a layer are mixed with the layers below it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Data, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BlendMode {
+    Normal,
+    Multiply,
+    Screen,
+    Overlay,
+    Darken,
+    Lighten,
+    ColorDodge,
+    ColorBurn,
+    HardLight,
+    SoftLight,
+    Difference,
+    Exclusion,
+    Hue,
+    Saturation,
+    Color,
+    Luminosity,
+}
+
+impl Default for BlendMode {
+    fn default() -> BlendMode {
+        BlendMode::Normal
+    }
+}
+
+type Rgb = [f64; 3];
+
+impl BlendMode {
+    pub const ALL: [BlendMode; 16] = [
+        BlendMode::Normal,
+        BlendMode::Multiply,
+        BlendMode::Screen,
+        BlendMode::Overlay,
+        BlendMode::Darken,
+        BlendMode::Lighten,
+        BlendMode::ColorDodge,
+        BlendMode::ColorBurn,
+        BlendMode::HardLight,
+        BlendMode::SoftLight,
+        BlendMode::Difference,
+        BlendMode::Exclusion,
+        BlendMode::Hue,
+        BlendMode::Saturation,
+        BlendMode::Color,
+        BlendMode::Luminosity,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            BlendMode::Normal => "Normal",
+            BlendMode::Multiply => "Multiply",
+            BlendMode::Screen => "Screen",
+            BlendMode::Overlay => "Overlay",
+            BlendMode::Darken => "Darken",
+            BlendMode::Lighten => "Lighten",
+            BlendMode::ColorDodge => "Color Dodge",
+            BlendMode::ColorBurn => "Color Burn",
+            BlendMode::HardLight => "Hard Light",
+            BlendMode::SoftLight => "Soft Light",
+            BlendMode::Difference => "Difference",
+            BlendMode::Exclusion => "Exclusion",
+            BlendMode::Hue => "Hue",
+            BlendMode::Saturation => "Saturation",
+            BlendMode::Color => "Color",
+            BlendMode::Luminosity => "Luminosity",
+        }
+    }
+
+    /// Composite `src` over `dest` with this mode and return the result
+    ///
+    /// See https://www.w3.org/TR/compositing-1/#blending
+    pub(crate) fn composite(self, dest: Rgba<u8>, src: Rgba<u8>, opacity: f64) -> Rgba<u8> {
+        let ab = dest.0[3] as f64 / 255.0;
+        let as_ = src.0[3] as f64 / 255.0 * opacity;
+        if as_ <= 0.0 {
+            return dest;
+        }
+
+        let cb = to_rgb(dest);
+        let cs = to_rgb(src);
+        let mixed = self.blend(cb, cs);
+
+        let ao = as_ + ab * (1.0 - as_);
+        let mut out = [0u8; 4];
+        for i in 0..3 {
+            // The blended color only shows where the backdrop is opaque
+            let c = (1.0 - ab) * cs[i] + ab * mixed[i];
+            let co = as_ * c + ab * (1.0 - as_) * cb[i];
+            out[i] = to_u8(co / ao);
+        }
+        out[3] = to_u8(ao);
+
+        Rgba(out)
+    }
+
+    fn blend(self, cb: Rgb, cs: Rgb) -> Rgb {
+        let separable =
+            |f: fn(f64, f64) -> f64| [f(cb[0], cs[0]), f(cb[1], cs[1]), f(cb[2], cs[2])];
+
+        match self {
+            BlendMode::Normal => cs,
+            BlendMode::Multiply => separable(multiply),
+            BlendMode::Screen => separable(screen),
+            BlendMode::Overlay => separable(|b, s| hard_light(s, b)),
+            BlendMode::Darken => separable(f64::min),
+            BlendMode::Lighten => separable(f64::max),
+            BlendMode::ColorDodge => separable(color_dodge),
+            BlendMode::ColorBurn => separable(color_burn),
+            BlendMode::HardLight => separable(hard_light),
+            BlendMode::SoftLight => separable(soft_light),
+            BlendMode::Difference => separable(|b, s| (b - s).abs()),
+            BlendMode::Exclusion => separable(|b, s| b + s - 2.0 * b * s),
+            BlendMode::Hue => set_lum(set_sat(cs, sat(cb)), lum(cb)),
+            BlendMode::Saturation => set_lum(set_sat(cb, sat(cs)), lum(cb)),
+            BlendMode::Color => set_lum(cs, lum(cb)),
+            BlendMode::Luminosity => set_lum(cb, lum(cs)),
+        }
+    }
+}
+
+fn to_rgb(p: Rgba<u8>) -> Rgb {
+    [p.0[0] as f64 / 255.0, p.0[1] as f64 / 255.0, p.0[2] as f64 / 255.0]
+}
+
+fn to_u8(c: f64) -> u8 {
+    (c.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+fn multiply(cb: f64, cs: f64) -> f64 {
+    cb * cs
+}
+
+fn screen(cb: f64, cs: f64) -> f64 {
+    cb + cs - cb * cs
+}
+
+fn hard_light(cb: f64, cs: f64) -> f64 {
+    if cs <= 0.5 {
+        multiply(cb, 2.0 * cs)
+    } else {
+        screen(cb, 2.0 * cs - 1.0)
+    }
+}
+
+fn color_dodge(cb: f64, cs: f64) -> f64 {
+    if cb <= 0.0 {
+        0.0
+    } else if cs >= 1.0 {
+        1.0
+    } else {
+        (cb / (1.0 - cs)).min(1.0)
+    }
+}
+
+fn color_burn(cb: f64, cs: f64) -> f64 {
+    if cb >= 1.0 {
+        1.0
+    } else if cs <= 0.0 {
+        0.0
+    } else {
+        1.0 - ((1.0 - cb) / cs).min(1.0)
+    }
+}
+
+fn soft_light(cb: f64, cs: f64) -> f64 {
+    if cs <= 0.5 {
+        cb - (1.0 - 2.0 * cs) * cb * (1.0 - cb)
+    } else {
+        let d = if cb <= 0.25 { ((16.0 * cb - 12.0) * cb + 4.0) * cb } else { cb.sqrt() };
+        cb + (2.0 * cs - 1.0) * (d - cb)
+    }
+}
+
+fn lum(c: Rgb) -> f64 {
+    0.3 * c[0] + 0.59 * c[1] + 0.11 * c[2]
+}
+
+fn clip_color(c: Rgb) -> Rgb {
+    let l = lum(c);
+    let n = c[0].min(c[1]).min(c[2]);
+    let x = c[0].max(c[1]).max(c[2]);
+
+    let mut c = c;
+    for it in c.iter_mut() {
+        if n < 0.0 {
+            *it = l + (*it - l) * l / (l - n);
+        }
+        if x > 1.0 {
+            *it = l + (*it - l) * (1.0 - l) / (x - l);
+        }
+    }
+    c
+}
+
+fn set_lum(c: Rgb, l: f64) -> Rgb {
+    let d = l - lum(c);
+    clip_color([c[0] + d, c[1] + d, c[2] + d])
+}
+
+fn sat(c: Rgb) -> f64 {
+    c[0].max(c[1]).max(c[2]) - c[0].min(c[1]).min(c[2])
+}
+
+fn set_sat(c: Rgb, s: f64) -> Rgb {
+    let mut idx = [0, 1, 2];
+    idx.sort_by(|a, b| c[*a].partial_cmp(&c[*b]).unwrap());
+    let (min, mid, max) = (idx[0], idx[1], idx[2]);
+
+    let mut out = [0.0; 3];
+    if c[max] > c[min] {
+        out[mid] = (c[mid] - c[min]) * s / (c[max] - c[min]);
+        out[max] = s;
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn rgb(r: u8, g: u8, b: u8) -> Rgba<u8> {
+        Rgba([r, g, b, 0xff])
+    }
+
+    #[test]
+    fn separable_modes_should_works() {
+        let dest = rgb(0x80, 0x40, 0xff);
+        let src = rgb(0x40, 0xc0, 0x00);
+
+        let expected = [
+            (BlendMode::Normal, rgb(0x40, 0xc0, 0x00)),
+            (BlendMode::Multiply, rgb(0x20, 0x30, 0x00)),
+            (BlendMode::Screen, rgb(0xa0, 0xd0, 0xff)),
+            (BlendMode::Overlay, rgb(0x41, 0x60, 0xff)),
+            (BlendMode::Darken, rgb(0x40, 0x40, 0x00)),
+            (BlendMode::Lighten, rgb(0x80, 0xc0, 0xff)),
+            (BlendMode::ColorDodge, rgb(0xab, 0xff, 0xff)),
+            (BlendMode::ColorBurn, rgb(0x00, 0x01, 0xff)),
+            (BlendMode::HardLight, rgb(0x40, 0xa1, 0x00)),
+            (BlendMode::SoftLight, rgb(0x60, 0x60, 0xff)),
+            (BlendMode::Difference, rgb(0x40, 0x80, 0xff)),
+            (BlendMode::Exclusion, rgb(0x80, 0xa0, 0xff)),
+        ];
+
+        for (mode, color) in expected.iter() {
+            assert_eq!(mode.composite(dest, src, 1.0), *color, "{}", mode.name());
+        }
+    }
+
+    #[test]
+    fn non_separable_modes_should_keep_luminosity() {
+        let dest = rgb(0x80, 0x80, 0x80);
+        let src = rgb(0xff, 0x00, 0x00);
+
+        // Gray has no hue or saturation, so only the luminosity of the backdrop stays
+        assert_eq!(BlendMode::Saturation.composite(dest, src, 1.0), dest);
+        assert_eq!(BlendMode::Luminosity.composite(dest, src, 1.0), rgb(0x4d, 0x4d, 0x4d));
+
+        let color = BlendMode::Color.composite(dest, src, 1.0);
+        assert!(color.0[0] > color.0[1] && color.0[1] == color.0[2]);
+        assert_eq!((lum(to_rgb(color)) * 255.0).round() as u8, 0x80);
+        assert_eq!(BlendMode::Hue.composite(dest, src, 1.0), dest);
+    }
+
+    #[test]
+    fn composite_should_respect_alpha() {
+        let dest = rgb(0x80, 0x40, 0xff);
+        let src = Rgba([0x40, 0xc0, 0x00, 0x00]);
+
+        for mode in BlendMode::ALL.iter() {
+            assert_eq!(mode.composite(dest, src, 1.0), dest, "{}", mode.name());
+            assert_eq!(mode.composite(dest, rgb(0, 0, 0), 0.0), dest, "{}", mode.name());
+        }
+
+        // The source is used as is over a transparent backdrop
+        let transparent = Rgba([0, 0, 0, 0]);
+        for mode in BlendMode::ALL.iter() {
+            assert_eq!(mode.composite(transparent, src, 1.0), transparent, "{}", mode.name());
+            assert_eq!(mode.composite(transparent, dest, 1.0), dest, "{}", mode.name());
+        }
+    }
+}
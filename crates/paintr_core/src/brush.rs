@@ -0,0 +1,225 @@
+use druid::{Data, Lens, Rect, Vec2};
+use image::{DynamicImage, GenericImage, GenericImageView, Rgba};
+use std::sync::Arc;
+
+use crate::BlendMode;
+
+/// Settings of a round brush
+#[derive(Debug, Clone, Copy, PartialEq, Data, Lens)]
+pub struct BrushSettings {
+    /// Diameter of a dab, in pixels
+    pub diameter: f64,
+    /// Sharpness of the edge of a dab, from 0.0 (soft) to 1.0 (hard)
+    pub hardness: f64,
+    /// Distance between dabs, in percent of the diameter
+    pub spacing: f64,
+    /// Alpha of a single dab, from 0.0 to 1.0
+    pub flow: f64,
+    /// Maximum alpha of a stroke, from 0.0 to 1.0
+    pub opacity: f64,
+}
+
+impl Default for BrushSettings {
+    fn default() -> BrushSettings {
+        BrushSettings { diameter: 10.0, hardness: 1.0, spacing: 25.0, flow: 1.0, opacity: 1.0 }
+    }
+}
+
+impl BrushSettings {
+    fn radius(&self) -> f64 {
+        self.diameter.max(1.0) / 2.0
+    }
+
+    fn step(&self) -> f64 {
+        (self.diameter * self.spacing / 100.0).max(1.0)
+    }
+
+    /// Alpha of a dab at the distance `dist` from its center
+    fn falloff(&self, dist: f64) -> f64 {
+        let r = self.radius();
+        // Keep at least one pixel of falloff for anti-aliasing
+        let edge = (r * (1.0 - self.hardness.clamp(0.0, 1.0))).max(1.0).min(r);
+        ((r - dist) / edge).clamp(0.0, 1.0)
+    }
+}
+
+/// A stroke in progress
+///
+/// Dabs are accumulated in a mask and composited over the image before the stroke,
+/// such that overlapping dabs never exceed the opacity of the brush.
+/// It is created once per stroke and owned by the plane it draws on.
+pub(crate) struct Stroke {
+    brush: BrushSettings,
+    color: Rgba<u8>,
+    base: Arc<DynamicImage>,
+    mask: Vec<f32>,
+    last: Option<Vec2>,
+    /// Distance along the path since the last dab
+    travelled: f64,
+    /// Bounds of the pixels changed since the last `take_dirty`
+    dirty: Option<Rect>,
+}
+
+impl Stroke {
+    pub(crate) fn new(base: Arc<DynamicImage>, brush: BrushSettings, color: Rgba<u8>) -> Stroke {
+        let mask = vec![0.0; (base.width() * base.height()) as usize];
+        Stroke { brush, color, base, mask, last: None, travelled: 0.0, dirty: None }
+    }
+
+    /// Whether the stroke is drawn with `brush` and `color`
+    pub(crate) fn is_drawn_by(&self, brush: &BrushSettings, color: Rgba<u8>) -> bool {
+        self.brush == *brush && self.color == color
+    }
+
+    /// A new stroke with the same brush and color, and the image before the stroke
+    pub(crate) fn restart(&self) -> (Stroke, DynamicImage) {
+        let img = self.base.as_ref().clone();
+        (Stroke::new(self.base.clone(), self.brush, self.color), img)
+    }
+
+    /// Bounds of the pixels changed since the last call, in image coordinates
+    pub(crate) fn take_dirty(&mut self) -> Option<Rect> {
+        self.dirty.take()
+    }
+
+    /// Continue the stroke to `pos`, placing dabs along the way
+    pub(crate) fn stroke_to(&mut self, img: &mut DynamicImage, pos: Vec2) {
+        let last = match self.last.replace(pos) {
+            Some(it) => it,
+            None => {
+                self.dab(img, pos);
+                return;
+            }
+        };
+
+        let step = self.brush.step();
+        let delta = pos - last;
+        let dist = delta.hypot();
+
+        let mut t = step - self.travelled;
+        while t <= dist {
+            self.dab(img, last + delta * (t / dist));
+            t += step;
+        }
+        self.travelled = dist - (t - step);
+    }
+
+    fn dab(&mut self, img: &mut DynamicImage, center: Vec2) {
+        let (w, h) = img.dimensions();
+        let r = self.brush.radius();
+        let clip = |v: f64, max: u32| (v.max(0.0) as u32).min(max);
+        let (x0, x1) = (clip((center.x - r).floor(), w), clip((center.x + r).ceil(), w));
+        let (y0, y1) = (clip((center.y - r).floor(), h), clip((center.y + r).ceil(), h));
+
+        let flow = self.brush.flow.clamp(0.0, 1.0) as f32;
+        let opacity = self.brush.opacity.clamp(0.0, 1.0);
+        if x0 < x1 && y0 < y1 {
+            let rt = Rect::new(x0 as f64, y0 as f64, x1 as f64, y1 as f64);
+            self.dirty = Some(self.dirty.map_or(rt, |it| it.union(rt)));
+        }
+
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let dist = (Vec2::new(x as f64 + 0.5, y as f64 + 0.5) - center).hypot();
+                let alpha = self.brush.falloff(dist) as f32 * flow;
+                if alpha <= 0.0 {
+                    continue;
+                }
+
+                let m = &mut self.mask[(y * w + x) as usize];
+                *m += alpha * (1.0 - *m);
+
+                let base = self.base.get_pixel(x, y);
+                let alpha = *m as f64 * opacity;
+                img.put_pixel(x, y, BlendMode::Normal.composite(base, self.color, alpha));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::image_utils::{colors::*, make_color_img};
+
+    fn stroke(brush: BrushSettings, pts: &[Vec2]) -> DynamicImage {
+        let mut img = make_color_img(32, 32, TRANSPARENT);
+        let mut stroke = Stroke::new(Arc::new(img.clone()), brush, BLACK);
+        for p in pts {
+            stroke.stroke_to(&mut img, *p);
+        }
+        img
+    }
+
+    #[test]
+    fn stroke_should_interpolate_between_samples() {
+        let img = stroke(BrushSettings::default(), &[Vec2::new(4.0, 16.0), Vec2::new(28.0, 16.0)]);
+
+        for x in 4..28 {
+            assert_eq!(img.get_pixel(x, 16), BLACK, "x = {}", x);
+        }
+        assert_eq!(img.get_pixel(16, 4), TRANSPARENT);
+    }
+
+    #[test]
+    fn stroke_should_track_changed_pixels() {
+        let mut img = make_color_img(32, 32, TRANSPARENT);
+        let mut stroke = Stroke::new(Arc::new(img.clone()), BrushSettings::default(), BLACK);
+        assert_eq!(stroke.take_dirty(), None);
+
+        stroke.stroke_to(&mut img, Vec2::new(8.0, 8.0));
+        assert_eq!(stroke.take_dirty(), Some(Rect::new(3.0, 3.0, 13.0, 13.0)));
+        assert_eq!(stroke.take_dirty(), None);
+
+        stroke.stroke_to(&mut img, Vec2::new(8.0, 16.0));
+        assert_eq!(stroke.take_dirty(), Some(Rect::new(3.0, 5.0, 13.0, 21.0)));
+    }
+
+    #[test]
+    fn stroke_should_respect_spacing() {
+        let brush = BrushSettings { diameter: 2.0, spacing: 400.0, ..Default::default() };
+        let img = stroke(brush, &[Vec2::new(4.5, 16.5), Vec2::new(28.5, 16.5)]);
+
+        // Dabs are placed every 8 pixels
+        assert_eq!(img.get_pixel(4, 16), BLACK);
+        assert_eq!(img.get_pixel(8, 16), TRANSPARENT);
+        assert_eq!(img.get_pixel(12, 16), BLACK);
+        assert_eq!(img.get_pixel(20, 16), BLACK);
+    }
+
+    #[test]
+    fn stroke_should_carry_spacing_between_samples() {
+        let brush = BrushSettings { diameter: 2.0, spacing: 400.0, ..Default::default() };
+        let pts: Vec<_> = (4..=28).map(|x| Vec2::new(x as f64 + 0.5, 16.5)).collect();
+
+        let img = stroke(brush, &pts);
+        assert_eq!(img.get_pixel(12, 16), BLACK);
+        assert_eq!(img.get_pixel(8, 16), TRANSPARENT);
+    }
+
+    #[test]
+    fn soft_brush_should_fade_out() {
+        let brush = BrushSettings { diameter: 16.0, hardness: 0.0, ..Default::default() };
+        let img = stroke(brush, &[Vec2::new(16.0, 16.0)]);
+
+        let center = img.get_pixel(16, 16).0[3];
+        let middle = img.get_pixel(20, 16).0[3];
+        let edge = img.get_pixel(23, 16).0[3];
+        assert!(center > middle && middle > edge && edge > 0, "{} {} {}", center, middle, edge);
+        assert_eq!(img.get_pixel(25, 16), TRANSPARENT);
+    }
+
+    #[test]
+    fn stroke_should_not_exceed_opacity() {
+        let brush = BrushSettings { opacity: 0.5, ..Default::default() };
+        let img =
+            stroke(brush, &[Vec2::new(16.0, 16.0), Vec2::new(17.0, 16.0), Vec2::new(16.0, 16.0)]);
+        assert_eq!(img.get_pixel(16, 16).0[3], 0x80);
+
+        let brush = BrushSettings { flow: 0.5, ..Default::default() };
+        let once = stroke(brush, &[Vec2::new(16.0, 16.0)]);
+        let twice = stroke(brush, &[Vec2::new(16.0, 16.0), Vec2::new(19.0, 16.0)]);
+        assert_eq!(once.get_pixel(16, 16).0[3], 0x80);
+        assert!(twice.get_pixel(16, 16).0[3] > 0x80);
+    }
+}
@@ -1,40 +1,127 @@
-use druid::{kurbo::Affine, PaintCtx};
-use druid::{Data, Point, RenderContext, Size, Vec2};
+use druid::PaintCtx;
+use druid::{Color, Data, Point, Rect, Size, Vec2};
 
-use crate::plane::{PlaneIndex, Planes};
+use crate::delta::ImageDelta;
+use crate::layer::{Layer, LayerId, LayerLock, Layers};
+use crate::{document, ora, psd};
 use crate::{image_utils, plane::Plane};
-use crate::{Paintable, Selection};
-use anyhow::Result;
-use std::sync::Arc;
+use crate::{BlendMode, BrushSettings, Diff, MagicWandSettings, Mask, Paintable, Selection};
+use crate::{ExportFormat, ExportOptions};
+use crate::{SelectionMode, SelectionModify};
+use anyhow::{anyhow, Result};
+use image::Rgba;
+use std::io::{BufWriter, Write};
+use std::sync::{Arc, Mutex};
 
 #[derive(Debug, Data, Clone)]
 enum SelectionBinder {
     Unbind(Selection),
-    Bind(Selection, PlaneIndex),
+    Bind(Selection, LayerId),
+}
+
+/// The last composited image, and the layers it was composited from
+#[derive(Clone)]
+struct Composite {
+    layers: Layers,
+    transform: Vec2,
+    size: Size,
+    img: Arc<image::DynamicImage>,
+}
+
+/// Cache of `CanvasData::merged`, each clone of a canvas has its own
+#[derive(Default)]
+struct CompositeCache(Mutex<Option<Composite>>);
+
+impl CompositeCache {
+    fn lock(&self) -> std::sync::MutexGuard<'_, Option<Composite>> {
+        self.0.lock().unwrap_or_else(|it| it.into_inner())
+    }
+}
+
+impl Clone for CompositeCache {
+    /// The clone starts from the last composited image, which is copied before it is updated
+    fn clone(&self) -> Self {
+        CompositeCache(Mutex::new(self.lock().clone()))
+    }
+}
+
+impl std::fmt::Debug for CompositeCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "CompositeCache")
+    }
+}
+
+impl Data for CompositeCache {
+    fn same(&self, _other: &Self) -> bool {
+        true
+    }
 }
 
-// FIXME: Change name to Layer
 #[derive(Debug, Data, Clone)]
 pub struct CanvasData {
     path: Arc<std::path::PathBuf>,
+    /// The options of the flat image format the canvas was opened from or last saved to
+    save_options: Option<ExportOptions>,
     selection: Option<SelectionBinder>,
-    planes: Planes,
+    layers: Layers,
     transform: Vec2,
     size: Size,
+    composite: CompositeCache,
 }
 
 impl CanvasData {
     pub fn new(path: impl Into<std::path::PathBuf>, img: image::DynamicImage) -> CanvasData {
-        let mut planes = Planes::new();
         let size = img.paint_size().unwrap();
-        planes.push(Plane::Image(Arc::new(img)));
+        let layers = Layers::new(Plane::Image(Arc::new(img)));
 
         CanvasData {
             selection: None,
-            planes,
+            layers,
             path: Arc::new(path.into()),
+            save_options: None,
             transform: Vec2::default(),
             size,
+            composite: CompositeCache::default(),
+        }
+    }
+
+    /// Open a document, or an image as a document of one layer
+    ///
+    /// It returns a warning if some content of the file could not be imported.
+    pub fn open(path: impl AsRef<std::path::Path>) -> Result<(CanvasData, Option<String>)> {
+        let path = path.as_ref();
+        if document::is_document(path) {
+            let reader = std::io::BufReader::new(std::fs::File::open(path)?);
+            return Ok((document::read(path, reader)?, None));
+        }
+        if ora::is_ora(path) {
+            let reader = std::io::BufReader::new(std::fs::File::open(path)?);
+            return Ok((ora::read(path, reader)?, None));
+        }
+        if psd::is_psd(path) {
+            return psd::read(path, &std::fs::read(path)?);
+        }
+
+        let img = image::open(path)?;
+        let mut canvas = CanvasData::new(path, image::DynamicImage::ImageRgba8(img.into_rgba8()));
+        canvas.save_options = ExportFormat::from_path(path).map(ExportOptions::new);
+        Ok((canvas, None))
+    }
+
+    pub(crate) fn from_layers(
+        path: impl Into<std::path::PathBuf>,
+        layers: Layers,
+        size: Size,
+        transform: Vec2,
+    ) -> CanvasData {
+        CanvasData {
+            path: Arc::new(path.into()),
+            save_options: None,
+            selection: None,
+            layers,
+            transform,
+            size,
+            composite: CompositeCache::default(),
         }
     }
 
@@ -42,13 +129,49 @@ impl CanvasData {
         self.path.as_ref()
     }
 
+    pub fn set_path(&mut self, path: impl Into<std::path::PathBuf>) {
+        self.path = Arc::new(path.into());
+    }
+
+    /// Whether `save` keeps the canvas at `path`, a flat image only keeps a single layer
+    /// without floating selection
+    pub fn can_save(&self, path: &std::path::Path) -> bool {
+        let flat = self.layers().len() == 1 && self.selection_layer().is_none();
+        document::is_document(path)
+            || ora::is_ora(path)
+            || (flat && ExportFormat::from_path(path).is_some())
+    }
+
+    /// Save as a document, or flatten to an image by the extension of `path`
+    ///
+    /// A flat image of the same format as before is saved with the same options.
     pub fn save(&mut self, path: &std::path::Path) -> Result<()> {
-        let img = self.merged();
-        img.save(path)?;
+        let options = ExportFormat::from_path(path).map(|format| match &self.save_options {
+            Some(it) if it.format == format => it.clone(),
+            _ => ExportOptions::new(format),
+        });
+
+        if document::is_document(path) {
+            write_file(path, |w| document::write(self, w))?;
+        } else if ora::is_ora(path) {
+            write_file(path, |w| ora::write(self, w))?;
+        } else if let Some(options) = &options {
+            self.export(path, options)?;
+        } else {
+            let (format, merged) = (image::ImageFormat::from_path(path)?, self.merged());
+            write_file(path, |w| Ok(merged.write_to(w, format)?))?;
+        }
         self.path = Arc::new(path.into());
+        self.save_options = options;
         Ok(())
     }
 
+    /// Flatten to an image, the path of the canvas is unchanged
+    pub fn export(&self, path: &std::path::Path, options: &ExportOptions) -> Result<()> {
+        let merged = self.merged();
+        write_file(path, |w| options.encode(&merged, w))
+    }
+
     pub fn selection(&self) -> Option<&Selection> {
         match self.selection.as_ref()? {
             SelectionBinder::Unbind(it) => Some(it),
@@ -56,15 +179,68 @@ impl CanvasData {
         }
     }
 
+    /// The layer which the selection is cut out to, if any
+    pub fn selection_layer(&self) -> Option<LayerId> {
+        match self.selection.as_ref()? {
+            SelectionBinder::Unbind(_) => None,
+            SelectionBinder::Bind(_, id) => Some(*id),
+        }
+    }
+
+    /// The composited image of the visible layers
+    ///
+    /// It is cached, and only the pixels changed by a stroke are recomposited.
     pub fn merged(&self) -> Arc<image::DynamicImage> {
+        let mut cache = self.composite.lock();
+        let cached =
+            cache.as_mut().filter(|it| it.transform == self.transform && it.size == self.size);
+        if let Some(it) = cached {
+            if it.layers == self.layers {
+                return it.img.clone();
+            }
+            if let Some(rt) = self.layers.changed_since(&it.layers) {
+                if rt.area() > 0.0 {
+                    let img = Arc::make_mut(&mut it.img);
+                    self.layers.merge_region(img, self.transform, rt + self.transform);
+                }
+                it.layers = self.layers.clone();
+                return it.img.clone();
+            }
+        }
+
+        let img = self.composite();
+        let layers = self.layers.clone();
+        let (transform, size) = (self.transform, self.size);
+        *cache = Some(Composite { layers, transform, size, img: img.clone() });
+        img
+    }
+
+    fn composite(&self) -> Arc<image::DynamicImage> {
         if self.transform == Vec2::ZERO {
-            let img = self.planes.merged().expect("There is at least plane in Canvas");
+            let img = self.layers.merged().expect("There is at least one layer in Canvas");
             return img;
         }
         // Create partial image based on offset and size
         let output =
             image_utils::transparent_image(self.size.width as u32, self.size.height as u32);
-        self.planes.merged_to(output, self.transform)
+        self.layers.merged_to(output, self.transform)
+    }
+
+    /// All layers, from bottom to top
+    pub fn layers(&self) -> &[Layer] {
+        self.layers.layers()
+    }
+
+    pub fn layer(&self, id: LayerId) -> Option<&Layer> {
+        self.layers.get(id)
+    }
+
+    pub fn active_layer(&self) -> LayerId {
+        self.layers.active()
+    }
+
+    pub fn set_active_layer(&mut self, id: LayerId) -> bool {
+        self.layers.set_active(id)
     }
 
     pub fn select(&mut self, sel: impl Into<Selection>) {
@@ -76,14 +252,142 @@ impl CanvasData {
         }
     }
 
+    /// Restore the selection, which could be bound to a floating layer
+    pub(crate) fn restore_selection(&mut self, sel: Selection, layer: Option<LayerId>) {
+        self.selection = Some(match layer.filter(|id| self.layers.get(*id).is_some()) {
+            Some(id) => SelectionBinder::Bind(sel, id),
+            None => SelectionBinder::Unbind(sel),
+        });
+    }
+
+    pub(crate) fn select_all(&mut self) {
+        self.select(Rect::from_origin_size(Point::ORIGIN, self.size));
+    }
+
+    /// Move the selection, and unbind it from the floating layer
+    pub(crate) fn move_selection_outline(&mut self, offset: Vec2) {
+        if let Some(sel) = self.selection() {
+            self.selection = Some(SelectionBinder::Unbind(sel.transform(offset)));
+        }
+    }
+
+    pub(crate) fn deselect(&mut self) {
+        self.selection = None;
+    }
+
+    /// Select the pixels of the canvas outside the selection, or all without a selection
+    pub(crate) fn invert_selection(&mut self) {
+        let all = Selection::from(Rect::from_origin_size(Point::ORIGIN, self.size));
+        match self.selection().cloned() {
+            Some(sel) => self.select(SelectionMode::Subtract.combine(Some(&all), sel)),
+            None => self.select(all),
+        }
+    }
+
+    pub(crate) fn modify_selection(&mut self, modify: SelectionModify) {
+        if let Some(sel) = self.selection() {
+            let sel = sel.modify(modify);
+            self.select(sel);
+        }
+    }
+
+    /// The image to sample colors from, with its offset in the canvas
+    fn sample(&self, merged: bool) -> Option<(Arc<image::DynamicImage>, Vec2)> {
+        if merged {
+            return Some((self.merged(), Vec2::ZERO));
+        }
+        let layer = self.layer(self.active_layer())?;
+        Some((layer.image(), layer.position().to_vec2() + self.transform))
+    }
+
+    /// The pixels similar to the one at `pt`
+    pub fn magic_wand(&self, pt: Point, settings: &MagicWandSettings) -> Option<Selection> {
+        let (img, offset) = self.sample(settings.sample_merged)?;
+        let mask = Mask::magic_wand(&img, pt - offset, settings);
+        Some(Selection::from(mask).transform(offset))
+    }
+
+    /// The pixels by their similarity to `color`
+    pub fn color_range(&self, color: &Color, fuzziness: f64, merged: bool) -> Option<Selection> {
+        let (img, offset) = self.sample(merged)?;
+        let mask = Mask::color_range(&img, image_utils::color_to_rgba(color), fuzziness);
+        Some(Selection::from(mask).transform(offset))
+    }
+
     pub(crate) fn paste(&mut self, img: Arc<image::DynamicImage>) {
-        let idx = self.planes.push(Plane::Image(img));
+        let name = Some("Pasted Layer".to_string());
+        self.layers.insert(name, Plane::Image(img), -self.transform);
+    }
+
+    pub(crate) fn insert_layer(&mut self) -> LayerId {
+        let img = image_utils::transparent_image(self.size.width as u32, self.size.height as u32);
+        self.layers.insert(None, Plane::Image(Arc::new(img)), -self.transform)
+    }
+
+    pub(crate) fn delete_layer(&mut self, id: LayerId) -> bool {
+        if !self.layers.remove(id) {
+            return false;
+        }
+
+        if let Some(SelectionBinder::Bind(sel, idx)) = &self.selection {
+            if *idx == id {
+                self.selection = Some(SelectionBinder::Unbind(sel.clone()));
+            }
+        }
+        true
+    }
+
+    pub(crate) fn reorder_layer(&mut self, id: LayerId, index: usize) -> bool {
+        self.layers.reorder(id, index)
+    }
+
+    pub(crate) fn duplicate_layer(&mut self, id: LayerId) -> Option<LayerId> {
+        self.layers.duplicate(id)
+    }
 
-        // FIXME: we don't need to mov the pasted image if we are using layer.
-        self.planes.move_with_index(idx, -self.transform);
+    pub(crate) fn merge_layer_down(&mut self, id: LayerId) -> Option<LayerId> {
+        let merged = self.layers.merge_down(id)?;
+
+        if let Some(SelectionBinder::Bind(sel, idx)) = &self.selection {
+            if *idx == id {
+                self.selection = Some(SelectionBinder::Unbind(sel.clone()));
+            }
+        }
+        Some(merged)
+    }
+
+    pub(crate) fn rename_layer(&mut self, id: LayerId, name: &str) -> bool {
+        self.layers.rename(id, name)
+    }
+
+    pub(crate) fn set_layer_opacity(&mut self, id: LayerId, opacity: f64) -> bool {
+        self.layers.set_opacity(id, opacity)
+    }
+
+    pub(crate) fn set_layer_blend_mode(&mut self, id: LayerId, mode: BlendMode) -> bool {
+        self.layers.set_blend_mode(id, mode)
+    }
+
+    pub(crate) fn set_layer_visible(&mut self, id: LayerId, visible: bool) -> bool {
+        self.layers.set_visible(id, visible)
+    }
+
+    pub(crate) fn set_layer_lock(&mut self, id: LayerId, lock: LayerLock, locked: bool) -> bool {
+        self.layers.set_lock(id, lock, locked)
+    }
+
+    pub(crate) fn move_layer(&mut self, id: LayerId, offset: Vec2) {
+        if !self.layers.move_layer(id, offset) {
+            return;
+        }
+
+        if let Some(SelectionBinder::Bind(sel, idx)) = &self.selection {
+            if *idx == id {
+                self.selection = Some(SelectionBinder::Bind(sel.transform(offset), id));
+            }
+        }
     }
 
-    //FIXME: should be move layer, when we implemented layer
     pub(crate) fn move_canvas(&mut self, offset: Vec2) {
         self.transform += offset;
 
@@ -101,8 +405,20 @@ impl CanvasData {
         }
     }
 
-    pub(crate) fn draw_with_brush(&mut self, pos: &Vec<Vec2>) {
-        self.planes.draw_with_brush(pos);
+    pub(crate) fn fill(&mut self, color: Rgba<u8>) -> bool {
+        let sel = self.selection().map(|it| it.transform(-self.transform));
+        self.layers.fill(sel.as_ref(), color)
+    }
+
+    pub(crate) fn draw_with_brush(
+        &mut self,
+        pos: &[Vec2],
+        brush: &BrushSettings,
+        color: Rgba<u8>,
+        new_stroke: bool,
+    ) {
+        let pos: Vec<_> = pos.iter().map(|p| *p - self.transform).collect();
+        self.layers.draw_with_brush(&pos, brush, color, new_stroke);
     }
 
     pub(crate) fn move_selection(&mut self, offset: Vec2) {
@@ -111,14 +427,18 @@ impl CanvasData {
                 SelectionBinder::Unbind(it) => {
                     // Bind the selection
                     let sel = it.transform(-self.transform);
-                    (it, self.planes.bind_selection(&sel))
+                    match self.layers.bind_selection(&sel) {
+                        Some(index) => (it, index),
+                        None => return,
+                    }
                 }
                 SelectionBinder::Bind(it, index) => (it, *index),
             };
 
-            let sel = selection.transform(offset);
-            self.planes.move_with_index(index, offset);
-            self.selection = Some(SelectionBinder::Bind(sel, index));
+            if self.layers.move_layer(index, offset) {
+                let sel = selection.transform(offset);
+                self.selection = Some(SelectionBinder::Bind(sel, index));
+            }
         }
     }
 
@@ -129,10 +449,9 @@ impl CanvasData {
 
 impl Paintable for CanvasData {
     fn paint(&self, paint_ctx: &mut PaintCtx) {
-        paint_ctx.with_save(|ctx| {
-            ctx.transform(Affine::translate(self.transform));
-            self.planes.paint(ctx);
-        });
+        // Paint the composited image, such that opacity and hidden layers look
+        // the same as the saved image.
+        self.merged().paint(paint_ctx);
     }
 
     fn paint_size(&self) -> Option<Size> {
@@ -140,12 +459,133 @@ impl Paintable for CanvasData {
     }
 }
 
+/// The old canvas, with the old pixels of changed layers kept as `ImageDelta`
+#[derive(Debug)]
+pub struct CanvasDelta {
+    canvas: CanvasData,
+    patches: Vec<(LayerId, ImageDelta)>,
+    size: usize,
+}
+
+impl Diff for CanvasData {
+    type Delta = CanvasDelta;
+
+    fn diff(old: &CanvasData, new: &CanvasData) -> CanvasDelta {
+        let mut canvas = old.clone();
+        let (mut patches, mut size) = (Vec::new(), 0);
+
+        for layer in old.layers.layers() {
+            let (id, img) = (layer.id(), layer.image());
+            let shared = new.layers.layers().iter().any(|it| Arc::ptr_eq(&it.image(), &img));
+            let current = new.layers.get(id).map(|it| it.image());
+
+            let img = match current {
+                _ if shared => img,
+                Some(current) if ImageDelta::can_diff(&img, &current) => {
+                    let delta = ImageDelta::new(&img, &current);
+                    size += delta.size();
+                    patches.push((id, delta));
+                    // Filled by `patch` from the current image
+                    Arc::new(image_utils::transparent_image(0, 0))
+                }
+                _ => {
+                    size += img.as_bytes().len();
+                    img
+                }
+            };
+            canvas.layers.set_image(id, img);
+        }
+
+        CanvasDelta { canvas, patches, size }
+    }
+
+    fn patch(&self, delta: &CanvasDelta) -> CanvasData {
+        let mut canvas = delta.canvas.clone();
+        for (id, it) in &delta.patches {
+            // Only the changed pixels can be restored without the current image of the layer
+            let current = self.layers.get(*id).map(|it| it.image());
+            canvas.layers.set_image(*id, Arc::new(it.apply(current.as_deref())));
+        }
+        canvas
+    }
+
+    fn delta_size(delta: &CanvasDelta) -> usize {
+        delta.size
+    }
+
+    fn data_size(&self) -> usize {
+        self.layers.data_size()
+    }
+}
+
+/// Write to a temporary file next to `path` and rename it, such that a failure leaves `path`
+/// unchanged
+fn write_file(
+    path: &std::path::Path,
+    write: impl FnOnce(&mut BufWriter<std::fs::File>) -> Result<()>,
+) -> Result<()> {
+    let name = path.file_name().ok_or_else(|| anyhow!("Invalid file name {}", path.display()))?;
+    let mut tmp = std::ffi::OsString::from(".");
+    tmp.push(name);
+    tmp.push(".tmp");
+    let tmp = path.with_file_name(tmp);
+
+    let result = std::fs::File::create(&tmp).map_err(Into::into).and_then(|file| {
+        let mut writer = BufWriter::new(file);
+        write(&mut writer)?;
+        writer.flush()?;
+        writer.get_ref().sync_all()?;
+        Ok(std::fs::rename(&tmp, path)?)
+    });
+    if result.is_err() {
+        let _ = std::fs::remove_file(&tmp);
+    }
+    result
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
     use crate::image_utils::{colors::*, make_color_img};
     use crate::test_utils::canvas_fixture;
-    use image::GenericImageView;
+    use image::{GenericImageView, Rgba};
+
+    #[test]
+    fn canvas_data_merged_should_update_changed_region() {
+        let mut canvas = canvas_fixture(32, 32, WHITE);
+        let layer = canvas.insert_layer();
+        canvas.set_layer_opacity(layer, 0.5);
+        canvas.move_layer(layer, Vec2::new(3.0, 2.0));
+        let brush = BrushSettings { hardness: 0.5, ..Default::default() };
+        let color = Rgba([0xFF, 0, 0, 0xFF]);
+
+        let before = canvas.merged();
+        let pts = [Vec2::new(4.0, 4.0), Vec2::new(12.0, 20.0), Vec2::new(28.0, 10.0)];
+        for (i, p) in pts.iter().enumerate() {
+            canvas.draw_with_brush(&[*p], &brush, color, i == 0);
+            assert_eq!(canvas.merged().to_bytes(), canvas.composite().to_bytes());
+        }
+        assert_eq!(before.get_pixel(12, 20), WHITE);
+        assert_ne!(canvas.merged().get_pixel(12, 20), WHITE);
+    }
+
+    #[test]
+    fn canvas_data_merged_should_keep_cache_of_clones_apart() {
+        let mut canvas = canvas_fixture(32, 32, WHITE);
+        let brush = BrushSettings::default();
+        canvas.draw_with_brush(&[Vec2::new(4.0, 4.0)], &brush, BLACK, true);
+        let merged = canvas.merged();
+
+        let mut copy = canvas.clone();
+        copy.move_canvas(Vec2::new(2.0, 0.0));
+        copy.merged();
+        assert!(Arc::ptr_eq(&canvas.merged(), &merged));
+
+        canvas.draw_with_brush(&[Vec2::new(20.0, 20.0)], &brush, BLACK, false);
+        assert_eq!(canvas.merged().to_bytes(), canvas.composite().to_bytes());
+        assert_eq!(copy.merged().to_bytes(), copy.composite().to_bytes());
+        assert_eq!(merged.get_pixel(20, 20), WHITE);
+    }
 
     #[test]
     fn canvas_data_merged_should_works() {
@@ -158,6 +598,26 @@ mod test {
         assert_eq!(merged.get_pixel(8, 8), WHITE);
     }
 
+    #[test]
+    fn canvas_data_magic_wand_should_sample_active_layer() {
+        let mut canvas = canvas_fixture(16, 16, WHITE);
+        canvas.move_canvas(Vec2::new(4.0, 4.0));
+        canvas.paste(Arc::new(make_color_img(4, 4, BLACK)));
+
+        let settings = MagicWandSettings::default();
+        let sel = canvas.magic_wand(Point::new(1.0, 1.0), &settings).unwrap();
+        assert_eq!(sel.area(), 16.0);
+        assert!(sel.contains(Point::new(3.5, 3.5)));
+
+        // Outside of the active layer
+        let sel = canvas.magic_wand(Point::new(8.0, 8.0), &settings).unwrap();
+        assert_eq!(sel.area(), 0.0);
+
+        let settings = MagicWandSettings { sample_merged: true, ..settings };
+        let sel = canvas.magic_wand(Point::new(8.0, 8.0), &settings).unwrap();
+        assert_eq!(sel.area(), 12.0 * 12.0);
+    }
+
     #[test]
     fn canvas_data_merged_should_works_with_moved() {
         let mut canvas = canvas_fixture(16, 16, WHITE);
@@ -171,4 +631,109 @@ mod test {
         assert_eq!(merged.get_pixel(0, 8), TRANSPARENT);
         assert_eq!(merged.get_pixel(8, 0), TRANSPARENT);
     }
+
+    fn blended(backdrop: Rgba<u8>, src: Rgba<u8>, mode: BlendMode) -> Rgba<u8> {
+        let mut canvas = canvas_fixture(16, 16, backdrop);
+        canvas.paste(Arc::new(make_color_img(4, 4, src)));
+        canvas.set_layer_blend_mode(canvas.active_layer(), mode);
+        let merged = canvas.merged();
+
+        assert_eq!(merged.get_pixel(8, 8), backdrop);
+        merged.get_pixel(0, 0)
+    }
+
+    #[test]
+    fn canvas_data_merged_should_works_with_blend_modes() {
+        let gray = Rgba([0x80, 0x80, 0x80, 0xff]);
+        let red = Rgba([0xff, 0x00, 0x00, 0xff]);
+        let white = Rgba([0xff, 0xff, 0xff, 0xff]);
+
+        assert_eq!(blended(WHITE, BLACK, BlendMode::Normal), BLACK);
+        assert_eq!(blended(white, YELLOW, BlendMode::Multiply), YELLOW);
+        assert_eq!(blended(BLACK, YELLOW, BlendMode::Screen), YELLOW);
+        assert_eq!(blended(BLACK, WHITE, BlendMode::Overlay), BLACK);
+        assert_eq!(blended(white, YELLOW, BlendMode::Darken), YELLOW);
+        assert_eq!(blended(WHITE, YELLOW, BlendMode::Lighten), WHITE);
+        assert_eq!(blended(BLACK, YELLOW, BlendMode::ColorDodge), BLACK);
+        assert_eq!(blended(white, YELLOW, BlendMode::ColorBurn), white);
+        assert_eq!(blended(gray, white, BlendMode::HardLight), white);
+        assert_eq!(blended(BLACK, WHITE, BlendMode::SoftLight), BLACK);
+        assert_eq!(blended(YELLOW, YELLOW, BlendMode::Difference), BLACK);
+        assert_eq!(blended(BLACK, YELLOW, BlendMode::Exclusion), YELLOW);
+        assert_eq!(blended(gray, red, BlendMode::Hue), gray);
+        assert_eq!(blended(gray, red, BlendMode::Saturation), gray);
+        // The luminosity of pure red
+        let dark_gray = Rgba([0x4d, 0x4d, 0x4d, 0xff]);
+        assert_eq!(blended(red, gray, BlendMode::Saturation), dark_gray);
+        assert_eq!(blended(gray, red, BlendMode::Luminosity), dark_gray);
+        assert_ne!(blended(gray, red, BlendMode::Color), gray);
+    }
+
+    #[test]
+    fn canvas_data_merged_should_blend_with_opacity() {
+        let mut canvas = canvas_fixture(16, 16, WHITE);
+        canvas.paste(Arc::new(make_color_img(4, 4, BLACK)));
+        let pasted = canvas.active_layer();
+        canvas.set_layer_blend_mode(pasted, BlendMode::Difference);
+        canvas.set_layer_opacity(pasted, 0.5);
+
+        assert_eq!(canvas.merged().get_pixel(0, 0), WHITE);
+
+        canvas.set_layer_blend_mode(pasted, BlendMode::Multiply);
+        let pixel = canvas.merged().get_pixel(0, 0);
+        assert_eq!(pixel.0[3], 0xff);
+        assert_eq!(pixel.0[0], 0x80);
+    }
+
+    #[test]
+    fn canvas_data_diff_should_restore_changed_tiles() {
+        let mut canvas = canvas_fixture(256, 256, WHITE);
+        canvas.select(Rect::new(0.0, 0.0, 10.0, 10.0));
+        let old = canvas.clone();
+        canvas.fill(BLACK);
+
+        let delta = CanvasData::diff(&old, &canvas);
+        assert_eq!(CanvasData::delta_size(&delta), 64 * 64 * 4);
+
+        let restored = canvas.patch(&delta);
+        assert_eq!(restored.merged().as_bytes(), old.merged().as_bytes());
+        assert_eq!(canvas.merged().get_pixel(0, 0), BLACK);
+    }
+
+    #[test]
+    fn canvas_data_should_save_known_formats() {
+        let mut canvas = canvas_fixture(16, 16, WHITE);
+        assert!(canvas.can_save(std::path::Path::new("a.paintr")));
+        assert!(canvas.can_save(std::path::Path::new("a.ora")));
+        assert!(canvas.can_save(std::path::Path::new("a.jpg")));
+        assert!(!canvas.can_save(std::path::Path::new("a.psd")));
+        assert!(!canvas.can_save(std::path::Path::new("Untitled")));
+
+        // A floating selection and layers are lost in a flat image
+        canvas.select(Rect::new(0.0, 0.0, 4.0, 4.0));
+        assert!(canvas.can_save(std::path::Path::new("a.png")));
+        canvas.restore_selection(Rect::new(0.0, 0.0, 4.0, 4.0).into(), Some(canvas.active_layer()));
+        assert!(!canvas.can_save(std::path::Path::new("a.png")));
+        canvas.deselect();
+        canvas.insert_layer();
+        assert!(!canvas.can_save(std::path::Path::new("a.png")));
+        assert!(canvas.can_save(std::path::Path::new("a.ora")));
+    }
+
+    #[test]
+    fn canvas_data_diff_should_restore_layers() {
+        let mut canvas = canvas_fixture(16, 16, WHITE);
+        let background = canvas.active_layer();
+        canvas.insert_layer();
+        let old = canvas.clone();
+
+        // Unchanged and deleted layers are kept as is
+        canvas.delete_layer(canvas.active_layer());
+        let delta = CanvasData::diff(&old, &canvas);
+        assert_eq!(CanvasData::delta_size(&delta), 16 * 16 * 4);
+
+        let restored = canvas.patch(&delta);
+        assert_eq!(restored.layers().len(), 2);
+        assert_eq!(restored.layer(background).unwrap().image().get_pixel(0, 0), WHITE);
+    }
 }
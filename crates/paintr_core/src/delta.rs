@@ -0,0 +1,111 @@
+use crate::image_utils::transparent_image;
+use image::{DynamicImage, GenericImage, GenericImageView};
+
+/// Width and height of a tile compared by `ImageDelta`
+const TILE_SIZE: u32 = 64;
+
+/// The old pixels of the tiles which differ between two images
+///
+/// Both images must have the same dimensions and color type.
+#[derive(Clone)]
+pub(crate) struct ImageDelta {
+    width: u32,
+    height: u32,
+    tiles: Vec<(u32, u32, DynamicImage)>,
+}
+
+impl std::fmt::Debug for ImageDelta {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ImageDelta {{ tiles: {} }}", self.tiles.len())
+    }
+}
+
+impl ImageDelta {
+    pub(crate) fn can_diff(old: &DynamicImage, new: &DynamicImage) -> bool {
+        old.dimensions() == new.dimensions() && old.color() == new.color()
+    }
+
+    pub(crate) fn new(old: &DynamicImage, new: &DynamicImage) -> ImageDelta {
+        debug_assert!(Self::can_diff(old, new));
+
+        let (w, h) = old.dimensions();
+        let (a, b) = (old.as_bytes(), new.as_bytes());
+        let bpp = old.color().bytes_per_pixel() as usize;
+
+        let mut tiles = Vec::new();
+        for y in (0..h).step_by(TILE_SIZE as usize) {
+            let th = TILE_SIZE.min(h - y);
+            for x in (0..w).step_by(TILE_SIZE as usize) {
+                let tw = TILE_SIZE.min(w - x);
+                let changed = (y..y + th).any(|row| {
+                    let start = (row as usize * w as usize + x as usize) * bpp;
+                    let end = start + tw as usize * bpp;
+                    a[start..end] != b[start..end]
+                });
+                if changed {
+                    tiles.push((x, y, old.crop_imm(x, y, tw, th)));
+                }
+            }
+        }
+
+        ImageDelta { width: w, height: h, tiles }
+    }
+
+    /// Whether the delta can be applied to `new`
+    pub(crate) fn fits(&self, new: &DynamicImage) -> bool {
+        new.dimensions() == (self.width, self.height)
+    }
+
+    /// Restore the old image from the new one, or only the changed pixels without it
+    pub(crate) fn apply(&self, new: Option<&DynamicImage>) -> DynamicImage {
+        let mut img = match new.filter(|it| self.fits(it)) {
+            Some(it) => it.clone(),
+            None => transparent_image(self.width, self.height),
+        };
+        for (x, y, tile) in &self.tiles {
+            img.copy_from(tile, *x, *y).expect("Tile must be inside the image");
+        }
+        img
+    }
+
+    pub(crate) fn size(&self) -> usize {
+        self.tiles.iter().map(|(_, _, tile)| tile.as_bytes().len()).sum()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::image_utils::{
+        colors::{BLACK, TRANSPARENT, WHITE},
+        make_color_img,
+    };
+
+    #[test]
+    fn image_delta_should_keep_changed_tiles() {
+        let old = make_color_img(200, 100, WHITE);
+        let mut new = old.clone();
+        new.put_pixel(10, 10, BLACK);
+        new.put_pixel(199, 99, BLACK);
+
+        let delta = ImageDelta::new(&old, &new);
+        assert_eq!(delta.tiles.len(), 2);
+        assert_eq!(delta.size(), (64 * 64 + 8 * 36) * 4);
+
+        let restored = delta.apply(Some(&new));
+        assert_eq!(restored.as_bytes(), old.as_bytes());
+
+        // Only the changed tiles are restored without the new image
+        let restored = delta.apply(None);
+        assert_eq!(restored.dimensions(), (200, 100));
+        assert_eq!(restored.get_pixel(10, 10), WHITE);
+        assert_eq!(restored.get_pixel(100, 10), TRANSPARENT);
+    }
+
+    #[test]
+    fn image_delta_should_be_empty_if_unchanged() {
+        let img = make_color_img(100, 100, WHITE);
+        let delta = ImageDelta::new(&img, &img.clone());
+        assert_eq!(delta.size(), 0);
+    }
+}
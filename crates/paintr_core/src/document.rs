@@ -0,0 +1,386 @@
+//! Native document format
+//!
+//! A `.paintr` document is a zip archive containing:
+//! - `mimetype`: `application/x-paintr`, stored uncompressed as the first entry
+//! - `manifest.json`: the canvas, the properties of every layer and the selection
+//! - `layers/<id>.png`: the pixels of every layer
+//! - `selection.png`: the coverage of a mask selection, if any
+use druid::kurbo::{BezPath, PathEl};
+use druid::{Point, Rect, Size, Vec2};
+use image::{DynamicImage, ImageFormat, ImageOutputFormat};
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Seek, Write};
+use std::path::Path;
+use std::sync::Arc;
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::layer::{Layer, LayerId, LayerLock, Layers};
+use crate::plane::Plane;
+use crate::{BlendMode, CanvasData, Ellipse, Mask, Paintable, Selection};
+use anyhow::{anyhow, Result};
+
+const EXTENSION: &str = "paintr";
+const MIMETYPE: &str = "application/x-paintr";
+const VERSION: u32 = 1;
+
+const MIMETYPE_ENTRY: &str = "mimetype";
+const MANIFEST_ENTRY: &str = "manifest.json";
+const SELECTION_ENTRY: &str = "selection.png";
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Manifest {
+    version: u32,
+    width: f64,
+    height: f64,
+    /// Offset of the canvas, as moved by the move tool
+    offset: [f64; 2],
+    active: usize,
+    /// From bottom to top
+    layers: Vec<LayerEntry>,
+    selection: Option<SelectionEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct LayerEntry {
+    id: usize,
+    name: String,
+    x: f64,
+    y: f64,
+    opacity: f64,
+    blend_mode: BlendMode,
+    visible: bool,
+    lock_pixels: bool,
+    lock_position: bool,
+    src: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SelectionEntry {
+    shape: ShapeEntry,
+    /// The floating layer which the selection is bound to
+    layer: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+enum ShapeEntry {
+    Rect { x0: f64, y0: f64, x1: f64, y1: f64 },
+    Ellipse { x0: f64, y0: f64, x1: f64, y1: f64, anti_alias: bool },
+    Path { elements: Vec<PathEntry> },
+    Mask { x: f64, y: f64, src: String },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum PathEntry {
+    MoveTo([f64; 2]),
+    LineTo([f64; 2]),
+    QuadTo([f64; 2], [f64; 2]),
+    CurveTo([f64; 2], [f64; 2], [f64; 2]),
+    ClosePath,
+}
+
+fn to_array(pt: Point) -> [f64; 2] {
+    [pt.x, pt.y]
+}
+
+fn to_point(pt: [f64; 2]) -> Point {
+    Point::new(pt[0], pt[1])
+}
+
+impl PathEntry {
+    fn new(el: PathEl) -> PathEntry {
+        match el {
+            PathEl::MoveTo(p) => PathEntry::MoveTo(to_array(p)),
+            PathEl::LineTo(p) => PathEntry::LineTo(to_array(p)),
+            PathEl::QuadTo(p1, p2) => PathEntry::QuadTo(to_array(p1), to_array(p2)),
+            PathEl::CurveTo(p1, p2, p3) => {
+                PathEntry::CurveTo(to_array(p1), to_array(p2), to_array(p3))
+            }
+            PathEl::ClosePath => PathEntry::ClosePath,
+        }
+    }
+
+    fn to_el(&self) -> PathEl {
+        match *self {
+            PathEntry::MoveTo(p) => PathEl::MoveTo(to_point(p)),
+            PathEntry::LineTo(p) => PathEl::LineTo(to_point(p)),
+            PathEntry::QuadTo(p1, p2) => PathEl::QuadTo(to_point(p1), to_point(p2)),
+            PathEntry::CurveTo(p1, p2, p3) => {
+                PathEl::CurveTo(to_point(p1), to_point(p2), to_point(p3))
+            }
+            PathEntry::ClosePath => PathEl::ClosePath,
+        }
+    }
+}
+
+pub(crate) fn has_extension(path: &Path, extension: &str) -> bool {
+    path.extension().map_or(false, |ext| ext.eq_ignore_ascii_case(extension))
+}
+
+/// Whether `path` should be saved and opened as a native document
+pub(crate) fn is_document(path: &Path) -> bool {
+    has_extension(path, EXTENSION)
+}
+
+pub(crate) fn encode_png(img: &DynamicImage) -> Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    img.write_to(&mut buf, ImageOutputFormat::Png)?;
+    Ok(buf)
+}
+
+pub(crate) fn read_entry<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    name: &str,
+) -> Result<Vec<u8>> {
+    let mut entry =
+        archive.by_name(name).map_err(|_| anyhow!("Missing {} in the document", name))?;
+    let mut buf = Vec::new();
+    entry.read_to_end(&mut buf)?;
+    Ok(buf)
+}
+
+pub(crate) fn read_png<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    name: &str,
+) -> Result<DynamicImage> {
+    let buf = read_entry(archive, name)?;
+    Ok(image::load_from_memory_with_format(&buf, ImageFormat::Png)?)
+}
+
+fn shape_entry(sel: &Selection, pngs: &mut Vec<(String, Vec<u8>)>) -> Result<ShapeEntry> {
+    Ok(match sel {
+        Selection::Rect(r) => ShapeEntry::Rect { x0: r.x0, y0: r.y0, x1: r.x1, y1: r.y1 },
+        Selection::Ellipse(it) => {
+            let r = it.rect();
+            ShapeEntry::Ellipse {
+                x0: r.x0,
+                y0: r.y0,
+                x1: r.x1,
+                y1: r.y1,
+                anti_alias: it.anti_alias(),
+            }
+        }
+        Selection::Path(path) => ShapeEntry::Path {
+            elements: path.elements().iter().copied().map(PathEntry::new).collect(),
+        },
+        Selection::Mask(mask) => {
+            let origin = mask.bounds().origin();
+            let img = DynamicImage::ImageLuma8(mask.to_image());
+            pngs.push((SELECTION_ENTRY.into(), encode_png(&img)?));
+            ShapeEntry::Mask { x: origin.x, y: origin.y, src: SELECTION_ENTRY.into() }
+        }
+    })
+}
+
+fn read_shape<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    shape: &ShapeEntry,
+) -> Result<Selection> {
+    Ok(match shape {
+        ShapeEntry::Rect { x0, y0, x1, y1 } => Selection::Rect(Rect::new(*x0, *y0, *x1, *y1)),
+        ShapeEntry::Ellipse { x0, y0, x1, y1, anti_alias } => {
+            Selection::Ellipse(Ellipse::new(Rect::new(*x0, *y0, *x1, *y1), *anti_alias))
+        }
+        ShapeEntry::Path { elements } => {
+            Selection::Path(BezPath::from_vec(elements.iter().map(PathEntry::to_el).collect()))
+        }
+        ShapeEntry::Mask { x, y, src } => {
+            let img = read_png(archive, src)?.into_luma8();
+            let (w, h) = img.dimensions();
+            Selection::Mask(Mask::new(Point::new(*x, *y), w, h, img.into_raw()))
+        }
+    })
+}
+
+/// Write the canvas as a native document
+pub(crate) fn write<W: Write + Seek>(canvas: &CanvasData, writer: W) -> Result<()> {
+    let size = canvas.paint_size().unwrap_or_default();
+    let mut pngs = Vec::new();
+
+    let mut layers = Vec::new();
+    for layer in canvas.layers() {
+        let src = format!("layers/{}.png", layer.id().0);
+        pngs.push((src.clone(), encode_png(&layer.image())?));
+
+        let pos = layer.position();
+        layers.push(LayerEntry {
+            id: layer.id().0,
+            name: layer.name().into(),
+            x: pos.x,
+            y: pos.y,
+            opacity: layer.opacity(),
+            blend_mode: layer.blend_mode(),
+            visible: layer.is_visible(),
+            lock_pixels: layer.is_locked(LayerLock::Pixels),
+            lock_position: layer.is_locked(LayerLock::Position),
+            src,
+        });
+    }
+
+    let selection = match canvas.selection() {
+        Some(sel) => Some(SelectionEntry {
+            shape: shape_entry(sel, &mut pngs)?,
+            layer: canvas.selection_layer().map(|id| id.0),
+        }),
+        None => None,
+    };
+
+    let manifest = Manifest {
+        version: VERSION,
+        width: size.width,
+        height: size.height,
+        offset: to_array(canvas.position()),
+        active: canvas.active_layer().0,
+        layers,
+        selection,
+    };
+
+    let mut zip = ZipWriter::new(writer);
+    let stored = FileOptions::default().compression_method(CompressionMethod::Stored);
+    zip.start_file(MIMETYPE_ENTRY, stored)?;
+    zip.write_all(MIMETYPE.as_bytes())?;
+
+    zip.start_file(MANIFEST_ENTRY, FileOptions::default())?;
+    zip.write_all(&serde_json::to_vec_pretty(&manifest)?)?;
+
+    // PNG is already compressed
+    for (name, buf) in pngs {
+        zip.start_file(name, stored)?;
+        zip.write_all(&buf)?;
+    }
+    zip.finish()?;
+    Ok(())
+}
+
+/// Read a native document, which will be saved to `path`
+pub(crate) fn read<R: Read + Seek>(path: &Path, reader: R) -> Result<CanvasData> {
+    let mut archive = ZipArchive::new(reader)?;
+    if read_entry(&mut archive, MIMETYPE_ENTRY)? != MIMETYPE.as_bytes() {
+        return Err(anyhow!("Not a paintr document"));
+    }
+
+    let manifest: Manifest = serde_json::from_slice(&read_entry(&mut archive, MANIFEST_ENTRY)?)?;
+    if manifest.version > VERSION {
+        return Err(anyhow!("Unsupported document version {}", manifest.version));
+    }
+
+    let mut layers = Vec::new();
+    for entry in &manifest.layers {
+        let img = read_png(&mut archive, &entry.src)?;
+        let img = DynamicImage::ImageRgba8(img.into_rgba8());
+        let plane = Plane::Image(Arc::new(img));
+        let pos = Vec2::new(entry.x, entry.y);
+        layers.push(Layer::new(LayerId(entry.id), entry.name.clone(), plane, pos));
+    }
+
+    let mut layers = Layers::from_layers(layers, LayerId(manifest.active))
+        .ok_or_else(|| anyhow!("Invalid layers in the document"))?;
+    for entry in &manifest.layers {
+        let id = LayerId(entry.id);
+        layers.set_opacity(id, entry.opacity);
+        layers.set_blend_mode(id, entry.blend_mode);
+        layers.set_visible(id, entry.visible);
+        layers.set_lock(id, LayerLock::Pixels, entry.lock_pixels);
+        layers.set_lock(id, LayerLock::Position, entry.lock_position);
+    }
+
+    let size = Size::new(manifest.width, manifest.height);
+    let mut canvas =
+        CanvasData::from_layers(path, layers, size, to_point(manifest.offset).to_vec2());
+    if let Some(sel) = &manifest.selection {
+        let shape = read_shape(&mut archive, &sel.shape)?;
+        canvas.restore_selection(shape, sel.layer.map(LayerId));
+    }
+
+    Ok(canvas)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::image_utils::colors::*;
+    use crate::test_utils::canvas_fixture;
+    use druid::Data;
+    use image::GenericImageView;
+    use std::io::Cursor;
+
+    fn round_trip(canvas: &CanvasData) -> CanvasData {
+        let mut buf = Cursor::new(Vec::new());
+        write(canvas, &mut buf).unwrap();
+        buf.set_position(0);
+        read(Path::new("test.paintr"), buf).unwrap()
+    }
+
+    #[test]
+    fn document_should_keep_layers() {
+        let mut canvas = canvas_fixture(10, 8, WHITE);
+        let bottom = canvas.active_layer();
+        let top = canvas.insert_layer();
+        canvas.rename_layer(top, "Top");
+        canvas.set_layer_opacity(top, 0.5);
+        canvas.set_layer_blend_mode(top, BlendMode::Multiply);
+        canvas.set_layer_lock(top, LayerLock::Position, true);
+        canvas.set_layer_visible(bottom, false);
+        canvas.set_active_layer(bottom);
+
+        let opened = round_trip(&canvas);
+        assert_eq!(opened.layers().len(), 2);
+        assert_eq!(opened.active_layer(), bottom);
+        assert_eq!(opened.paint_size(), Some(Size::new(10.0, 8.0)));
+
+        let layer = opened.layer(top).unwrap();
+        assert_eq!(layer.name(), "Top");
+        assert_eq!(layer.opacity(), 0.5);
+        assert_eq!(layer.blend_mode(), BlendMode::Multiply);
+        assert!(layer.is_locked(LayerLock::Position));
+        assert!(!opened.layer(bottom).unwrap().is_visible());
+        assert_eq!(opened.layer(bottom).unwrap().image().get_pixel(3, 3), WHITE);
+
+        // New layers should not reuse the saved ids
+        let mut opened = opened;
+        let id = opened.insert_layer();
+        assert!(id != top && id != bottom);
+    }
+
+    #[test]
+    fn document_should_keep_selection() {
+        let mut canvas = canvas_fixture(10, 8, WHITE);
+        canvas.select(Ellipse::new(Rect::new(1.0, 1.0, 6.0, 5.0), true));
+        let opened = round_trip(&canvas);
+        assert!(opened.selection().unwrap().same(canvas.selection().unwrap()));
+
+        let mask = Mask::new(Point::new(2.0, 3.0), 2, 2, vec![0xff, 0x80, 0x00, 0xff]);
+        canvas.select(mask);
+        let opened = round_trip(&canvas);
+        let bounds = opened.selection().map(|it| it.bounds());
+        assert_eq!(bounds, canvas.selection().map(|it| it.bounds()));
+        assert!(opened.selection().unwrap().contains(Point::new(2.5, 3.5)));
+        assert!(!opened.selection().unwrap().contains(Point::new(2.5, 4.5)));
+    }
+
+    #[test]
+    fn document_should_reject_newer_version() {
+        let canvas = canvas_fixture(4, 4, BLACK);
+        let mut buf = Cursor::new(Vec::new());
+        write(&canvas, &mut buf).unwrap();
+        let mut json: serde_json::Value = serde_json::from_slice(
+            &read_entry(&mut ZipArchive::new(&mut buf).unwrap(), MANIFEST_ENTRY).unwrap(),
+        )
+        .unwrap();
+        json["version"] = (VERSION + 1).into();
+
+        let mut out = Cursor::new(Vec::new());
+        let mut zip = ZipWriter::new(&mut out);
+        zip.start_file(MIMETYPE_ENTRY, FileOptions::default()).unwrap();
+        zip.write_all(MIMETYPE.as_bytes()).unwrap();
+        zip.start_file(MANIFEST_ENTRY, FileOptions::default()).unwrap();
+        zip.write_all(json.to_string().as_bytes()).unwrap();
+        zip.finish().unwrap();
+        drop(zip);
+
+        out.set_position(0);
+        assert!(read(Path::new("test.paintr"), out).is_err());
+    }
+}
@@ -34,20 +34,55 @@ pub trait Edit<T: Data>: Debug {
     }
 
     fn description(&self) -> EditDesc;
+
+    /// Approximate memory used by the values kept by the edit, in bytes
+    fn size(&self) -> usize {
+        0
+    }
+}
+
+/// Data which can be restored from a delta, instead of keeping a full copy in the undo history
+pub trait Diff: Data {
+    type Delta: Debug;
+
+    /// The delta to restore `old` from `new`
+    fn diff(old: &Self, new: &Self) -> Self::Delta;
+
+    /// Restore the data before the delta from `self`, which is the data after it
+    fn patch(&self, delta: &Self::Delta) -> Self;
+
+    /// Approximate memory used by the delta, in bytes
+    fn delta_size(delta: &Self::Delta) -> usize;
+
+    /// Approximate memory used by a full copy of the data, in bytes
+    fn data_size(&self) -> usize;
 }
 
+/// The history of edits, as a tree of undo steps
+///
+/// Making an edit after undo starts a new branch, the old future is kept as a sibling branch.
 #[derive(Debug, Clone)]
 pub struct UndoHistory<T>
 where
-    T: Data + Debug,
+    T: Diff + Debug,
 {
-    undos: Vec<UndoState<T>>,
-    redos: Vec<Arc<dyn Edit<T>>>,
+    /// Nodes are indexed in creation order, removed nodes are left as `None` until compacted
+    nodes: Vec<Option<Node<T>>>,
+    root: usize,
+    current: usize,
+    snapshots: Vec<(String, T)>,
+    max_steps: usize,
+    max_bytes: usize,
+    bytes: usize,
+    /// The node which was saved last, `None` once it is removed
+    saved: Option<usize>,
+    /// Increased on every change, such that widgets can be updated
+    version: usize,
 }
 
-impl<T: Data + Debug> Data for UndoHistory<T> {
-    fn same(&self, _other: &Self) -> bool {
-        true
+impl<T: Diff + Debug> Data for UndoHistory<T> {
+    fn same(&self, other: &Self) -> bool {
+        self.version == other.version
     }
 }
 
@@ -57,63 +92,778 @@ pub enum EditKind {
     Mergeable,
 }
 
+/// A step on the current line of the history
+#[derive(Clone, Debug)]
+pub struct HistoryEntry {
+    desc: EditDesc,
+    branch: usize,
+    branches: usize,
+}
+
+impl HistoryEntry {
+    pub fn desc(&self) -> &EditDesc {
+        &self.desc
+    }
+
+    /// The index of the step among its sibling branches
+    pub fn branch(&self) -> usize {
+        self.branch
+    }
+
+    /// The number of sibling branches, including this step
+    pub fn branches(&self) -> usize {
+        self.branches
+    }
+}
+
+#[derive(Debug)]
+enum OldData<T: Diff> {
+    /// The data before the edit, while the edit could still be merged
+    Full(T),
+    /// The delta from the data after the edit, and its size
+    Delta(Arc<T::Delta>, usize),
+}
+
+impl<T: Diff> Clone for OldData<T> {
+    fn clone(&self) -> Self {
+        match self {
+            OldData::Full(it) => OldData::Full(it.clone()),
+            OldData::Delta(it, size) => OldData::Delta(it.clone(), *size),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
-struct UndoState<T: Data> {
-    old: T,
+struct UndoState<T: Diff> {
+    old: OldData<T>,
     kind: EditKind,
     edit: Arc<dyn Edit<T>>,
 }
 
-impl<T: Data> UndoState<T> {
+impl<T: Diff> UndoState<T> {
     fn new(old: T, edit: Arc<dyn Edit<T>>, kind: EditKind) -> UndoState<T> {
-        UndoState { old, edit, kind }
+        UndoState { old: OldData::Full(old), edit, kind }
     }
 
-    fn undo(self, data: &mut T) -> Arc<dyn Edit<T>> {
-        let (edit, old) = (self.edit, self.old);
-        *data = old;
-        edit
+    /// Whether the next edit could be merged into this one
+    fn is_open(&self) -> bool {
+        self.kind == EditKind::Mergeable && matches!(self.old, OldData::Full(_))
+    }
+
+    /// Replace the full snapshot by a delta from `new`, and update `bytes` by the new size
+    fn seal(&mut self, new: &T, bytes: &mut usize) {
+        if let OldData::Full(old) = &self.old {
+            let delta = T::diff(old, new);
+            let size = T::delta_size(&delta);
+            *bytes -= self.size();
+            self.old = OldData::Delta(Arc::new(delta), size);
+            *bytes += self.size();
+        }
+    }
+
+    /// Approximate memory used by the step, including the edit to redo
+    fn size(&self) -> usize {
+        let old = match &self.old {
+            OldData::Full(it) => it.data_size(),
+            OldData::Delta(_, size) => *size,
+        };
+        old + self.edit.size()
+    }
+
+    /// The data before the edit, from `data` after the edit
+    fn undo(&self, data: &T) -> T {
+        match &self.old {
+            OldData::Full(old) => old.clone(),
+            OldData::Delta(delta, _) => data.patch(delta),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Node<T: Diff> {
+    parent: Option<usize>,
+    children: Vec<usize>,
+    /// The child to redo
+    next: Option<usize>,
+    /// The step from the parent to this node, the root has no step
+    step: Option<UndoState<T>>,
+}
+
+impl<T: Diff> Node<T> {
+    fn new(parent: Option<usize>, step: Option<UndoState<T>>) -> Node<T> {
+        Node { parent, children: Vec::new(), next: None, step }
+    }
+}
+
+impl<T: Diff + Debug> Default for UndoHistory<T> {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
-impl<T: Data + Debug> UndoHistory<T> {
-    /// Add an edit on the undo stack and apply this edit
+impl<T: Diff + Debug> UndoHistory<T> {
+    pub const DEFAULT_MAX_STEPS: usize = 100;
+    pub const DEFAULT_MAX_BYTES: usize = 512 * 1024 * 1024;
+
+    /// Add an edit as a step after the current one and apply this edit
     pub fn edit(&mut self, data: &mut T, mut edit: impl Edit<T> + 'static, kind: EditKind) {
         let old = edit.execute(data);
-        if let Some(last) = self.undos.last_mut() {
-            if last.kind == EditKind::Mergeable && last.edit.merge(&mut edit) {
-                last.edit = Arc::new(edit);
-                last.kind = kind;
-                self.redos.clear();
+        self.version += 1;
+
+        let node = self.nodes[self.current].as_mut().expect("Current node must exist");
+        let leaf = node.children.is_empty();
+        if let Some(step) = node.step.as_mut().filter(|it| leaf && it.is_open()) {
+            if step.edit.merge(&mut edit) {
+                self.bytes -= step.size();
+                step.edit = Arc::new(edit);
+                step.kind = kind;
+                self.bytes += step.size();
+                if kind == EditKind::NonMergeable {
+                    step.seal(data, &mut self.bytes);
+                }
+                self.shrink();
                 return;
             }
         }
 
-        self.undos.push(UndoState::new(old, Arc::new(edit), kind));
-        self.redos.clear();
+        self.push(old, Arc::new(edit), kind, data);
     }
 
     pub fn new() -> UndoHistory<T> {
-        UndoHistory { undos: Vec::new(), redos: Vec::new() }
+        Self::with_limits(Self::DEFAULT_MAX_STEPS, Self::DEFAULT_MAX_BYTES)
+    }
+
+    /// Keep at most `max_steps` undo steps, using about `max_bytes` of memory
+    ///
+    /// Steps of other branches are dropped first, then the oldest ones. The last step is
+    /// always kept, even if it is larger than `max_bytes`.
+    pub fn with_limits(max_steps: usize, max_bytes: usize) -> UndoHistory<T> {
+        UndoHistory {
+            nodes: vec![Some(Node::new(None, None))],
+            root: 0,
+            current: 0,
+            snapshots: Vec::new(),
+            max_steps: max_steps.max(1),
+            max_bytes,
+            bytes: 0,
+            saved: Some(0),
+            version: 0,
+        }
+    }
+
+    /// Approximate memory used by the undo steps, in bytes
+    pub fn memory_usage(&self) -> usize {
+        self.bytes
     }
 
     pub fn undo(&mut self, data: &mut T) -> Option<EditDesc> {
-        let edit = self.undos.pop()?.undo(data);
-        let desc = edit.description();
-        self.redos.push(edit);
+        let current = self.current;
+        let node = self.nodes[current].as_mut().expect("Current node must exist");
+        let parent = node.parent?;
+        let step = node.step.as_mut().expect("Only the root has no step");
+
+        step.seal(data, &mut self.bytes);
+        *data = step.undo(data);
+        let desc = step.edit.description();
+
+        self.node_mut(parent).next = Some(current);
+        self.current = parent;
+        self.version += 1;
+        Some(desc)
+    }
+
+    /// Revert the current step and drop it, if edits could still be merged into it
+    pub fn cancel(&mut self, data: &mut T) -> Option<EditDesc> {
+        let current = self.current;
+        let node = self.node(current);
+        let parent = node.parent.filter(|_| node.children.is_empty())?;
+        let step = node.step.as_ref().filter(|it| it.is_open())?;
+
+        *data = step.undo(data);
+        let desc = step.edit.description();
+        self.bytes -= step.size();
+
+        self.nodes[current] = None;
+        let parent_node = self.node_mut(parent);
+        parent_node.children.retain(|it| *it != current);
+        parent_node.next = parent_node.children.last().copied();
+        self.current = parent;
+        self.version += 1;
         Some(desc)
     }
 
     pub fn redo(&mut self, data: &mut T) -> Option<EditDesc> {
-        let edit = self.redos.pop()?;
+        let next = self.node(self.current).next?;
+        Some(self.enter(data, next))
+    }
+
+    /// Undo the current step and redo its previous or next sibling branch
+    pub fn switch_branch(&mut self, data: &mut T, forward: bool) -> Option<EditDesc> {
+        let parent = self.node(self.current).parent?;
+        let siblings = &self.node(parent).children;
+        let index = siblings.iter().position(|it| *it == self.current)?;
+        let index = if forward { index + 1 } else { index.checked_sub(1)? };
+        let sibling = *siblings.get(index)?;
+
+        self.undo(data);
+        Some(self.enter(data, sibling))
+    }
+
+    /// The steps from the oldest one to the current one, followed by the steps to redo
+    pub fn entries(&self) -> Vec<HistoryEntry> {
+        self.line()
+            .into_iter()
+            .map(|id| {
+                let node = self.node(id);
+                let siblings =
+                    &self.node(node.parent.expect("Only the root has no parent")).children;
+                HistoryEntry {
+                    desc: node.step.as_ref().expect("Only the root has no step").edit.description(),
+                    branch: siblings.iter().position(|it| *it == id).unwrap_or_default(),
+                    branches: siblings.len(),
+                }
+            })
+            .collect()
+    }
+
+    /// The number of steps applied, the current state is after `entries()[position() - 1]`
+    pub fn position(&self) -> usize {
+        self.ancestors(self.current).count()
+    }
+
+    /// Undo or redo until `position` steps of `entries()` are applied
+    pub fn goto(&mut self, data: &mut T, position: usize) -> bool {
+        let position = position.min(self.line().len());
+        let moved = position != self.position();
+        while self.position() > position {
+            self.undo(data);
+        }
+        while self.position() < position {
+            self.redo(data);
+        }
+        moved
+    }
+
+    /// Remember the current state as saved, further edits are not merged into it
+    pub fn mark_saved(&mut self, data: &T) {
+        let current = self.nodes[self.current].as_mut().expect("Current node must exist");
+        if let Some(step) = current.step.as_mut() {
+            step.seal(data, &mut self.bytes);
+        }
+        self.saved = Some(self.current);
+        self.version += 1;
+    }
+
+    /// Forget the saved state, such that the data is unsaved until `mark_saved`
+    pub fn mark_unsaved(&mut self) {
+        self.saved = None;
+        self.version += 1;
+    }
+
+    /// Whether the current state differs from the saved one
+    pub fn is_dirty(&self) -> bool {
+        self.saved != Some(self.current)
+    }
+
+    pub fn snapshots(&self) -> impl Iterator<Item = &str> {
+        self.snapshots.iter().map(|(name, _)| name.as_str())
+    }
+
+    /// Keep a copy of `data`, which survives further edits
+    ///
+    /// Snapshots count in the memory limit, the oldest ones are dropped if they alone
+    /// exceed it. Undo steps are dropped first otherwise.
+    pub fn take_snapshot(&mut self, name: impl Into<String>, data: &T) {
+        self.bytes += data.data_size();
+        self.snapshots.push((name.into(), data.clone()));
+        while self.snapshots.len() > 1 && self.snapshots_size() > self.max_bytes {
+            let (_, snapshot) = self.snapshots.remove(0);
+            self.bytes -= snapshot.data_size();
+        }
+        self.shrink();
+        self.version += 1;
+    }
+
+    fn snapshots_size(&self) -> usize {
+        self.snapshots.iter().map(|(_, it)| it.data_size()).sum()
+    }
+
+    /// Restore a snapshot as a new step, such that it can be undone
+    pub fn restore_snapshot(&mut self, data: &mut T, index: usize) -> Option<EditDesc> {
+        let (name, snapshot) = self.snapshots.get(index)?.clone();
+        let edit = RestoreSnapshot { name, data: snapshot };
         let desc = edit.description();
-        let old = edit.execute(data);
-        self.undos.push(UndoState::new(old, edit, EditKind::NonMergeable));
+        self.edit(data, edit, EditKind::NonMergeable);
         Some(desc)
     }
+
+    fn node(&self, id: usize) -> &Node<T> {
+        self.nodes[id].as_ref().expect("Node must exist")
+    }
+
+    fn node_mut(&mut self, id: usize) -> &mut Node<T> {
+        self.nodes[id].as_mut().expect("Node must exist")
+    }
+
+    /// The nodes from `id` to the child of the root
+    fn ancestors(&self, id: usize) -> impl Iterator<Item = usize> + '_ {
+        let first = Some(id).filter(|it| *it != self.root);
+        std::iter::successors(first, move |it| self.node(*it).parent.filter(|p| *p != self.root))
+    }
+
+    /// The nodes of `entries()`
+    fn line(&self) -> Vec<usize> {
+        let mut line: Vec<_> = self.ancestors(self.current).collect();
+        line.reverse();
+        line.extend(std::iter::successors(self.node(self.current).next, |it| self.node(*it).next));
+        line
+    }
+
+    fn steps(&self) -> usize {
+        self.nodes.iter().flatten().count() - 1
+    }
+
+    /// Redo the step to `child` of the current node
+    fn enter(&mut self, data: &mut T, child: usize) -> EditDesc {
+        let edit = self.node(child).step.as_ref().expect("Only the root has no step").edit.clone();
+        edit.apply(data);
+
+        self.node_mut(self.current).next = Some(child);
+        self.current = child;
+        self.version += 1;
+        edit.description()
+    }
+
+    fn push(&mut self, old: T, edit: Arc<dyn Edit<T>>, kind: EditKind, data: &T) {
+        // The current step cannot be merged anymore, and `old` is the data after it
+        let current = self.nodes[self.current].as_mut().expect("Current node must exist");
+        if let Some(step) = current.step.as_mut() {
+            step.seal(&old, &mut self.bytes);
+        }
+
+        let mut step = UndoState::new(old, edit, kind);
+        self.bytes += step.size();
+        if kind == EditKind::NonMergeable {
+            step.seal(data, &mut self.bytes);
+        }
+
+        let id = self.nodes.len();
+        self.nodes.push(Some(Node::new(Some(self.current), Some(step))));
+        let parent = self.node_mut(self.current);
+        parent.children.push(id);
+        parent.next = Some(id);
+        self.current = id;
+        self.shrink();
+    }
+
+    /// Drop undo steps until it fits the limits
+    fn shrink(&mut self) {
+        while self.steps() > 1 && (self.steps() > self.max_steps || self.bytes > self.max_bytes) {
+            match self.removable() {
+                Some(id) => self.remove(id),
+                None => break,
+            }
+        }
+        if self.nodes.len() > 2 * (self.steps() + 1) {
+            self.compact();
+        }
+    }
+
+    /// The oldest leaf of other branches, or the oldest step if the root has no other branch
+    fn removable(&self) -> Option<usize> {
+        let leaf = self.nodes.iter().enumerate().find_map(|(id, node)| {
+            let node = node.as_ref()?;
+            Some(id).filter(|_| node.children.is_empty() && id != self.current)
+        });
+
+        let root = self.node(self.root);
+        leaf.or_else(|| root.children.first().copied().filter(|_| root.children.len() == 1))
+    }
+
+    /// Drop the removed nodes from `nodes`, keeping the order of creation
+    fn compact(&mut self) {
+        let mut ids = vec![None; self.nodes.len()];
+        let live = self.nodes.iter().enumerate().filter(|(_, it)| it.is_some());
+        for (new, (old, _)) in live.enumerate() {
+            ids[old] = Some(new);
+        }
+        let map = |id: usize| ids[id].expect("Node must exist");
+
+        let nodes = std::mem::take(&mut self.nodes);
+        self.nodes = nodes
+            .into_iter()
+            .flatten()
+            .map(|mut node| {
+                node.parent = node.parent.map(map);
+                node.children.iter_mut().for_each(|it| *it = map(*it));
+                node.next = node.next.map(map);
+                Some(node)
+            })
+            .collect();
+        self.root = map(self.root);
+        self.current = map(self.current);
+        self.saved = self.saved.and_then(|it| ids[it]);
+    }
+
+    fn remove(&mut self, id: usize) {
+        let mut node = self.nodes[id].take().expect("Node must exist");
+        self.bytes -= node.step.take().map_or(0, |it| it.size());
+
+        let parent = node.parent.expect("Only the root has no parent");
+        if node.children.is_empty() {
+            let parent = self.node_mut(parent);
+            parent.children.retain(|it| *it != id);
+            if parent.next == Some(id) {
+                parent.next = parent.children.last().copied();
+            }
+        } else {
+            // Make it the new root
+            self.nodes[parent] = None;
+            node.parent = None;
+            self.root = id;
+            self.nodes[id] = Some(node);
+        }
+    }
+}
+
+#[derive(Debug)]
+struct RestoreSnapshot<T> {
+    name: String,
+    data: T,
+}
+
+impl<T: Diff + Debug> Edit<T> for RestoreSnapshot<T> {
+    fn apply(&self, data: &mut T) {
+        *data = self.data.clone();
+    }
+
+    fn description(&self) -> EditDesc {
+        EditDesc::new(format!("Restore {}", self.name))
+    }
+
+    fn size(&self) -> usize {
+        self.data.data_size()
+    }
 }
 
 #[cfg(test)]
 mod test {
-    // FIXME: Add tests for UndoHistory
+    use super::*;
+
+    /// A counter, its delta is the old value and takes 100 bytes, a full copy takes 1000
+    #[derive(Debug, Clone, Copy, PartialEq, Data)]
+    struct Counter(i32);
+
+    impl Diff for Counter {
+        type Delta = i32;
+
+        fn diff(old: &Counter, _new: &Counter) -> i32 {
+            old.0
+        }
+
+        fn patch(&self, delta: &i32) -> Counter {
+            Counter(*delta)
+        }
+
+        fn delta_size(_delta: &i32) -> usize {
+            100
+        }
+
+        fn data_size(&self) -> usize {
+            1000
+        }
+    }
+
+    #[derive(Debug)]
+    struct Add(i32);
+
+    impl Edit<Counter> for Add {
+        fn apply(&self, data: &mut Counter) {
+            data.0 += self.0;
+        }
+
+        fn merge(&self, other: &mut dyn Any) -> bool {
+            if let Some(other) = other.downcast_mut::<Add>() {
+                other.0 += self.0;
+                return true;
+            }
+            false
+        }
+
+        fn description(&self) -> EditDesc {
+            EditDesc::new(format!("Add {}", self.0))
+        }
+    }
+
+    #[test]
+    fn undo_history_should_undo_and_redo() {
+        let (mut history, mut data) = (UndoHistory::new(), Counter(0));
+        history.edit(&mut data, Add(1), EditKind::NonMergeable);
+        history.edit(&mut data, Add(2), EditKind::NonMergeable);
+        assert_eq!(data, Counter(3));
+
+        assert_eq!(history.undo(&mut data).unwrap().to_string(), "Add 2");
+        assert_eq!(data, Counter(1));
+        assert_eq!(history.undo(&mut data).unwrap().to_string(), "Add 1");
+        assert_eq!(data, Counter(0));
+        assert!(history.undo(&mut data).is_none());
+
+        assert_eq!(history.redo(&mut data).unwrap().to_string(), "Add 1");
+        assert_eq!(data, Counter(1));
+
+        // A new edit starts a new branch, which has nothing to redo
+        history.edit(&mut data, Add(5), EditKind::NonMergeable);
+        assert!(history.redo(&mut data).is_none());
+        history.undo(&mut data);
+        assert_eq!(data, Counter(1));
+    }
+
+    #[test]
+    fn undo_history_should_merge_edits() {
+        let (mut history, mut data) = (UndoHistory::new(), Counter(0));
+        history.edit(&mut data, Add(1), EditKind::Mergeable);
+        history.edit(&mut data, Add(2), EditKind::Mergeable);
+        history.edit(&mut data, Add(3), EditKind::NonMergeable);
+        history.edit(&mut data, Add(4), EditKind::NonMergeable);
+        assert_eq!(data, Counter(10));
+        assert_eq!(history.steps(), 2);
+
+        assert_eq!(history.undo(&mut data).unwrap().to_string(), "Add 4");
+        assert_eq!(history.undo(&mut data).unwrap().to_string(), "Add 6");
+        assert_eq!(data, Counter(0));
+    }
+
+    #[test]
+    fn undo_history_should_keep_full_snapshot_while_mergeable() {
+        let (mut history, mut data) = (UndoHistory::new(), Counter(0));
+        history.edit(&mut data, Add(1), EditKind::Mergeable);
+        history.edit(&mut data, Add(1), EditKind::Mergeable);
+        assert_eq!(history.memory_usage(), 1000);
+
+        history.edit(&mut data, Add(1), EditKind::NonMergeable);
+        assert_eq!(history.memory_usage(), 100);
+
+        // The step is kept for redo
+        history.undo(&mut data);
+        assert_eq!(history.memory_usage(), 100);
+        assert_eq!(data, Counter(0));
+    }
+
+    #[test]
+    fn undo_history_should_cancel_open_step() {
+        let mut history = UndoHistory::new();
+        let mut counter = Counter(0);
+        history.edit(&mut counter, Add(1), EditKind::NonMergeable);
+        history.edit(&mut counter, Add(2), EditKind::Mergeable);
+        history.edit(&mut counter, Add(3), EditKind::Mergeable);
+
+        assert!(history.cancel(&mut counter).is_some());
+        assert_eq!(counter.0, 1);
+        assert_eq!(history.entries().len(), 1);
+        assert_eq!(history.memory_usage(), 100);
+        assert!(history.redo(&mut counter).is_none());
+
+        // A finished step is not canceled
+        assert!(history.cancel(&mut counter).is_none());
+        assert_eq!(counter.0, 1);
+    }
+
+    #[test]
+    fn undo_history_should_limit_steps() {
+        let (mut history, mut data) = (UndoHistory::with_limits(3, usize::MAX), Counter(0));
+        for i in 1..=5 {
+            history.edit(&mut data, Add(i), EditKind::NonMergeable);
+        }
+        assert_eq!(history.steps(), 3);
+        assert_eq!(history.memory_usage(), 300);
+
+        while history.undo(&mut data).is_some() {}
+        assert_eq!(data, Counter(3));
+    }
+
+    #[test]
+    fn undo_history_should_limit_memory() {
+        let (mut history, mut data) = (UndoHistory::with_limits(100, 250), Counter(0));
+        for i in 1..=5 {
+            history.edit(&mut data, Add(i), EditKind::NonMergeable);
+        }
+        assert_eq!(history.steps(), 2);
+        assert_eq!(history.memory_usage(), 200);
+
+        // The last step is kept, even over the limit
+        let mut history = UndoHistory::with_limits(100, 10);
+        history.edit(&mut data, Add(1), EditKind::NonMergeable);
+        history.edit(&mut data, Add(1), EditKind::NonMergeable);
+        assert_eq!(history.steps(), 1);
+    }
+
+    #[test]
+    fn undo_history_should_goto_position() {
+        let (mut history, mut data) = (UndoHistory::new(), Counter(0));
+        for i in 1..=4 {
+            history.edit(&mut data, Add(i), EditKind::NonMergeable);
+        }
+
+        assert!(history.goto(&mut data, 1));
+        assert_eq!(data, Counter(1));
+        assert_eq!(history.position(), 1);
+        let entries: Vec<_> = history.entries().iter().map(|it| it.desc().to_string()).collect();
+        assert_eq!(entries, ["Add 1", "Add 2", "Add 3", "Add 4"]);
+
+        assert!(history.goto(&mut data, 3));
+        assert_eq!(data, Counter(6));
+        assert!(!history.goto(&mut data, 3));
+        assert!(history.goto(&mut data, 100));
+        assert_eq!(data, Counter(10));
+    }
+
+    #[test]
+    fn undo_history_should_restore_snapshots() {
+        let (mut history, mut data) = (UndoHistory::new(), Counter(0));
+        history.edit(&mut data, Add(1), EditKind::NonMergeable);
+        history.take_snapshot("One", &data);
+        history.edit(&mut data, Add(2), EditKind::NonMergeable);
+        assert_eq!(history.snapshots().collect::<Vec<_>>(), ["One"]);
+
+        let desc = history.restore_snapshot(&mut data, 0).unwrap();
+        assert_eq!(desc.to_string(), "Restore One");
+        assert_eq!(data, Counter(1));
+
+        history.undo(&mut data);
+        assert_eq!(data, Counter(3));
+        assert!(history.restore_snapshot(&mut data, 1).is_none());
+    }
+
+    #[test]
+    fn undo_history_should_limit_snapshots() {
+        let (mut history, mut data) = (UndoHistory::with_limits(100, 2250), Counter(0));
+        for name in &["One", "Two", "Three"] {
+            history.edit(&mut data, Add(1), EditKind::NonMergeable);
+            history.take_snapshot(*name, &data);
+        }
+        assert_eq!(history.snapshots().collect::<Vec<_>>(), ["Two", "Three"]);
+
+        // Steps are dropped to make room for the snapshots
+        assert_eq!(history.steps(), 2);
+        assert_eq!(history.memory_usage(), 2200);
+    }
+
+    #[test]
+    fn undo_history_should_keep_branches() {
+        let (mut history, mut data) = (UndoHistory::new(), Counter(0));
+        history.edit(&mut data, Add(1), EditKind::NonMergeable);
+        history.edit(&mut data, Add(2), EditKind::NonMergeable);
+        history.undo(&mut data);
+        history.edit(&mut data, Add(5), EditKind::NonMergeable);
+        assert_eq!(data, Counter(6));
+        assert_eq!(history.steps(), 3);
+
+        let entry = history.entries().pop().unwrap();
+        assert_eq!((entry.branch(), entry.branches()), (1, 2));
+        assert!(history.switch_branch(&mut data, true).is_none());
+
+        assert_eq!(history.switch_branch(&mut data, false).unwrap().to_string(), "Add 2");
+        assert_eq!(data, Counter(3));
+
+        // Redo follows the last visited branch
+        history.undo(&mut data);
+        history.redo(&mut data);
+        assert_eq!(data, Counter(3));
+        assert_eq!(history.switch_branch(&mut data, true).unwrap().to_string(), "Add 5");
+        assert_eq!(data, Counter(6));
+    }
+
+    #[test]
+    fn undo_history_should_drop_other_branches_before_oldest_step() {
+        let (mut history, mut data) = (UndoHistory::with_limits(3, usize::MAX), Counter(0));
+        for i in 1..=3 {
+            history.edit(&mut data, Add(i), EditKind::NonMergeable);
+        }
+        history.undo(&mut data);
+        history.undo(&mut data);
+        history.edit(&mut data, Add(4), EditKind::NonMergeable);
+
+        // The leaf of the other branch is dropped, the first step is kept
+        let entries = history.entries();
+        assert_eq!(
+            entries.iter().map(|it| it.desc().to_string()).collect::<Vec<_>>(),
+            ["Add 1", "Add 4"]
+        );
+        assert_eq!(entries[1].branches(), 2);
+    }
+
+    #[test]
+    fn undo_history_should_compact_removed_nodes() {
+        let (mut history, mut data) = (UndoHistory::with_limits(2, usize::MAX), Counter(0));
+        history.edit(&mut data, Add(1), EditKind::NonMergeable);
+        history.mark_saved(&data);
+        for _ in 0..10 {
+            history.edit(&mut data, Add(1), EditKind::NonMergeable);
+        }
+        assert!(history.nodes.len() <= 2 * (history.steps() + 1));
+        assert!(history.is_dirty());
+
+        assert!(history.undo(&mut data).is_some());
+        assert!(history.undo(&mut data).is_some());
+        assert!(history.undo(&mut data).is_none());
+        assert_eq!(data, Counter(9));
+        assert!(history.is_dirty());
+    }
+
+    #[test]
+    fn undo_history_should_merge_only_at_leaf() {
+        let (mut history, mut data) = (UndoHistory::new(), Counter(0));
+        history.edit(&mut data, Add(1), EditKind::Mergeable);
+        history.edit(&mut data, Add(1), EditKind::Mergeable);
+        history.undo(&mut data);
+        history.redo(&mut data);
+        history.edit(&mut data, Add(1), EditKind::NonMergeable);
+        assert_eq!(history.steps(), 2);
+
+        history.undo(&mut data);
+        history.undo(&mut data);
+        assert_eq!(data, Counter(0));
+    }
+
+    #[test]
+    fn undo_history_should_drop_other_branches_first() {
+        let (mut history, mut data) = (UndoHistory::with_limits(3, usize::MAX), Counter(0));
+        history.edit(&mut data, Add(1), EditKind::NonMergeable);
+        history.edit(&mut data, Add(2), EditKind::NonMergeable);
+        history.undo(&mut data);
+        history.edit(&mut data, Add(3), EditKind::NonMergeable);
+        history.edit(&mut data, Add(4), EditKind::NonMergeable);
+        assert_eq!(history.steps(), 3);
+        assert!(history.switch_branch(&mut data, false).is_none());
+
+        history.edit(&mut data, Add(5), EditKind::NonMergeable);
+        let entries: Vec<_> = history.entries().iter().map(|it| it.desc().to_string()).collect();
+        assert_eq!(entries, ["Add 3", "Add 4", "Add 5"]);
+        while history.undo(&mut data).is_some() {}
+        assert_eq!(data, Counter(1));
+    }
+
+    #[test]
+    fn undo_history_should_track_saved_state() {
+        let (mut history, mut data) = (UndoHistory::new(), Counter(0));
+        assert!(!history.is_dirty());
+        history.edit(&mut data, Add(1), EditKind::Mergeable);
+        assert!(history.is_dirty());
+
+        // The saved step is not merged with further edits
+        history.mark_saved(&data);
+        assert!(!history.is_dirty());
+        history.edit(&mut data, Add(2), EditKind::Mergeable);
+        assert_eq!(history.steps(), 2);
+        assert!(history.is_dirty());
+
+        history.undo(&mut data);
+        assert!(!history.is_dirty());
+        history.undo(&mut data);
+        assert!(history.is_dirty());
+
+        history.mark_unsaved();
+        history.redo(&mut data);
+        assert!(history.is_dirty());
+    }
 }
@@ -0,0 +1,284 @@
+//! Options of exporting the flattened image
+use druid::{Color, Data, Lens};
+use image::codecs::{bmp, gif, jpeg, png, tga, tiff};
+use image::{ColorType, DynamicImage, GenericImageView, Rgb, RgbImage};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::io::{Seek, Write};
+use std::path::Path;
+
+use crate::image_utils::color_to_rgba;
+use anyhow::Result;
+
+/// A format of flattened images
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Data, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Png,
+    Jpeg,
+    WebP,
+    Tiff,
+    Tga,
+    Bmp,
+    Gif,
+}
+
+impl ExportFormat {
+    pub const ALL: [ExportFormat; 7] = [
+        ExportFormat::Png,
+        ExportFormat::Jpeg,
+        ExportFormat::WebP,
+        ExportFormat::Tiff,
+        ExportFormat::Tga,
+        ExportFormat::Bmp,
+        ExportFormat::Gif,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ExportFormat::Png => "PNG",
+            ExportFormat::Jpeg => "JPEG",
+            ExportFormat::WebP => "WebP",
+            ExportFormat::Tiff => "TIFF",
+            ExportFormat::Tga => "TGA",
+            ExportFormat::Bmp => "BMP",
+            ExportFormat::Gif => "GIF",
+        }
+    }
+
+    /// File extensions of the format, the first one is preferred
+    pub fn extensions(&self) -> &'static [&'static str] {
+        match self {
+            ExportFormat::Png => &["png"],
+            ExportFormat::Jpeg => &["jpg", "jpeg"],
+            ExportFormat::WebP => &["webp"],
+            ExportFormat::Tiff => &["tif", "tiff"],
+            ExportFormat::Tga => &["tga"],
+            ExportFormat::Bmp => &["bmp"],
+            ExportFormat::Gif => &["gif"],
+        }
+    }
+
+    pub fn from_path(path: &Path) -> Option<ExportFormat> {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        ExportFormat::ALL.iter().copied().find(|it| it.extensions().contains(&ext.as_str()))
+    }
+
+    /// Whether the format has any option to choose before exporting
+    pub fn has_options(&self) -> bool {
+        matches!(self, ExportFormat::Png | ExportFormat::Jpeg | ExportFormat::WebP)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Data, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PngCompression {
+    Default,
+    Fast,
+    Best,
+}
+
+impl PngCompression {
+    pub const ALL: [PngCompression; 3] =
+        [PngCompression::Default, PngCompression::Fast, PngCompression::Best];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PngCompression::Default => "Default",
+            PngCompression::Fast => "Fast",
+            PngCompression::Best => "Best",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Data, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PngFilter {
+    NoFilter,
+    Sub,
+    Up,
+    Average,
+    Paeth,
+}
+
+impl PngFilter {
+    pub const ALL: [PngFilter; 5] =
+        [PngFilter::NoFilter, PngFilter::Sub, PngFilter::Up, PngFilter::Average, PngFilter::Paeth];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PngFilter::NoFilter => "None",
+            PngFilter::Sub => "Sub",
+            PngFilter::Up => "Up",
+            PngFilter::Average => "Average",
+            PngFilter::Paeth => "Paeth",
+        }
+    }
+}
+
+/// Options of exporting the flattened image, only some of them are used by each format
+#[derive(Debug, Clone, PartialEq, Data, Lens, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ExportOptions {
+    pub format: ExportFormat,
+    /// Quality of JPEG and lossy WebP, from 1 to 100
+    pub quality: f64,
+    /// The color behind transparent pixels, for formats without alpha
+    #[serde(serialize_with = "serialize_color", deserialize_with = "deserialize_color")]
+    pub background: Color,
+    pub png_compression: PngCompression,
+    pub png_filter: PngFilter,
+    /// Encode WebP without loss, `quality` is ignored
+    pub lossless: bool,
+}
+
+/// Colors are kept as `0xRRGGBBAA`
+fn serialize_color<S: Serializer>(color: &Color, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_u32(color.as_rgba_u32())
+}
+
+fn deserialize_color<'de, D: Deserializer<'de>>(d: D) -> Result<Color, D::Error> {
+    Ok(Color::from_rgba32_u32(u32::deserialize(d)?))
+}
+
+impl ExportOptions {
+    pub fn new(format: ExportFormat) -> ExportOptions {
+        ExportOptions {
+            format,
+            quality: 90.0,
+            background: Color::WHITE,
+            png_compression: PngCompression::Default,
+            png_filter: PngFilter::Paeth,
+            lossless: false,
+        }
+    }
+
+    /// Composite the image over the background color
+    fn flatten(&self, img: &DynamicImage) -> RgbImage {
+        let bg = color_to_rgba(&self.background);
+        RgbImage::from_fn(img.width(), img.height(), |x, y| {
+            let p = img.get_pixel(x, y);
+            let a = p[3] as u32;
+            let mix = |c: u8, b: u8| ((c as u32 * a + b as u32 * (255 - a) + 127) / 255) as u8;
+            Rgb([mix(p[0], bg[0]), mix(p[1], bg[1]), mix(p[2], bg[2])])
+        })
+    }
+
+    pub fn encode(&self, img: &DynamicImage, w: &mut (impl Write + Seek)) -> Result<()> {
+        let rgba = img.to_rgba8();
+        let (width, height) = rgba.dimensions();
+        let quality = self.quality.clamp(1.0, 100.0);
+
+        match self.format {
+            ExportFormat::Png => {
+                let compression = match self.png_compression {
+                    PngCompression::Default => png::CompressionType::Default,
+                    PngCompression::Fast => png::CompressionType::Fast,
+                    PngCompression::Best => png::CompressionType::Best,
+                };
+                let filter = match self.png_filter {
+                    PngFilter::NoFilter => png::FilterType::NoFilter,
+                    PngFilter::Sub => png::FilterType::Sub,
+                    PngFilter::Up => png::FilterType::Up,
+                    PngFilter::Average => png::FilterType::Avg,
+                    PngFilter::Paeth => png::FilterType::Paeth,
+                };
+                png::PngEncoder::new_with_quality(w, compression, filter).encode(
+                    &rgba,
+                    width,
+                    height,
+                    ColorType::Rgba8,
+                )?;
+            }
+            ExportFormat::Jpeg => {
+                let rgb = self.flatten(img);
+                jpeg::JpegEncoder::new_with_quality(w, quality as u8).encode(
+                    &rgb,
+                    width,
+                    height,
+                    ColorType::Rgb8,
+                )?;
+            }
+            ExportFormat::WebP => {
+                let encoder = webp::Encoder::from_rgba(&rgba, width, height);
+                let data = match self.lossless {
+                    true => encoder.encode_lossless(),
+                    false => encoder.encode(quality as f32),
+                };
+                w.write_all(&data)?;
+            }
+            ExportFormat::Tiff => {
+                tiff::TiffEncoder::new(w).encode(&rgba, width, height, ColorType::Rgba8)?
+            }
+            ExportFormat::Tga => {
+                tga::TgaEncoder::new(w).encode(&rgba, width, height, ColorType::Rgba8)?
+            }
+            ExportFormat::Bmp => {
+                bmp::BmpEncoder::new(w).encode(&rgba, width, height, ColorType::Rgba8)?
+            }
+            ExportFormat::Gif => {
+                gif::GifEncoder::new(w).encode(&rgba, width, height, ColorType::Rgba8)?
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::image_utils::{colors::*, make_color_img};
+    use image::ImageFormat;
+    use std::io::Cursor;
+
+    fn encode(img: &DynamicImage, options: &ExportOptions, format: ImageFormat) -> DynamicImage {
+        let mut buf = Cursor::new(Vec::new());
+        options.encode(img, &mut buf).unwrap();
+        image::load_from_memory_with_format(buf.get_ref(), format).unwrap()
+    }
+
+    #[test]
+    fn export_format_should_match_extension() {
+        assert_eq!(ExportFormat::from_path(Path::new("a.JPEG")), Some(ExportFormat::Jpeg));
+        assert_eq!(ExportFormat::from_path(Path::new("a.tif")), Some(ExportFormat::Tiff));
+        assert_eq!(ExportFormat::from_path(Path::new("a.paintr")), None);
+        assert_eq!(ExportFormat::from_path(Path::new("a")), None);
+    }
+
+    #[test]
+    fn export_jpeg_should_flatten_onto_background() {
+        let img = make_color_img(8, 8, TRANSPARENT);
+        let mut options = ExportOptions::new(ExportFormat::Jpeg);
+        options.background = Color::rgb8(0, 0, 0);
+
+        let p = encode(&img, &options, ImageFormat::Jpeg).get_pixel(4, 4);
+        assert!(p[0] < 8 && p[1] < 8 && p[2] < 8);
+    }
+
+    #[test]
+    fn export_lossless_formats_should_keep_pixels() {
+        let img = make_color_img(8, 8, WHITE);
+        let formats = [
+            (ExportFormat::Png, ImageFormat::Png),
+            (ExportFormat::Tiff, ImageFormat::Tiff),
+            (ExportFormat::Tga, ImageFormat::Tga),
+            (ExportFormat::Bmp, ImageFormat::Bmp),
+        ];
+        for (export, format) in &formats {
+            let mut options = ExportOptions::new(*export);
+            options.png_compression = PngCompression::Best;
+            options.png_filter = PngFilter::NoFilter;
+            assert_eq!(encode(&img, &options, *format).get_pixel(3, 3), WHITE, "{:?}", export);
+        }
+    }
+
+    #[test]
+    fn export_options_should_serialize() {
+        let mut options = ExportOptions::new(ExportFormat::WebP);
+        options.background = Color::rgba8(1, 2, 3, 4);
+        options.lossless = true;
+
+        let json = serde_json::to_string(&options).unwrap();
+        assert!(json.contains("\"format\":\"webp\""), "{}", json);
+        assert_eq!(serde_json::from_str::<ExportOptions>(&json).unwrap(), options);
+    }
+}
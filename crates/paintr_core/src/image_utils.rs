@@ -1,8 +1,8 @@
-use crate::Paintable;
-use druid::{Point, Rect, Vec2};
+use crate::{BlendMode, Paintable};
+use druid::{Color, Point, Rect, Vec2};
 use image::{
     error::ParameterError, error::ParameterErrorKind, DynamicImage, GenericImage, GenericImageView,
-    ImageBuffer, Pixel, Rgba,
+    ImageBuffer, Rgba,
 };
 
 pub mod colors {
@@ -14,7 +14,14 @@ pub mod colors {
     pub const YELLOW: Rgba<u8> = Rgba([0xffu8, 0xc9u8, 0x22u8, 0xffu8]);
 }
 
-fn blend_from<O>(dest: &mut DynamicImage, other: &O, x: u32, y: u32) -> image::ImageResult<()>
+fn blend_from<O>(
+    dest: &mut DynamicImage,
+    other: &O,
+    x: u32,
+    y: u32,
+    opacity: f64,
+    mode: BlendMode,
+) -> image::ImageResult<()>
 where
     O: GenericImageView<Pixel = Rgba<u8>>,
 {
@@ -29,9 +36,8 @@ where
     for i in 0..other.width() {
         for k in 0..other.height() {
             let p = other.get_pixel(i, k);
-            let mut to = dest.get_pixel(i + x, k + y);
-            to.blend(&p);
-            dest.put_pixel(i + x, k + y, to);
+            let to = dest.get_pixel(i + x, k + y);
+            dest.put_pixel(i + x, k + y, mode.composite(to, p, opacity));
         }
     }
 
@@ -42,13 +48,31 @@ pub(crate) fn merge_image(
     dest: &mut image::DynamicImage,
     src: &image::DynamicImage,
     transform: Vec2,
+    opacity: f64,
+    mode: BlendMode,
+) {
+    let full = Rect::from_origin_size(Point::ZERO, dest.paint_size().unwrap());
+    merge_image_in(dest, src, transform, opacity, mode, full);
+}
+
+/// Merge only the pixels of `src` inside `clip`, which is in the coordinates of `dest`
+pub(crate) fn merge_image_in(
+    dest: &mut image::DynamicImage,
+    src: &image::DynamicImage,
+    transform: Vec2,
+    opacity: f64,
+    mode: BlendMode,
+    clip: Rect,
 ) {
     let src_size = src.paint_size().unwrap();
     let dest_size = dest.paint_size().unwrap();
 
-    let full = Rect::from_origin_size(Point::ZERO, dest_size);
+    let full = Rect::from_origin_size(Point::ZERO, dest_size).intersect(clip.round());
     let rt = Rect::from_origin_size(transform.to_point(), src_size);
     let rt = rt.intersect(full);
+    if rt.area() == 0.0 {
+        return;
+    }
 
     let origin = rt.origin().to_vec2();
     let offset = origin - transform;
@@ -56,7 +80,13 @@ pub(crate) fn merge_image(
     let section =
         src.view(offset.x as u32, offset.y as u32, rt.size().width as u32, rt.size().height as u32);
 
-    blend_from(dest, &section, origin.x as u32, origin.y as u32).expect("The size is invalid");
+    blend_from(dest, &section, origin.x as u32, origin.y as u32, opacity, mode)
+        .expect("The size is invalid");
+}
+
+pub(crate) fn color_to_rgba(color: &Color) -> Rgba<u8> {
+    let (r, g, b, a) = color.as_rgba8();
+    Rgba([r, g, b, a])
 }
 
 pub(crate) fn make_color_img(w: u32, h: u32, color: Rgba<u8>) -> DynamicImage {
@@ -0,0 +1,576 @@
+use crate::image_utils;
+use crate::plane::{DrawPlane, Plane};
+use crate::{BlendMode, BrushSettings, CopyMode, Paintable, Selection};
+use druid::{Data, Point, Rect, Size, Vec2};
+use image::{DynamicImage, GenericImage, GenericImageView, Rgba};
+
+use std::sync::Arc;
+
+/// Identify a layer, it stays the same when layers are reordered
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Data)]
+pub struct LayerId(pub(crate) usize);
+
+/// The locks of a layer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayerLock {
+    /// The pixels of the layer cannot be changed
+    Pixels,
+    /// The layer cannot be moved
+    Position,
+}
+
+#[derive(Debug, Clone)]
+pub struct Layer {
+    id: LayerId,
+    name: Arc<String>,
+    inner: Arc<Plane>,
+    transform: Vec2,
+    opacity: f64,
+    blend_mode: BlendMode,
+    visible: bool,
+    lock_pixels: bool,
+    lock_position: bool,
+}
+
+impl Layer {
+    pub fn id(&self) -> LayerId {
+        self.id
+    }
+
+    pub fn name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    pub fn position(&self) -> Point {
+        self.transform.to_point()
+    }
+
+    pub fn size(&self) -> Size {
+        self.inner.paint_size().unwrap_or_default()
+    }
+
+    pub fn image(&self) -> Arc<DynamicImage> {
+        self.inner.image()
+    }
+
+    /// Opacity of the layer, from 0.0 (0%) to 1.0 (100%)
+    pub fn opacity(&self) -> f64 {
+        self.opacity
+    }
+
+    pub fn blend_mode(&self) -> BlendMode {
+        self.blend_mode
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    pub fn is_locked(&self, lock: LayerLock) -> bool {
+        match lock {
+            LayerLock::Pixels => self.lock_pixels,
+            LayerLock::Position => self.lock_position,
+        }
+    }
+
+    /// Whether the pixels of the layer can be changed by tools
+    pub fn is_editable(&self) -> bool {
+        self.visible && !self.lock_pixels
+    }
+
+    pub(crate) fn new(id: LayerId, name: String, plane: Plane, transform: Vec2) -> Layer {
+        Layer {
+            id,
+            name: Arc::new(name),
+            inner: Arc::new(plane),
+            transform,
+            opacity: 1.0,
+            blend_mode: BlendMode::Normal,
+            visible: true,
+            lock_pixels: false,
+            lock_position: false,
+        }
+    }
+
+    fn bounds(&self) -> Rect {
+        Rect::from_origin_size(self.position(), self.size())
+    }
+}
+
+impl PartialEq for Layer {
+    fn eq(&self, other: &Layer) -> bool {
+        self.id == other.id
+            && self.name == other.name
+            && self.transform == other.transform
+            && self.opacity == other.opacity
+            && self.blend_mode == other.blend_mode
+            && self.visible == other.visible
+            && self.lock_pixels == other.lock_pixels
+            && self.lock_position == other.lock_position
+            && Arc::ptr_eq(&self.inner, &other.inner)
+    }
+}
+
+/// A stack of layers, from bottom to top
+///
+/// It contains at least one layer and one of them is the active layer.
+#[derive(Debug, Clone)]
+pub(crate) struct Layers {
+    layers: Vec<Layer>,
+    active: LayerId,
+    next_id: usize,
+}
+
+impl Eq for Layers {}
+impl PartialEq for Layers {
+    fn eq(&self, other: &Layers) -> bool {
+        self.active == other.active && self.layers == other.layers
+    }
+}
+impl Data for Layers {
+    fn same(&self, other: &Self) -> bool {
+        self == other
+    }
+}
+
+impl Layers {
+    pub(crate) fn new(plane: impl Into<Plane>) -> Layers {
+        let background = Layer::new(LayerId(0), "Background".into(), plane.into(), Vec2::ZERO);
+        Layers { active: background.id, layers: vec![background], next_id: 1 }
+    }
+
+    /// Restore a stack of layers, it fails if there is no layer or `active` is not found
+    pub(crate) fn from_layers(layers: Vec<Layer>, active: LayerId) -> Option<Layers> {
+        let next_id = layers.iter().map(|it| it.id.0 + 1).max()?;
+        let layers = Layers { layers, active, next_id };
+        layers.index_of(active)?;
+        Some(layers)
+    }
+
+    pub(crate) fn layers(&self) -> &[Layer] {
+        &self.layers
+    }
+
+    pub(crate) fn get(&self, id: LayerId) -> Option<&Layer> {
+        self.layers.iter().find(|it| it.id == id)
+    }
+
+    fn index_of(&self, id: LayerId) -> Option<usize> {
+        self.layers.iter().position(|it| it.id == id)
+    }
+
+    pub(crate) fn active(&self) -> LayerId {
+        self.active
+    }
+
+    fn active_index(&self) -> usize {
+        self.index_of(self.active).expect("Active layer must exist")
+    }
+
+    pub(crate) fn set_active(&mut self, id: LayerId) -> bool {
+        if self.index_of(id).is_none() {
+            return false;
+        }
+        self.active = id;
+        true
+    }
+
+    /// Approximate memory used by the pixels of the layers, in bytes
+    pub(crate) fn data_size(&self) -> usize {
+        self.layers.iter().map(|it| it.inner.with_image(|img| img.as_bytes().len())).sum()
+    }
+
+    pub(crate) fn max_size(&self) -> Option<Size> {
+        return self.layers.iter().fold(None, |acc, layer| {
+            let size = layer.inner.paint_size();
+            match (acc, size) {
+                (None, _) => size,
+                (Some(_), None) => acc,
+                (Some(acc), Some(size)) => {
+                    Some((acc.width.max(size.width), acc.height.max(size.height)).into())
+                }
+            }
+        });
+    }
+
+    /// Insert a new layer above the active layer and make it active
+    pub(crate) fn insert(
+        &mut self,
+        name: Option<String>,
+        plane: impl Into<Plane>,
+        transform: Vec2,
+    ) -> LayerId {
+        let id = self.alloc_id();
+        let name = name.unwrap_or_else(|| format!("Layer {}", id.0));
+        self.insert_layer(Layer::new(id, name, plane.into(), transform))
+    }
+
+    fn alloc_id(&mut self) -> LayerId {
+        let id = LayerId(self.next_id);
+        self.next_id += 1;
+        id
+    }
+
+    fn insert_layer(&mut self, layer: Layer) -> LayerId {
+        let id = layer.id;
+        let idx = self.active_index() + 1;
+        self.layers.insert(idx, layer);
+        self.active = id;
+        id
+    }
+
+    /// Remove a layer, the last remaining layer cannot be removed
+    pub(crate) fn remove(&mut self, id: LayerId) -> bool {
+        let idx = match self.index_of(id) {
+            Some(idx) if self.layers.len() > 1 => idx,
+            _ => return false,
+        };
+
+        self.layers.remove(idx);
+        if self.active == id {
+            self.active = self.layers[idx.saturating_sub(1)].id;
+        }
+        true
+    }
+
+    /// Move a layer to `index` of the stack, 0 is the bottom
+    pub(crate) fn reorder(&mut self, id: LayerId, index: usize) -> bool {
+        let idx = match self.index_of(id) {
+            Some(idx) => idx,
+            None => return false,
+        };
+
+        let layer = self.layers.remove(idx);
+        let index = index.min(self.layers.len());
+        self.layers.insert(index, layer);
+        idx != index
+    }
+
+    pub(crate) fn duplicate(&mut self, id: LayerId) -> Option<LayerId> {
+        let layer = self.get(id)?.clone();
+        self.active = id;
+
+        let layer = Layer {
+            id: self.alloc_id(),
+            name: Arc::new(format!("{} copy", layer.name)),
+            inner: Arc::new(Plane::Image(layer.image())),
+            ..layer
+        };
+        Some(self.insert_layer(layer))
+    }
+
+    /// Merge a layer into the layer below it, return the merged layer
+    pub(crate) fn merge_down(&mut self, id: LayerId) -> Option<LayerId> {
+        let idx = self.index_of(id)?;
+        if idx == 0 {
+            return None;
+        }
+
+        let (lower, upper) = (&self.layers[idx - 1], &self.layers[idx]);
+        if lower.lock_pixels {
+            return None;
+        }
+
+        let bounds = lower.bounds().union(upper.bounds());
+        let origin = bounds.origin().to_vec2();
+
+        let mut img = image_utils::transparent_image(bounds.width() as u32, bounds.height() as u32);
+        let transform = lower.transform - origin;
+        image_utils::merge_image(&mut img, &lower.image(), transform, 1.0, BlendMode::Normal);
+        if upper.visible {
+            let transform = upper.transform - origin;
+            let (opacity, mode) = (upper.opacity, upper.blend_mode);
+            image_utils::merge_image(&mut img, &upper.image(), transform, opacity, mode);
+        }
+
+        let lower = &mut self.layers[idx - 1];
+        lower.inner = Arc::new(Plane::Image(Arc::new(img)));
+        lower.transform = origin;
+        let lower_id = lower.id;
+
+        self.layers.remove(idx);
+        if self.active == id {
+            self.active = lower_id;
+        }
+        Some(lower_id)
+    }
+
+    pub(crate) fn rename(&mut self, id: LayerId, name: &str) -> bool {
+        self.update(id, |layer| layer.name = Arc::new(name.into()))
+    }
+
+    pub(crate) fn set_opacity(&mut self, id: LayerId, opacity: f64) -> bool {
+        self.update(id, |layer| layer.opacity = opacity.clamp(0.0, 1.0))
+    }
+
+    pub(crate) fn set_blend_mode(&mut self, id: LayerId, mode: BlendMode) -> bool {
+        self.update(id, |layer| layer.blend_mode = mode)
+    }
+
+    pub(crate) fn set_visible(&mut self, id: LayerId, visible: bool) -> bool {
+        self.update(id, |layer| layer.visible = visible)
+    }
+
+    pub(crate) fn set_lock(&mut self, id: LayerId, lock: LayerLock, locked: bool) -> bool {
+        self.update(id, |layer| match lock {
+            LayerLock::Pixels => layer.lock_pixels = locked,
+            LayerLock::Position => layer.lock_position = locked,
+        })
+    }
+
+    /// Replace the pixels of a layer, it stops any stroke being drawn on it
+    pub(crate) fn set_image(&mut self, id: LayerId, img: Arc<DynamicImage>) -> bool {
+        self.update(id, |layer| layer.inner = Arc::new(Plane::Image(img)))
+    }
+
+    fn update(&mut self, id: LayerId, f: impl FnOnce(&mut Layer)) -> bool {
+        match self.index_of(id) {
+            Some(idx) => {
+                f(&mut self.layers[idx]);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Merge all visible layers, ignore negative transform
+    pub(crate) fn merged(&self) -> Option<Arc<DynamicImage>> {
+        let size = self.max_size()?;
+        let img = image_utils::transparent_image(size.width as u32, size.height as u32);
+        Some(self.merged_to(img, Vec2::ZERO))
+    }
+
+    pub(crate) fn merged_to(&self, mut img: DynamicImage, transform: Vec2) -> Arc<DynamicImage> {
+        for layer in self.layers.iter().filter(|it| it.visible) {
+            let transform = layer.transform + transform;
+            let (opacity, mode) = (layer.opacity, layer.blend_mode);
+            layer.inner.with_image(|src| {
+                image_utils::merge_image(&mut img, src, transform, opacity, mode)
+            });
+        }
+        Arc::new(img)
+    }
+
+    /// Recomposite the pixels of `img` inside `clip`, as `merged_to` would have
+    pub(crate) fn merge_region(&self, img: &mut DynamicImage, transform: Vec2, clip: Rect) {
+        let full = Rect::new(0.0, 0.0, img.width() as f64, img.height() as f64);
+        let rt = full.intersect(clip.round());
+        for y in rt.y0 as u32..rt.y1 as u32 {
+            for x in rt.x0 as u32..rt.x1 as u32 {
+                img.put_pixel(x, y, image_utils::colors::TRANSPARENT);
+            }
+        }
+
+        for layer in self.layers.iter().filter(|it| it.visible) {
+            let transform = layer.transform + transform;
+            let (opacity, mode) = (layer.opacity, layer.blend_mode);
+            layer.inner.with_image(|src| {
+                image_utils::merge_image_in(img, src, transform, opacity, mode, rt)
+            });
+        }
+    }
+
+    /// Bounds of the pixels changed since `old`, if only strokes were drawn on its layers
+    pub(crate) fn changed_since(&self, old: &Layers) -> Option<Rect> {
+        if self.layers.len() != old.layers.len() {
+            return None;
+        }
+
+        let mut changed: Option<Rect> = None;
+        for (layer, old) in self.layers.iter().zip(old.layers.iter()) {
+            // Only the pixels of a plane may be changed, by continuing a stroke
+            let attributes = Layer { inner: old.inner.clone(), ..layer.clone() };
+            if attributes != *old {
+                return None;
+            }
+            if Arc::ptr_eq(&layer.inner, &old.inner) {
+                continue;
+            }
+
+            let rt = match (layer.inner.as_ref(), old.inner.as_ref()) {
+                (Plane::Draw(it), Plane::Draw(old)) => it.changed_since(old)?,
+                _ => return None,
+            };
+            if layer.visible && rt.area() > 0.0 {
+                let rt = rt + layer.transform;
+                changed = Some(changed.map_or(rt, |it| it.union(rt)));
+            }
+        }
+        Some(changed.unwrap_or(Rect::ZERO))
+    }
+
+    pub(crate) fn move_layer(&mut self, id: LayerId, offset: Vec2) -> bool {
+        match self.index_of(id) {
+            Some(idx) if !self.layers[idx].lock_position => {
+                self.layers[idx].transform += offset;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Cut out the selection from the active layer to a new floating layer
+    pub(crate) fn bind_selection(&mut self, sel: &Selection) -> Option<LayerId> {
+        let idx = self.active_index();
+        let layer = &mut self.layers[idx];
+        if !layer.is_editable() {
+            return None;
+        }
+
+        let target = sel.transform(-layer.transform);
+        let img = layer.inner.image();
+
+        let cutout = target.copy(img.clone(), CopyMode::Expand).unwrap_or_else(|| {
+            let size = sel.bounds().size();
+            Arc::new(image_utils::transparent_image(size.width as u32, size.height as u32))
+        });
+
+        if let Some(it) = target.cutout(img) {
+            layer.inner = Arc::new(Plane::Image(it));
+        }
+
+        let name = Some("Floating Selection".to_string());
+        Some(self.insert(name, Plane::Image(cutout), sel.position().to_vec2()))
+    }
+
+    /// Fill the active layer inside the selection, or the whole layer without a selection
+    pub(crate) fn fill(&mut self, sel: Option<&Selection>, color: Rgba<u8>) -> bool {
+        let idx = self.active_index();
+        let layer = &mut self.layers[idx];
+        if !layer.is_editable() {
+            return false;
+        }
+
+        let mut img = layer.image().as_ref().clone();
+        let (w, h) = img.dimensions();
+        for y in 0..h {
+            for x in 0..w {
+                let pt = Point::new(x as f64 + 0.5, y as f64 + 0.5) + layer.transform;
+                if sel.map_or(true, |sel| sel.contains(pt)) {
+                    let p = BlendMode::Normal.composite(img.get_pixel(x, y), color, 1.0);
+                    img.put_pixel(x, y, p);
+                }
+            }
+        }
+
+        layer.inner = Arc::new(Plane::Image(Arc::new(img)));
+        true
+    }
+
+    /// Draw on the active layer, `pos` is relative to the layers origin
+    pub(crate) fn draw_with_brush(
+        &mut self,
+        pos: &[Vec2],
+        brush: &BrushSettings,
+        color: Rgba<u8>,
+        new_stroke: bool,
+    ) {
+        let idx = self.active_index();
+        let layer = &mut self.layers[idx];
+        if !layer.is_editable() {
+            return;
+        }
+
+        let pos: Vec<_> = pos.iter().map(|p| *p - layer.transform).collect();
+        let plane = match layer.inner.as_ref() {
+            Plane::Draw(plane) if !new_stroke => plane.draw(&pos, brush, color),
+            _ => None,
+        };
+
+        // The pixels before the stroke are copied once, they may be shared with the undo history
+        let plane = plane.unwrap_or_else(|| {
+            let plane = DrawPlane::new(layer.inner.image(), *brush, color);
+            plane.draw(&pos, brush, color).expect("A new stroke must be drawable")
+        });
+        layer.inner = Arc::new(Plane::Draw(plane));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::image_utils::{
+        colors::{BLACK, TRANSPARENT, WHITE},
+        make_color_img,
+    };
+    use image::GenericImageView;
+
+    fn layers_fixture() -> Layers {
+        Layers::new(Plane::Image(Arc::new(make_color_img(8, 8, WHITE))))
+    }
+
+    #[test]
+    fn layers_insert_should_be_above_active() {
+        let mut layers = layers_fixture();
+        let background = layers.active();
+        let first =
+            layers.insert(None, Plane::Image(Arc::new(make_color_img(2, 2, BLACK))), Vec2::ZERO);
+        layers.set_active(background);
+        let second =
+            layers.insert(None, Plane::Image(Arc::new(make_color_img(2, 2, BLACK))), Vec2::ZERO);
+
+        let ids: Vec<_> = layers.layers().iter().map(|it| it.id()).collect();
+        assert_eq!(ids, vec![background, second, first]);
+        assert_eq!(layers.active(), second);
+        assert_eq!(layers.get(first).unwrap().name(), "Layer 1");
+    }
+
+    #[test]
+    fn layers_remove_should_keep_last_layer() {
+        let mut layers = layers_fixture();
+        let background = layers.active();
+        let id =
+            layers.insert(None, Plane::Image(Arc::new(make_color_img(2, 2, BLACK))), Vec2::ZERO);
+
+        assert!(layers.remove(id));
+        assert_eq!(layers.active(), background);
+        assert!(!layers.remove(background));
+        assert_eq!(layers.layers().len(), 1);
+    }
+
+    #[test]
+    fn layers_reorder_should_works() {
+        let mut layers = layers_fixture();
+        let background = layers.active();
+        let id =
+            layers.insert(None, Plane::Image(Arc::new(make_color_img(2, 2, BLACK))), Vec2::ZERO);
+
+        assert!(layers.reorder(id, 0));
+        assert_eq!(layers.layers()[0].id(), id);
+        assert_eq!(layers.layers()[1].id(), background);
+        assert_eq!(layers.merged().unwrap().get_pixel(0, 0), WHITE);
+    }
+
+    #[test]
+    fn layers_duplicate_should_works() {
+        let mut layers = layers_fixture();
+        let background = layers.active();
+        let id = layers.duplicate(background).unwrap();
+
+        assert_ne!(id, background);
+        assert_eq!(layers.get(id).unwrap().name(), "Background copy");
+        assert_eq!(layers.layers().len(), 2);
+    }
+
+    #[test]
+    fn layers_merge_down_should_works() {
+        let mut layers = layers_fixture();
+        let background = layers.active();
+        let id = layers.insert(
+            None,
+            Plane::Image(Arc::new(make_color_img(4, 4, BLACK))),
+            Vec2::new(6.0, 6.0),
+        );
+
+        assert_eq!(layers.merge_down(id), Some(background));
+        assert_eq!(layers.layers().len(), 1);
+        assert_eq!(layers.active(), background);
+
+        let merged = layers.get(background).unwrap().image();
+        assert_eq!(merged.dimensions(), (10, 10));
+        assert_eq!(merged.get_pixel(0, 0), WHITE);
+        assert_eq!(merged.get_pixel(7, 7), BLACK);
+        assert_eq!(merged.get_pixel(9, 0), TRANSPARENT);
+        assert_eq!(layers.merge_down(background), None);
+    }
+}
@@ -11,22 +11,36 @@ macro_rules! impl_from {
     }
 }
 
+mod blend;
+mod brush;
 mod canvas;
 mod clipboard;
+mod delta;
+mod document;
 mod edit;
+mod export;
 mod image_utils;
+mod layer;
+mod ora;
 mod paintable;
 mod plane;
+mod psd;
 mod selections;
 
 #[cfg(test)]
 mod test_utils;
 
-pub use canvas::CanvasData;
+pub use blend::BlendMode;
+pub use brush::BrushSettings;
+pub use canvas::{CanvasData, CanvasDelta};
 pub use clipboard::{get_image_from_clipboard, put_image_to_clipboard, ClipboardError};
-pub use edit::{Edit, EditDesc, EditKind, UndoHistory};
+pub use edit::{Diff, Edit, EditDesc, EditKind, HistoryEntry, UndoHistory};
+pub use export::{ExportFormat, ExportOptions, PngCompression, PngFilter};
+pub use layer::{Layer, LayerId, LayerLock};
 pub use paintable::Paintable;
-pub use selections::{CopyMode, Selection};
+pub use selections::{
+    CopyMode, Ellipse, MagicWandSettings, Mask, Selection, SelectionMode, SelectionModify,
+};
 pub mod actions;
 pub use image_utils::colors;
 
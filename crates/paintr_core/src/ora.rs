@@ -0,0 +1,371 @@
+//! OpenRaster format
+//!
+//! See <https://www.freedesktop.org/wiki/Specifications/OpenRaster/>, only raster layers are
+//! supported, the layers of nested stacks are flattened into one stack.
+use druid::{Size, Vec2};
+use image::{DynamicImage, GenericImageView};
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use std::io::{Read, Seek, Write};
+use std::path::Path;
+use std::sync::Arc;
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::document::{encode_png, has_extension, read_entry, read_png};
+use crate::layer::{Layer, LayerId, Layers};
+use crate::plane::Plane;
+use crate::{BlendMode, CanvasData, Paintable};
+use anyhow::{anyhow, Result};
+
+const EXTENSION: &str = "ora";
+const MIMETYPE: &str = "image/openraster";
+const THUMBNAIL_SIZE: u32 = 256;
+
+const MIMETYPE_ENTRY: &str = "mimetype";
+const STACK_ENTRY: &str = "stack.xml";
+const MERGED_ENTRY: &str = "mergedimage.png";
+const THUMBNAIL_ENTRY: &str = "Thumbnails/thumbnail.png";
+
+const COMPOSITE_OPS: [(BlendMode, &str); 16] = [
+    (BlendMode::Normal, "svg:src-over"),
+    (BlendMode::Multiply, "svg:multiply"),
+    (BlendMode::Screen, "svg:screen"),
+    (BlendMode::Overlay, "svg:overlay"),
+    (BlendMode::Darken, "svg:darken"),
+    (BlendMode::Lighten, "svg:lighten"),
+    (BlendMode::ColorDodge, "svg:color-dodge"),
+    (BlendMode::ColorBurn, "svg:color-burn"),
+    (BlendMode::HardLight, "svg:hard-light"),
+    (BlendMode::SoftLight, "svg:soft-light"),
+    (BlendMode::Difference, "svg:difference"),
+    (BlendMode::Exclusion, "svg:exclusion"),
+    (BlendMode::Hue, "svg:hue"),
+    (BlendMode::Saturation, "svg:saturation"),
+    (BlendMode::Color, "svg:color"),
+    (BlendMode::Luminosity, "svg:luminosity"),
+];
+
+fn composite_op(mode: BlendMode) -> &'static str {
+    COMPOSITE_OPS.iter().find(|(it, _)| *it == mode).map_or("svg:src-over", |(_, op)| op)
+}
+
+/// Unsupported operators fall back to normal
+fn blend_mode(op: &str) -> BlendMode {
+    COMPOSITE_OPS.iter().find(|(_, it)| *it == op).map_or(BlendMode::Normal, |(mode, _)| *mode)
+}
+
+pub(crate) fn is_ora(path: &Path) -> bool {
+    has_extension(path, EXTENSION)
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// A `layer` element of `stack.xml`
+#[derive(Debug)]
+struct LayerEntry {
+    name: String,
+    src: String,
+    x: f64,
+    y: f64,
+    opacity: f64,
+    visible: bool,
+    blend_mode: BlendMode,
+}
+
+impl LayerEntry {
+    fn new(reader: &Reader<&[u8]>, e: &BytesStart) -> Result<LayerEntry> {
+        let mut entry = LayerEntry {
+            name: String::new(),
+            src: String::new(),
+            x: 0.0,
+            y: 0.0,
+            opacity: 1.0,
+            visible: true,
+            blend_mode: BlendMode::Normal,
+        };
+        for attr in e.attributes() {
+            let attr = attr?;
+            let value = attr.unescape_and_decode_value(reader)?;
+            match attr.key {
+                b"name" => entry.name = value,
+                b"src" => entry.src = value,
+                b"x" => entry.x = value.parse()?,
+                b"y" => entry.y = value.parse()?,
+                b"opacity" => entry.opacity = parse_opacity(&value)?,
+                b"visibility" => entry.visible = value != "hidden",
+                b"composite-op" => entry.blend_mode = blend_mode(&value),
+                _ => (),
+            }
+        }
+        Ok(entry)
+    }
+}
+
+fn parse_opacity(value: &str) -> Result<f64> {
+    let opacity: f64 = value.parse()?;
+    Ok(if opacity.is_nan() { 1.0 } else { opacity.max(0.0).min(1.0) })
+}
+
+/// The attributes of a stack which apply to the layers in it
+#[derive(Clone, Copy)]
+struct StackEntry {
+    /// The offset relative to the image
+    offset: Vec2,
+    opacity: f64,
+    visible: bool,
+}
+
+/// The size of the image and its layers from top to bottom
+fn parse_stack(xml: &str) -> Result<(Size, Vec<LayerEntry>)> {
+    let mut reader = Reader::from_str(xml);
+    reader.trim_text(true);
+
+    let mut size = None;
+    let mut layers = Vec::new();
+    // The enclosing stacks, each one combined with its own enclosing stacks
+    let root = StackEntry { offset: Vec2::ZERO, opacity: 1.0, visible: true };
+    let mut stacks = vec![root];
+    let mut buf = Vec::new();
+    loop {
+        match reader.read_event(&mut buf)? {
+            Event::Start(ref e) if e.name() == b"stack" => {
+                let mut stack = stacks.last().copied().unwrap_or(root);
+                for attr in e.attributes() {
+                    let attr = attr?;
+                    let value = attr.unescape_and_decode_value(&reader)?;
+                    match attr.key {
+                        b"x" => stack.offset.x += value.parse::<f64>()?,
+                        b"y" => stack.offset.y += value.parse::<f64>()?,
+                        b"opacity" => stack.opacity *= parse_opacity(&value)?,
+                        b"visibility" => stack.visible &= value != "hidden",
+                        _ => (),
+                    }
+                }
+                stacks.push(stack);
+            }
+            Event::End(ref e) if e.name() == b"stack" => {
+                stacks.pop();
+            }
+            Event::Start(ref e) | Event::Empty(ref e) => match e.name() {
+                b"image" => {
+                    let (mut w, mut h) = (0.0, 0.0);
+                    for attr in e.attributes() {
+                        let attr = attr?;
+                        match attr.key {
+                            b"w" => w = attr.unescape_and_decode_value(&reader)?.parse()?,
+                            b"h" => h = attr.unescape_and_decode_value(&reader)?.parse()?,
+                            _ => (),
+                        }
+                    }
+                    size = Some(Size::new(w, h));
+                }
+                b"layer" => {
+                    let mut entry = LayerEntry::new(&reader, e)?;
+                    let stack = stacks.last().copied().unwrap_or(root);
+                    entry.x += stack.offset.x;
+                    entry.y += stack.offset.y;
+                    entry.opacity *= stack.opacity;
+                    entry.visible &= stack.visible;
+                    layers.push(entry);
+                }
+                _ => (),
+            },
+            Event::Eof => break,
+            _ => (),
+        }
+        buf.clear();
+    }
+
+    let size = size.filter(|it| it.width > 0.0 && it.height > 0.0);
+    Ok((size.ok_or_else(|| anyhow!("Invalid image size in {}", STACK_ENTRY))?, layers))
+}
+
+fn write_stack(canvas: &CanvasData) -> String {
+    let size = canvas.paint_size().unwrap_or_default();
+    let mut xml = String::from("<?xml version='1.0' encoding='UTF-8'?>\n");
+    xml += &format!("<image version=\"0.0.5\" w=\"{}\" h=\"{}\">\n", size.width, size.height);
+    xml += "  <stack>\n";
+    for layer in canvas.layers().iter().rev() {
+        // The canvas offset is folded into the layers, ORA only has integer offsets
+        let pos = layer.position() + canvas.position().to_vec2();
+        xml += &format!(
+            "    <layer name=\"{}\" src=\"data/{}.png\" x=\"{}\" y=\"{}\" opacity=\"{}\" \
+             visibility=\"{}\" composite-op=\"{}\"/>\n",
+            escape(layer.name()),
+            layer.id().0,
+            pos.x.round(),
+            pos.y.round(),
+            layer.opacity(),
+            if layer.is_visible() { "visible" } else { "hidden" },
+            composite_op(layer.blend_mode()),
+        );
+    }
+    xml += "  </stack>\n</image>\n";
+    xml
+}
+
+/// Write the canvas as an OpenRaster image
+pub(crate) fn write<W: Write + Seek>(canvas: &CanvasData, writer: W) -> Result<()> {
+    let merged = canvas.merged();
+    let thumbnail = match merged.width() > THUMBNAIL_SIZE || merged.height() > THUMBNAIL_SIZE {
+        true => merged.thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE),
+        false => merged.as_ref().clone(),
+    };
+
+    let mut zip = ZipWriter::new(writer);
+    let stored = FileOptions::default().compression_method(CompressionMethod::Stored);
+    zip.start_file(MIMETYPE_ENTRY, stored)?;
+    zip.write_all(MIMETYPE.as_bytes())?;
+
+    zip.start_file(STACK_ENTRY, FileOptions::default())?;
+    zip.write_all(write_stack(canvas).as_bytes())?;
+
+    for layer in canvas.layers() {
+        zip.start_file(format!("data/{}.png", layer.id().0), stored)?;
+        zip.write_all(&encode_png(&layer.image())?)?;
+    }
+
+    zip.start_file(MERGED_ENTRY, stored)?;
+    zip.write_all(&encode_png(&merged)?)?;
+    zip.start_file(THUMBNAIL_ENTRY, stored)?;
+    zip.write_all(&encode_png(&thumbnail)?)?;
+
+    zip.finish()?;
+    Ok(())
+}
+
+/// Read an OpenRaster image, the top layer is active
+pub(crate) fn read<R: Read + Seek>(path: &Path, reader: R) -> Result<CanvasData> {
+    let mut archive = ZipArchive::new(reader)?;
+    let stack = String::from_utf8(read_entry(&mut archive, STACK_ENTRY)?)?;
+    let (size, entries) = parse_stack(&stack)?;
+
+    let mut layers = Vec::new();
+    for (index, entry) in entries.iter().rev().enumerate() {
+        let img = DynamicImage::ImageRgba8(read_png(&mut archive, &entry.src)?.into_rgba8());
+        let name = match entry.name.is_empty() {
+            true => format!("Layer {}", index),
+            false => entry.name.clone(),
+        };
+        let pos = Vec2::new(entry.x, entry.y);
+        layers.push(Layer::new(LayerId(index), name, Plane::Image(Arc::new(img)), pos));
+    }
+
+    let top = LayerId(layers.len().saturating_sub(1));
+    let mut layers = Layers::from_layers(layers, top)
+        .ok_or_else(|| anyhow!("No layer was found in {}", STACK_ENTRY))?;
+    for (index, entry) in entries.iter().rev().enumerate() {
+        layers.set_opacity(LayerId(index), entry.opacity);
+        layers.set_blend_mode(LayerId(index), entry.blend_mode);
+        layers.set_visible(LayerId(index), entry.visible);
+    }
+
+    Ok(CanvasData::from_layers(path, layers, size, Vec2::ZERO))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::image_utils::colors::*;
+    use crate::test_utils::canvas_fixture;
+    use std::io::Cursor;
+
+    fn round_trip(canvas: &CanvasData) -> CanvasData {
+        let mut buf = Cursor::new(Vec::new());
+        write(canvas, &mut buf).unwrap();
+        buf.set_position(0);
+        read(Path::new("test.ora"), buf).unwrap()
+    }
+
+    #[test]
+    fn ora_should_keep_layers() {
+        let mut canvas = canvas_fixture(10, 8, WHITE);
+        let bottom = canvas.active_layer();
+        let top = canvas.insert_layer();
+        canvas.rename_layer(top, "<Top & \"Bottom\">");
+        canvas.set_layer_opacity(top, 0.5);
+        canvas.set_layer_blend_mode(top, BlendMode::Screen);
+        canvas.set_layer_visible(bottom, false);
+        canvas.move_layer(top, Vec2::new(3.0, -2.0));
+
+        let opened = round_trip(&canvas);
+        assert_eq!(opened.paint_size(), Some(Size::new(10.0, 8.0)));
+        assert_eq!(opened.layers().len(), 2);
+
+        let (bottom, top) = (&opened.layers()[0], &opened.layers()[1]);
+        assert_eq!(opened.active_layer(), top.id());
+        assert_eq!(bottom.name(), "Background");
+        assert!(!bottom.is_visible());
+        assert_eq!(bottom.image().get_pixel(3, 3), WHITE);
+        assert_eq!(top.name(), "<Top & \"Bottom\">");
+        assert_eq!(top.opacity(), 0.5);
+        assert_eq!(top.blend_mode(), BlendMode::Screen);
+        assert_eq!(top.position(), druid::Point::new(3.0, -2.0));
+        assert_eq!(top.size(), Size::new(10.0, 8.0));
+    }
+
+    #[test]
+    fn ora_should_write_merged_image_and_thumbnail() {
+        let canvas = canvas_fixture(300, 150, BLACK);
+        let mut buf = Cursor::new(Vec::new());
+        write(&canvas, &mut buf).unwrap();
+
+        let mut archive = ZipArchive::new(buf).unwrap();
+        assert_eq!(read_entry(&mut archive, MIMETYPE_ENTRY).unwrap(), MIMETYPE.as_bytes());
+        let merged = read_png(&mut archive, MERGED_ENTRY).unwrap();
+        assert_eq!(merged.dimensions(), (300, 150));
+        assert_eq!(merged.get_pixel(10, 10), BLACK);
+        let thumbnail = read_png(&mut archive, THUMBNAIL_ENTRY).unwrap();
+        assert_eq!(thumbnail.dimensions(), (256, 128));
+    }
+
+    #[test]
+    fn ora_should_read_defaults_and_unknown_ops() {
+        let xml = r#"<image w="4" h="2"><stack>
+            <layer src="data/a.png" composite-op="svg:dst-in" visibility="hidden"/>
+            <stack x="1"><layer name="B" src="data/b.png" x="2" opacity="0.25"/></stack>
+        </stack></image>"#;
+        let (size, layers) = parse_stack(xml).unwrap();
+        assert_eq!(size, Size::new(4.0, 2.0));
+        assert_eq!(layers.len(), 2);
+        assert_eq!(layers[0].blend_mode, BlendMode::Normal);
+        assert!(!layers[0].visible);
+        assert_eq!((layers[1].name.as_str(), layers[1].x, layers[1].opacity), ("B", 3.0, 0.25));
+    }
+
+    #[test]
+    fn ora_should_clamp_opacity() {
+        let xml = r#"<image w="4" h="2"><stack>
+            <layer src="data/a.png" opacity="1.5"/>
+            <layer src="data/b.png" opacity="-0.5"/>
+            <layer src="data/c.png" opacity="NaN"/>
+        </stack></image>"#;
+        let (_, layers) = parse_stack(xml).unwrap();
+        let opacity: Vec<_> = layers.iter().map(|it| it.opacity).collect();
+        assert_eq!(opacity, vec![1.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn ora_should_apply_nested_stacks() {
+        let xml = r#"<image w="4" h="2"><stack>
+            <stack x="1" y="2" opacity="0.5">
+                <stack x="10" opacity="0.5" visibility="hidden">
+                    <layer src="data/a.png" x="2" y="1" opacity="0.5"/>
+                </stack>
+                <layer src="data/b.png" x="2"/>
+            </stack>
+            <stack x="5" visibility="hidden"/>
+            <layer src="data/c.png" x="2" opacity="0.5"/>
+        </stack></image>"#;
+        let (_, layers) = parse_stack(xml).unwrap();
+        let pos: Vec<_> = layers.iter().map(|it| (it.x, it.y)).collect();
+        assert_eq!(pos, vec![(13.0, 3.0), (3.0, 2.0), (2.0, 0.0)]);
+        let opacity: Vec<_> = layers.iter().map(|it| it.opacity).collect();
+        assert_eq!(opacity, vec![0.125, 0.5, 0.5]);
+        let visible: Vec<_> = layers.iter().map(|it| it.visible).collect();
+        assert_eq!(visible, vec![false, true, true]);
+    }
+}
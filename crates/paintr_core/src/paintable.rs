@@ -10,6 +10,11 @@ pub trait Paintable {
     fn paint_size(&self) -> Option<Size>;
 }
 
+/// The zoom of the current transform, which is never rotated
+pub(crate) fn paint_scale(paint_ctx: &PaintCtx) -> f64 {
+    paint_ctx.render_ctx.current_transform().as_coeffs()[0].abs()
+}
+
 impl Paintable for DynamicImage {
     fn paint(&self, paint_ctx: &mut PaintCtx) {
         let size = (self.width() as usize, self.height() as usize);
@@ -19,11 +24,16 @@ impl Paintable for DynamicImage {
             .render_ctx
             .make_image(size.0, size.1, &self.as_rgba8().unwrap(), ImageFormat::RgbaSeparate)
             .unwrap();
+        // Keep the pixels sharp when zoomed in
+        let interp = match paint_scale(paint_ctx) > 1.0 {
+            true => InterpolationMode::NearestNeighbor,
+            false => InterpolationMode::Bilinear,
+        };
         // The image is automatically scaled to fit the rect you pass to draw_image
         paint_ctx.render_ctx.draw_image(
             &image,
             Rect::from_origin_size(Point::ORIGIN, self.paint_size().unwrap()),
-            InterpolationMode::Bilinear,
+            interp,
         );
     }
 
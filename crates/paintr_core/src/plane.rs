@@ -1,28 +1,147 @@
-use crate::image_utils;
-use crate::{CopyMode, Paintable, Selection};
-use druid::{kurbo::Affine, PaintCtx};
-use druid::{Data, RenderContext, Size, Vec2};
-use image::{DynamicImage, GenericImageView};
-use imageproc::drawing;
+use crate::brush::{BrushSettings, Stroke};
+use crate::Paintable;
+use druid::PaintCtx;
+use druid::{Rect, Size, Vec2};
+use image::{DynamicImage, GenericImageView, Rgba};
 
-use std::{cell::RefCell, sync::Arc};
+use std::sync::{Arc, Mutex, MutexGuard};
 
-pub struct DrawPlane {
+/// The pixels of a stroke in progress, which are drawn in place
+struct StrokeBuffer {
     img: DynamicImage,
-    brush: Vec<Vec2>,
+    stroke: Stroke,
+    /// The number of times drawn on
+    version: usize,
+    /// Bounds of the pixels changed by each draw, `changes[v]` leads to version `v + 1`
+    changes: Vec<Rect>,
+    /// The positions of each draw, to draw an earlier version again
+    draws: Vec<Vec<Vec2>>,
+    /// The image of the latest version, taken on demand
+    snapshot: Option<Arc<DynamicImage>>,
+    /// The image of the earlier version which was asked for last
+    replayed: Option<(usize, Arc<DynamicImage>)>,
+}
+
+impl StrokeBuffer {
+    /// The pixels of an earlier `version`, drawn again from the pixels before the stroke
+    fn replay(&mut self, version: usize) -> Arc<DynamicImage> {
+        match &self.replayed {
+            Some((v, img)) if *v == version => return img.clone(),
+            _ => (),
+        }
+
+        let (mut stroke, mut img) = self.stroke.restart();
+        for p in self.draws[..version].iter().flatten() {
+            stroke.stroke_to(&mut img, *p);
+        }
+        let img = Arc::new(img);
+        self.replayed = Some((version, img.clone()));
+        img
+    }
+}
+
+/// A plane being drawn on by a stroke
+///
+/// Every clone shares the pixels of the stroke, each draw makes a new version of the plane
+/// instead of copying them. Clones of an earlier version, like the ones kept by the undo
+/// history, get their pixels by drawing the stroke again up to their version.
+#[derive(Clone)]
+pub struct DrawPlane {
+    buffer: Arc<Mutex<StrokeBuffer>>,
+    version: usize,
 }
 
 impl DrawPlane {
-    pub fn new(w: u32, h: u32) -> Self {
-        let img = image_utils::transparent_image(w, h);
+    /// Start a stroke on a copy of `base`
+    pub(crate) fn new(base: Arc<DynamicImage>, brush: BrushSettings, color: Rgba<u8>) -> Self {
+        let img = base.as_ref().clone();
+        let buffer = StrokeBuffer {
+            img,
+            stroke: Stroke::new(base, brush, color),
+            version: 0,
+            changes: Vec::new(),
+            draws: Vec::new(),
+            snapshot: None,
+            replayed: None,
+        };
+        DrawPlane { buffer: Arc::new(Mutex::new(buffer)), version: 0 }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, StrokeBuffer> {
+        self.buffer.lock().unwrap_or_else(|it| it.into_inner())
+    }
+
+    /// Continue the stroke as a new version of the plane
+    ///
+    /// It fails if the brush was changed or this is not the latest version of the stroke.
+    pub(crate) fn draw(
+        &self,
+        pos: &[Vec2],
+        brush: &BrushSettings,
+        color: Rgba<u8>,
+    ) -> Option<DrawPlane> {
+        let mut buffer = self.lock();
+        let buffer = &mut *buffer;
+        if buffer.version != self.version || !buffer.stroke.is_drawn_by(brush, color) {
+            return None;
+        }
+
+        for p in pos {
+            buffer.stroke.stroke_to(&mut buffer.img, *p);
+        }
+        buffer.changes.push(buffer.stroke.take_dirty().unwrap_or(Rect::ZERO));
+        buffer.draws.push(pos.to_vec());
+        buffer.version += 1;
+        buffer.snapshot = None;
+        Some(DrawPlane { buffer: self.buffer.clone(), version: buffer.version })
+    }
+
+    /// Call `f` with the pixels of the plane, without copying them
+    pub(crate) fn with_image<R>(&self, f: impl FnOnce(&DynamicImage) -> R) -> R {
+        let mut buffer = self.lock();
+        if buffer.version == self.version {
+            return f(&buffer.img);
+        }
+
+        let img = buffer.replay(self.version);
+        drop(buffer);
+        f(&img)
+    }
+
+    /// Bounds of the pixels changed since `old`, if it is an earlier version of the same stroke
+    pub(crate) fn changed_since(&self, old: &DrawPlane) -> Option<Rect> {
+        if !Arc::ptr_eq(&self.buffer, &old.buffer) || old.version > self.version {
+            return None;
+        }
 
-        Self { img, brush: vec![] }
+        let buffer = self.lock();
+        let changes = buffer.changes.get(old.version..self.version)?;
+        Some(changes.iter().filter(|it| it.area() > 0.0).fold(Rect::ZERO, |acc, it| {
+            if acc.area() > 0.0 {
+                acc.union(*it)
+            } else {
+                *it
+            }
+        }))
+    }
+
+    /// A copy of the pixels, which is kept until the plane is drawn on again
+    fn image(&self) -> Arc<DynamicImage> {
+        let mut buffer = self.lock();
+        let buffer = &mut *buffer;
+        if buffer.version != self.version {
+            return buffer.replay(self.version);
+        }
+
+        let img = &buffer.img;
+        buffer.snapshot.get_or_insert_with(|| Arc::new(img.clone())).clone()
     }
 }
 
+#[derive(Clone)]
 pub enum Plane {
     Image(Arc<DynamicImage>),
-    Draw(Arc<RefCell<DrawPlane>>),
+    Draw(DrawPlane),
 }
 
 impl std::fmt::Debug for Plane {
@@ -31,14 +150,9 @@ impl std::fmt::Debug for Plane {
             Plane::Image(img) => {
                 ("Image", format!("DynamicImage[{}, {}]", img.width(), img.height()))
             }
-            Plane::Draw(img) => (
-                "Draw",
-                format!(
-                    "DynamicImage[{}, {}]",
-                    img.borrow().img.width(),
-                    img.borrow().img.height()
-                ),
-            ),
+            Plane::Draw(it) => it.with_image(|img| {
+                ("Draw", format!("DynamicImage[{}, {}]", img.width(), img.height()))
+            }),
         };
 
         write!(f, "Plane {{ {} : {} }}", kind, s)
@@ -49,168 +163,31 @@ impl Paintable for Plane {
     fn paint(&self, paint_ctx: &mut PaintCtx) {
         match self {
             Plane::Image(it) => it.paint(paint_ctx),
-            Plane::Draw(it) => it.borrow().img.paint(paint_ctx),
+            Plane::Draw(it) => it.with_image(|img| img.paint(paint_ctx)),
         };
     }
 
     fn paint_size(&self) -> Option<Size> {
         match self {
             Plane::Image(it) => it.paint_size(),
-            Plane::Draw(it) => it.borrow().img.paint_size(),
+            Plane::Draw(it) => it.with_image(|img| img.paint_size()),
         }
     }
 }
 
 impl Plane {
-    fn image(&self) -> Arc<DynamicImage> {
+    pub(crate) fn image(&self) -> Arc<DynamicImage> {
         match self {
             Plane::Image(it) => it.clone(),
-            Plane::Draw(it) => Arc::new(it.borrow().img.clone()),
-        }
-    }
-}
-
-#[derive(Debug, Clone, Copy, Data)]
-pub(crate) struct PlaneIndex(usize);
-
-#[derive(Debug, Clone)]
-struct PlaneData {
-    inner: Arc<Plane>,
-    transform: Vec2,
-}
-
-// FIXME: Move it to Canvas
-#[derive(Debug, Clone)]
-pub(crate) struct Planes {
-    planes: Vec<PlaneData>,
-}
-
-impl Eq for Planes {}
-impl PartialEq for Planes {
-    fn eq(&self, other: &Planes) -> bool {
-        if self.planes.len() != other.planes.len() {
-            return false;
-        }
-        self.planes.iter().zip(other.planes.iter()).all(|(a, b)| Arc::ptr_eq(&a.inner, &b.inner))
-    }
-}
-impl Data for Planes {
-    fn same(&self, other: &Self) -> bool {
-        self == other
-    }
-}
-
-impl Planes {
-    pub(crate) fn new() -> Planes {
-        Planes { planes: Vec::new() }
-    }
-
-    pub(crate) fn max_size(&self) -> Option<Size> {
-        return self.planes.iter().fold(None, |acc, plane_data| {
-            let size = plane_data.inner.paint_size();
-            match (acc, size) {
-                (None, _) => size,
-                (Some(_), None) => acc,
-                (Some(acc), Some(size)) => {
-                    Some((acc.width.max(size.width), acc.height.max(size.height)).into())
-                }
-            }
-        });
-    }
-
-    pub(crate) fn push(&mut self, plane: impl Into<Plane>) -> PlaneIndex {
-        self.planes.push(PlaneData { inner: Arc::new(plane.into()), transform: Vec2::ZERO });
-        PlaneIndex(self.planes.len() - 1)
-    }
-
-    /// Merge all plane, ignore negative transform
-    pub(crate) fn merged(&self) -> Option<Arc<DynamicImage>> {
-        let size = self.max_size()?;
-        let mut img = image_utils::transparent_image(size.width as u32, size.height as u32);
-
-        for plane in &self.planes {
-            image_utils::merge_image(&mut img, &plane.inner.image(), plane.transform);
-        }
-
-        Some(Arc::new(img))
-    }
-
-    pub(crate) fn merged_to(&self, mut img: DynamicImage, transform: Vec2) -> Arc<DynamicImage> {
-        for plane in &self.planes {
-            image_utils::merge_image(&mut img, &plane.inner.image(), plane.transform + transform);
-        }
-        Arc::new(img)
-    }
-
-    pub(crate) fn move_with_index(&mut self, idx: PlaneIndex, offset: Vec2) {
-        self.planes[idx.0].transform += offset;
-    }
-
-    pub(crate) fn bind_selection(&mut self, sel: &Selection) -> PlaneIndex {
-        let merged = self.merged().expect("Expect at least one plane exists");
-        let cutout = sel.copy(merged, CopyMode::Expand).expect("Fail to copy image from selection");
-
-        // TODO: Cut out all other planes
-        for plane in &mut self.planes {
-            let target = sel.transform(-plane.transform);
-            let img = plane.inner.image();
-            if let Some(it) = target.cutout(img) {
-                plane.inner = Arc::new(Plane::Image(it));
-            }
-        }
-
-        self.planes.push(PlaneData {
-            inner: Arc::new(Plane::Image(cutout)),
-            transform: sel.position().to_vec2(),
-        });
-        PlaneIndex(self.planes.len() - 1)
-    }
-
-    pub(crate) fn draw_with_brush(&mut self, pos: &Vec<Vec2>) {
-        let (size, last) = match (self.max_size(), self.planes.last()) {
-            (Some(size), Some(last)) => (size, last),
-            _ => return,
-        };
-
-        if let Plane::Image(_) = last.inner.as_ref() {
-            let empty =
-                Arc::new(RefCell::new(DrawPlane::new(size.width as u32, size.height as u32)));
-            self.push(Plane::Draw(empty.clone()));
-        }
-
-        // reuse last plane if it is draw plane
-        let mut empty = match self.planes.last().map(|it| it.inner.as_ref()) {
-            Some(Plane::Draw(img)) => img.borrow_mut(),
-            _ => unreachable!(),
-        };
-
-        for p in pos {
-            if !empty.brush.iter().all(|it| it != p) {
-                continue;
-            }
-
-            drawing::draw_filled_circle_mut(
-                &mut empty.img,
-                (p.x as i32, p.y as i32),
-                5,
-                image_utils::colors::YELLOW,
-            );
-
-            empty.brush.push(*p);
+            Plane::Draw(it) => it.image(),
         }
     }
-}
 
-impl Paintable for Planes {
-    fn paint(&self, paint_ctx: &mut PaintCtx) {
-        for plane in &self.planes {
-            paint_ctx.with_save(|ctx| {
-                ctx.transform(Affine::translate(plane.transform));
-                plane.inner.paint(ctx);
-            });
+    /// Call `f` with the pixels of the plane, without copying them
+    pub(crate) fn with_image<R>(&self, f: impl FnOnce(&DynamicImage) -> R) -> R {
+        match self {
+            Plane::Image(it) => f(it),
+            Plane::Draw(it) => it.with_image(f),
         }
     }
-    fn paint_size(&self) -> Option<Size> {
-        self.max_size()
-    }
 }
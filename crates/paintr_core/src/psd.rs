@@ -0,0 +1,633 @@
+//! Photoshop format, read only
+//!
+//! Raster layers of 8-bit RGB and grayscale documents are imported with their names, offsets,
+//! opacity, visibility and blend modes. Documents using other layer features fall back to the
+//! composite image.
+use byteorder::{BigEndian, ReadBytesExt};
+use druid::{Size, Vec2};
+use image::{DynamicImage, RgbaImage};
+use std::io::Cursor;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::document::has_extension;
+use crate::layer::{Layer, LayerId, Layers};
+use crate::plane::Plane;
+use crate::{BlendMode, CanvasData};
+use anyhow::{anyhow, Result};
+
+const EXTENSION: &str = "psd";
+/// The largest width and height of a PSD document
+const MAX_SIZE: u32 = 30000;
+
+const MODE_GRAYSCALE: u16 = 1;
+const MODE_RGB: u16 = 3;
+
+const COMPRESSION_RAW: u16 = 0;
+const COMPRESSION_RLE: u16 = 1;
+
+/// Flags of a layer record
+const FLAG_HIDDEN: u8 = 0x02;
+
+/// Keys of additional layer information for adjustment and fill layers
+const ADJUSTMENT_KEYS: [&[u8; 4]; 20] = [
+    b"SoCo", b"GdFl", b"PtFl", b"brit", b"levl", b"curv", b"expA", b"vibA", b"hue ", b"hue2",
+    b"blnc", b"blwh", b"phfl", b"mixr", b"clrL", b"nvrt", b"post", b"thrs", b"grdm", b"selc",
+];
+
+const BLEND_KEYS: [(&[u8; 4], BlendMode); 16] = [
+    (b"norm", BlendMode::Normal),
+    (b"mul ", BlendMode::Multiply),
+    (b"scrn", BlendMode::Screen),
+    (b"over", BlendMode::Overlay),
+    (b"dark", BlendMode::Darken),
+    (b"lite", BlendMode::Lighten),
+    (b"div ", BlendMode::ColorDodge),
+    (b"idiv", BlendMode::ColorBurn),
+    (b"hLit", BlendMode::HardLight),
+    (b"sLit", BlendMode::SoftLight),
+    (b"diff", BlendMode::Difference),
+    (b"smud", BlendMode::Exclusion),
+    (b"hue ", BlendMode::Hue),
+    (b"sat ", BlendMode::Saturation),
+    (b"colr", BlendMode::Color),
+    (b"lum ", BlendMode::Luminosity),
+];
+
+pub(crate) fn is_psd(path: &Path) -> bool {
+    has_extension(path, EXTENSION)
+}
+
+/// A layer feature which cannot be imported
+#[derive(Debug)]
+struct Unsupported(&'static str);
+
+impl std::fmt::Display for Unsupported {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} are not supported", self.0)
+    }
+}
+
+impl std::error::Error for Unsupported {}
+
+type Reader<'a> = Cursor<&'a [u8]>;
+
+fn read_bytes<'a>(r: &mut Reader<'a>, len: usize) -> Result<&'a [u8]> {
+    let start = r.position() as usize;
+    let end = start.checked_add(len).ok_or_else(|| anyhow!("Truncated PSD"))?;
+    let bytes = r.get_ref().get(start..end).ok_or_else(|| anyhow!("Truncated PSD"))?;
+    r.set_position(end as u64);
+    Ok(bytes)
+}
+
+fn remaining(r: &Reader) -> usize {
+    r.get_ref().len().saturating_sub(r.position() as usize)
+}
+
+/// Read a section starting with its length, returns the reader of its content
+fn read_section<'a>(r: &mut Reader<'a>) -> Result<Reader<'a>> {
+    let len = r.read_u32::<BigEndian>()? as usize;
+    Ok(Cursor::new(read_bytes(r, len)?))
+}
+
+struct Header {
+    channels: usize,
+    width: u32,
+    height: u32,
+    mode: u16,
+}
+
+impl Header {
+    fn read(r: &mut Reader) -> Result<Header> {
+        if read_bytes(r, 4)? != b"8BPS" {
+            return Err(anyhow!("Not a PSD file"));
+        }
+        if r.read_u16::<BigEndian>()? != 1 {
+            return Err(anyhow!("Only PSD version 1 is supported"));
+        }
+        read_bytes(r, 6)?;
+
+        let channels = r.read_u16::<BigEndian>()? as usize;
+        let height = r.read_u32::<BigEndian>()?;
+        let width = r.read_u32::<BigEndian>()?;
+        let depth = r.read_u16::<BigEndian>()?;
+        let mode = r.read_u16::<BigEndian>()?;
+        if depth != 8 {
+            return Err(anyhow!("Only 8-bit PSD is supported, found {}-bit", depth));
+        }
+        if mode != MODE_RGB && mode != MODE_GRAYSCALE {
+            return Err(anyhow!("Only RGB and grayscale PSD are supported"));
+        }
+        if width == 0 || height == 0 || width > MAX_SIZE || height > MAX_SIZE {
+            return Err(anyhow!("Invalid PSD size"));
+        }
+        if channels < if mode == MODE_RGB { 3 } else { 1 } {
+            return Err(anyhow!("Missing color channels in PSD"));
+        }
+
+        Ok(Header { channels, width, height, mode })
+    }
+}
+
+/// Decode a PackBits compressed row
+fn unpack_bits(data: &[u8], out: &mut Vec<u8>) -> Result<()> {
+    let mut r = Cursor::new(data);
+    while (r.position() as usize) < data.len() {
+        let n = r.read_i8()?;
+        match n {
+            -128 => (),
+            0..=127 => out.extend_from_slice(read_bytes(&mut r, n as usize + 1)?),
+            _ => {
+                let b = r.read_u8()?;
+                out.resize(out.len() + (1 - n as isize) as usize, b);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Decode `planes` of `width` by `height` pixels stored one after another
+fn read_planes(
+    r: &mut Reader,
+    compression: u16,
+    width: u32,
+    height: u32,
+    planes: usize,
+) -> Result<Vec<Vec<u8>>> {
+    let (w, h) = (width as usize, height as usize);
+    let size = w.checked_mul(h).ok_or_else(|| anyhow!("Invalid PSD channel size"))?;
+    match compression {
+        COMPRESSION_RAW => (0..planes).map(|_| Ok(read_bytes(r, size)?.to_vec())).collect(),
+        COMPRESSION_RLE => {
+            // Two bytes for the length of each row
+            let rows = planes.checked_mul(h).filter(|it| *it <= remaining(r) / 2);
+            let rows = rows.ok_or_else(|| anyhow!("Truncated PSD"))?;
+            let counts = (0..rows)
+                .map(|_| Ok(r.read_u16::<BigEndian>()? as usize))
+                .collect::<Result<Vec<_>>>()?;
+            // A PackBits run of two bytes is at most 128 pixels
+            if counts.iter().any(|count| w / 128 > *count)
+                || counts.iter().sum::<usize>() > remaining(r)
+            {
+                return Err(anyhow!("Truncated PSD"));
+            }
+
+            let mut result = Vec::new();
+            for rows in counts.chunks(h) {
+                let mut plane = Vec::with_capacity(size);
+                for count in rows {
+                    let start = plane.len();
+                    unpack_bits(read_bytes(r, *count)?, &mut plane)?;
+                    plane.resize(start + w, 0);
+                }
+                result.push(plane);
+            }
+            Ok(result)
+        }
+        _ => Err(Unsupported("Zip compressed channels").into()),
+    }
+}
+
+/// Combine the color planes and the optional alpha plane
+fn to_image(
+    mode: u16,
+    width: u32,
+    height: u32,
+    planes: &[&[u8]],
+    alpha: Option<&[u8]>,
+) -> DynamicImage {
+    let img = RgbaImage::from_fn(width, height, |x, y| {
+        let i = y as usize * width as usize + x as usize;
+        let a = alpha.map_or(0xff, |it| it[i]);
+        match mode {
+            MODE_GRAYSCALE => image::Rgba([planes[0][i], planes[0][i], planes[0][i], a]),
+            _ => image::Rgba([planes[0][i], planes[1][i], planes[2][i], a]),
+        }
+    });
+    DynamicImage::ImageRgba8(img)
+}
+
+struct LayerRecord {
+    name: String,
+    top: i32,
+    left: i32,
+    width: u32,
+    height: u32,
+    /// Channel ids and the length of their data
+    channels: Vec<(i16, usize)>,
+    blend_mode: BlendMode,
+    opacity: u8,
+    visible: bool,
+}
+
+impl LayerRecord {
+    fn read(r: &mut Reader, header: &Header) -> Result<LayerRecord> {
+        let top = r.read_i32::<BigEndian>()?;
+        let left = r.read_i32::<BigEndian>()?;
+        let bottom = r.read_i32::<BigEndian>()?;
+        let right = r.read_i32::<BigEndian>()?;
+
+        let extent = |start: i32, end: i32| {
+            let len = end.checked_sub(start).filter(|it| *it >= 0);
+            len.map(|it| it as u32).ok_or_else(|| anyhow!("Invalid PSD layer bounds"))
+        };
+        let (width, height) = (extent(left, right)?, extent(top, bottom)?);
+
+        let count = r.read_u16::<BigEndian>()?;
+        let channels = (0..count)
+            .map(|_| Ok((r.read_i16::<BigEndian>()?, r.read_u32::<BigEndian>()? as usize)))
+            .collect::<Result<Vec<_>>>()?;
+
+        if read_bytes(r, 4)? != b"8BIM" {
+            return Err(anyhow!("Invalid PSD layer record"));
+        }
+        let key = read_bytes(r, 4)?;
+        let blend_mode = BLEND_KEYS.iter().find(|(it, _)| &it[..] == key).map(|(_, mode)| *mode);
+        let opacity = r.read_u8()?;
+        let clipping = r.read_u8()?;
+        let flags = r.read_u8()?;
+        read_bytes(r, 1)?;
+
+        let mut extra = read_section(r)?;
+        let mask = read_section(&mut extra)?;
+        read_section(&mut extra)?;
+
+        // Pascal string padded to a multiple of 4 bytes
+        let len = extra.read_u8()? as usize;
+        let mut name = String::from_utf8_lossy(read_bytes(&mut extra, len)?).into_owned();
+        read_bytes(&mut extra, (4 - (len + 1) % 4) % 4)?;
+
+        while extra.get_ref().len() - extra.position() as usize >= 12 {
+            let _signature = read_bytes(&mut extra, 4)?;
+            let key = read_bytes(&mut extra, 4)?;
+            let mut data = read_section(&mut extra)?;
+            match key {
+                b"luni" => {
+                    let len = data.read_u32::<BigEndian>()?;
+                    let units = (0..len)
+                        .map(|_| data.read_u16::<BigEndian>())
+                        .collect::<std::io::Result<Vec<_>>>()?;
+                    name = String::from_utf16_lossy(&units);
+                }
+                b"lsct" | b"lsdk" => return Err(Unsupported("Layer groups").into()),
+                b"TySh" | b"tySh" => return Err(Unsupported("Text layers").into()),
+                _ if ADJUSTMENT_KEYS.iter().any(|it| &it[..] == key) => {
+                    return Err(Unsupported("Adjustment layers").into())
+                }
+                _ => (),
+            }
+        }
+
+        if !mask.get_ref().is_empty() {
+            return Err(Unsupported("Layer masks").into());
+        }
+        if clipping != 0 {
+            return Err(Unsupported("Clipping masks").into());
+        }
+        let blend_mode = blend_mode.ok_or(Unsupported("Some blend modes"))?;
+        if width > header.width || height > header.height {
+            return Err(Unsupported("Layers larger than the document").into());
+        }
+
+        Ok(LayerRecord {
+            name,
+            top,
+            left,
+            width,
+            height,
+            channels,
+            blend_mode,
+            opacity,
+            visible: flags & FLAG_HIDDEN == 0,
+        })
+    }
+
+    /// Read the channel data following all layer records
+    fn read_image(&self, r: &mut Reader, mode: u16) -> Result<Option<DynamicImage>> {
+        let mut planes = Vec::new();
+        for (id, len) in &self.channels {
+            let mut data = Cursor::new(read_bytes(r, *len)?);
+            let compression = data.read_u16::<BigEndian>()?;
+            // Skip the channels of layer masks
+            if *id >= -1 && self.width > 0 && self.height > 0 {
+                let plane = read_planes(&mut data, compression, self.width, self.height, 1)?;
+                planes.push((*id, plane.into_iter().next().unwrap_or_default()));
+            }
+        }
+        if self.width == 0 || self.height == 0 {
+            return Ok(None);
+        }
+
+        let colors = if mode == MODE_GRAYSCALE { 1 } else { 3 };
+        // The size is bounded by the document
+        let size = self.width as usize * self.height as usize;
+        let empty = vec![0; size];
+        let plane = |id: i16| planes.iter().find(|(it, _)| *it == id).map(|(_, it)| it.as_slice());
+
+        let sorted: Vec<&[u8]> = (0..colors).map(|id| plane(id).unwrap_or(&empty)).collect();
+        Ok(Some(to_image(mode, self.width, self.height, &sorted, plane(-1))))
+    }
+}
+
+/// Read the layers from bottom to top, with their properties
+fn read_layers(
+    info: &mut Reader,
+    header: &Header,
+    count: usize,
+) -> Result<Vec<(Layer, LayerRecord)>> {
+    let records =
+        (0..count).map(|_| LayerRecord::read(info, header)).collect::<Result<Vec<_>>>()?;
+
+    let mut layers = Vec::new();
+    for record in records {
+        let img = match record.read_image(info, header.mode)? {
+            Some(it) => it,
+            // An empty layer has no pixel
+            None => continue,
+        };
+        let id = LayerId(layers.len());
+        let pos = Vec2::new(record.left as f64, record.top as f64);
+        let layer = Layer::new(id, record.name.clone(), Plane::Image(Arc::new(img)), pos);
+        layers.push((layer, record));
+    }
+    Ok(layers)
+}
+
+/// Read the composite image, its first alpha channel is the transparency with `merged_alpha`
+fn read_composite(r: &mut Reader, header: &Header, merged_alpha: bool) -> Result<DynamicImage> {
+    let compression = r.read_u16::<BigEndian>()?;
+    let planes = read_planes(r, compression, header.width, header.height, header.channels)?;
+    let planes: Vec<&[u8]> = planes.iter().map(|it| it.as_slice()).collect();
+
+    let colors = if header.mode == MODE_GRAYSCALE { 1 } else { 3 };
+    let alpha = planes.get(colors).copied().filter(|_| merged_alpha);
+    Ok(to_image(header.mode, header.width, header.height, &planes, alpha))
+}
+
+/// Read a PSD file, with a warning if only the composite image could be imported
+pub(crate) fn read(path: &Path, data: &[u8]) -> Result<(CanvasData, Option<String>)> {
+    let mut r = Cursor::new(data);
+    let header = Header::read(&mut r)?;
+    let size = Size::new(header.width as f64, header.height as f64);
+
+    read_section(&mut r)?;
+    read_section(&mut r)?;
+
+    let mut layer_and_mask = read_section(&mut r)?;
+    let mut info = read_section(&mut layer_and_mask)?;
+    // A negative count means the first alpha channel is the transparency of the composite
+    let count = match info.get_ref().is_empty() {
+        true => 0,
+        false => info.read_i16::<BigEndian>()?,
+    };
+    let (layers, warning) = match read_layers(&mut info, &header, count.unsigned_abs() as usize) {
+        Ok(layers) => (layers, None),
+        Err(err) => match err.downcast_ref::<Unsupported>() {
+            Some(it) => (Vec::new(), Some(format!("{}, only the merged image was imported", it))),
+            None => return Err(err),
+        },
+    };
+
+    if layers.is_empty() {
+        let img = read_composite(&mut r, &header, count < 0)?;
+        return Ok((CanvasData::new(path, img), warning));
+    }
+
+    let top = LayerId(layers.len() - 1);
+    let (layers, records): (Vec<_>, Vec<_>) = layers.into_iter().unzip();
+    let mut layers = Layers::from_layers(layers, top).expect("Layers must not be empty");
+    for (index, record) in records.iter().enumerate() {
+        layers.set_opacity(LayerId(index), record.opacity as f64 / 255.0);
+        layers.set_blend_mode(LayerId(index), record.blend_mode);
+        layers.set_visible(LayerId(index), record.visible);
+    }
+    Ok((CanvasData::from_layers(path, layers, size, Vec2::ZERO), None))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::image_utils::colors::*;
+    use byteorder::WriteBytesExt;
+    use image::GenericImageView;
+    use std::io::Write;
+
+    struct TestLayer {
+        name: &'static str,
+        rect: (i32, i32, u32, u32),
+        blend_key: &'static [u8; 4],
+        opacity: u8,
+        flags: u8,
+        color: [u8; 4],
+    }
+
+    fn section(out: &mut Vec<u8>, data: &[u8]) {
+        out.write_u32::<BigEndian>(data.len() as u32).unwrap();
+        out.write_all(data).unwrap();
+    }
+
+    /// RLE compress a channel filled with `value`, as one run per row
+    fn rle_channel(w: u32, h: u32, value: u8) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.write_u16::<BigEndian>(COMPRESSION_RLE).unwrap();
+        let mut row = Vec::new();
+        let mut left = w;
+        while left > 0 {
+            let n = left.min(128);
+            row.extend_from_slice(&[(1 - n as i32) as i8 as u8, value]);
+            left -= n;
+        }
+        for _ in 0..h {
+            out.write_u16::<BigEndian>(row.len() as u16).unwrap();
+        }
+        for _ in 0..h {
+            out.extend_from_slice(&row);
+        }
+        out
+    }
+
+    fn raw_channel(w: u32, h: u32, value: u8) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.write_u16::<BigEndian>(COMPRESSION_RAW).unwrap();
+        out.resize(out.len() + (w * h) as usize, value);
+        out
+    }
+
+    fn make_psd(w: u32, h: u32, layers: &[TestLayer], composite: [u8; 4]) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.write_all(b"8BPS").unwrap();
+        out.write_u16::<BigEndian>(1).unwrap();
+        out.write_all(&[0; 6]).unwrap();
+        out.write_u16::<BigEndian>(4).unwrap();
+        out.write_u32::<BigEndian>(h).unwrap();
+        out.write_u32::<BigEndian>(w).unwrap();
+        out.write_u16::<BigEndian>(8).unwrap();
+        out.write_u16::<BigEndian>(MODE_RGB).unwrap();
+        section(&mut out, &[]);
+        section(&mut out, &[]);
+
+        let mut records = Vec::new();
+        let mut channels = Vec::new();
+        records.write_i16::<BigEndian>(layers.len() as i16).unwrap();
+        for layer in layers {
+            let (x, y, lw, lh) = layer.rect;
+            for v in &[y, x, y + lh as i32, x + lw as i32] {
+                records.write_i32::<BigEndian>(*v).unwrap();
+            }
+            records.write_u16::<BigEndian>(4).unwrap();
+            for (i, id) in [0i16, 1, 2, -1].iter().enumerate() {
+                // Mix both compressions
+                let data = match i % 2 {
+                    0 => rle_channel(lw, lh, layer.color[i]),
+                    _ => raw_channel(lw, lh, layer.color[i]),
+                };
+                records.write_i16::<BigEndian>(*id).unwrap();
+                records.write_u32::<BigEndian>(data.len() as u32).unwrap();
+                channels.extend(data);
+            }
+            records.write_all(b"8BIM").unwrap();
+            records.write_all(layer.blend_key).unwrap();
+            records.write_all(&[layer.opacity, 0, layer.flags, 0]).unwrap();
+
+            let mut extra = Vec::new();
+            section(&mut extra, &[]);
+            section(&mut extra, &[]);
+            let mut name = vec![layer.name.len() as u8];
+            name.extend_from_slice(layer.name.as_bytes());
+            while name.len() % 4 != 0 {
+                name.push(0);
+            }
+            extra.extend(name);
+            section(&mut records, &extra);
+        }
+        records.extend(channels);
+
+        let mut info = Vec::new();
+        section(&mut info, &records);
+        section(&mut out, &info);
+
+        out.write_u16::<BigEndian>(COMPRESSION_RAW).unwrap();
+        for value in &composite {
+            out.resize(out.len() + (w * h) as usize, *value);
+        }
+        out
+    }
+
+    #[test]
+    fn psd_should_import_layers() {
+        let layers = [
+            TestLayer {
+                name: "Background",
+                rect: (0, 0, 200, 3),
+                blend_key: b"norm",
+                opacity: 255,
+                flags: 0,
+                color: WHITE.0,
+            },
+            TestLayer {
+                name: "Top",
+                rect: (-2, 1, 4, 2),
+                blend_key: b"mul ",
+                opacity: 51,
+                flags: FLAG_HIDDEN,
+                color: [0xff, 0x00, 0x00, 0x80],
+            },
+        ];
+        let data = make_psd(200, 3, &layers, BLACK.0);
+        let (canvas, warning) = read(Path::new("test.psd"), &data).unwrap();
+        assert_eq!(warning, None);
+        assert_eq!(canvas.layers().len(), 2);
+
+        let (bottom, top) = (&canvas.layers()[0], &canvas.layers()[1]);
+        assert_eq!(canvas.active_layer(), top.id());
+        assert_eq!(bottom.name(), "Background");
+        assert_eq!(bottom.size(), Size::new(200.0, 3.0));
+        assert_eq!(bottom.image().get_pixel(199, 2), WHITE);
+        assert_eq!(top.name(), "Top");
+        assert_eq!(top.position(), druid::Point::new(-2.0, 1.0));
+        assert_eq!(top.image().get_pixel(3, 1), image::Rgba([0xff, 0x00, 0x00, 0x80]));
+        assert_eq!(top.opacity(), 0.2);
+        assert_eq!(top.blend_mode(), BlendMode::Multiply);
+        assert!(!top.is_visible());
+    }
+
+    #[test]
+    fn psd_should_fall_back_to_composite() {
+        let layers = [TestLayer {
+            name: "Dissolve",
+            rect: (0, 0, 4, 4),
+            blend_key: b"diss",
+            opacity: 255,
+            flags: 0,
+            color: WHITE.0,
+        }];
+        let data = make_psd(4, 4, &layers, BLACK.0);
+        let (canvas, warning) = read(Path::new("test.psd"), &data).unwrap();
+        assert!(warning.is_some());
+        assert_eq!(canvas.layers().len(), 1);
+        assert_eq!(canvas.merged().get_pixel(1, 1), BLACK);
+
+        // A flat document is not a fallback
+        let data = make_psd(4, 4, &[], WHITE.0);
+        let (canvas, warning) = read(Path::new("test.psd"), &data).unwrap();
+        assert_eq!(warning, None);
+        assert_eq!(canvas.merged().get_pixel(1, 1), WHITE);
+    }
+
+    #[test]
+    fn psd_should_read_composite_alpha_with_merged_alpha_flag() {
+        let layers = [TestLayer {
+            name: "Dissolve",
+            rect: (0, 0, 4, 4),
+            blend_key: b"diss",
+            opacity: 255,
+            flags: 0,
+            color: WHITE.0,
+        }];
+        let mut data = make_psd(4, 4, &layers, [0xff, 0x00, 0x00, 0x80]);
+        let (canvas, _) = read(Path::new("test.psd"), &data).unwrap();
+        assert_eq!(canvas.merged().get_pixel(1, 1), image::Rgba([0xff, 0x00, 0x00, 0xff]));
+
+        // The layer count follows the header, the image resources and the section lengths
+        data[42..44].copy_from_slice(&(-1i16).to_be_bytes());
+        let (canvas, _) = read(Path::new("test.psd"), &data).unwrap();
+        assert_eq!(canvas.merged().get_pixel(1, 1), image::Rgba([0xff, 0x00, 0x00, 0x80]));
+    }
+
+    #[test]
+    fn psd_should_reject_invalid_layer_bounds() {
+        let layer = |rect| TestLayer {
+            name: "Layer",
+            rect,
+            blend_key: b"norm",
+            opacity: 255,
+            flags: 0,
+            color: WHITE.0,
+        };
+
+        // The layer bounds follow the header, the image resources and the section lengths
+        let mut data = make_psd(4, 4, &[layer((0, 0, 4, 4))], BLACK.0);
+        data[48..52].copy_from_slice(&i32::MIN.to_be_bytes());
+        data[56..60].copy_from_slice(&i32::MAX.to_be_bytes());
+        assert!(read(Path::new("test.psd"), &data).is_err());
+
+        let data = make_psd(4, 4, &[layer((-2, 0, 8, 4))], BLACK.0);
+        let (canvas, warning) = read(Path::new("test.psd"), &data).unwrap();
+        assert!(warning.is_some());
+        assert_eq!(canvas.merged().get_pixel(1, 1), BLACK);
+
+        // The row lengths of a huge channel do not fit in the file
+        let mut data = make_psd(4, 4, &[], BLACK.0);
+        data[14..22].copy_from_slice(&[0, 0, 0x75, 0x30, 0, 0, 0x75, 0x30]);
+        let at = data.len() - 4 * 16 - 2;
+        data[at..at + 2].copy_from_slice(&COMPRESSION_RLE.to_be_bytes());
+        assert!(read(Path::new("test.psd"), &data).is_err());
+    }
+
+    #[test]
+    fn unpack_bits_should_works() {
+        let mut out = Vec::new();
+        unpack_bits(&[0x02, 1, 2, 3, 0xfd, 7, 0x80, 0x00, 9], &mut out).unwrap();
+        assert_eq!(out, vec![1, 2, 3, 7, 7, 7, 7, 9]);
+    }
+}
@@ -1,33 +1,147 @@
+use druid::kurbo::{BezPath, Shape};
 use druid::piet::StrokeStyle;
-use druid::{kurbo::Shape, PaintCtx};
+use druid::PaintCtx;
 use druid::{Color, Data, Point, Rect, RenderContext, Size, Vec2};
 
-use crate::Paintable;
-use image::DynamicImage;
+use crate::{image_utils, paintable::paint_scale, Paintable};
+use image::{DynamicImage, GenericImage, GenericImageView};
 use std::sync::Arc;
+mod ellipse;
+mod mask;
+mod path;
 mod rect;
 
-trait SelectionShape: Shape {
+pub use ellipse::Ellipse;
+pub use mask::{MagicWandSettings, Mask};
+
+trait SelectionShape {
     fn size(&self) -> Size;
     fn description(&self) -> String;
     fn copy(&self, img: Arc<DynamicImage>, mode: CopyMode) -> Option<Arc<DynamicImage>>;
     fn cutout(&self, img: Arc<DynamicImage>) -> Option<Arc<DynamicImage>>;
     fn contains(&self, pt: Point) -> bool;
+    fn to_mask(&self) -> Mask;
     fn transform(&self, offset: Vec2) -> Self;
     fn same(&self, other: &Self) -> bool;
 }
 
+/// Copy the pixels inside `bounds`, with the alpha scaled by the coverage of each pixel
+fn copy_masked(
+    bounds: Rect,
+    img: Arc<DynamicImage>,
+    mode: CopyMode,
+    coverage: impl Fn(u32, u32) -> f64,
+) -> Option<Arc<DynamicImage>> {
+    let origin = match mode {
+        CopyMode::Shrink => rect::intersect(bounds, &img)?.origin(),
+        CopyMode::Expand => bounds.origin(),
+    };
+    let mut output = bounds.copy(img, mode)?.as_ref().clone();
+
+    let (w, h) = output.dimensions();
+    for y in 0..h {
+        for x in 0..w {
+            let (px, py) = (origin.x as i64 + x as i64, origin.y as i64 + y as i64);
+            let coverage = match px >= 0 && py >= 0 {
+                true => coverage(px as u32, py as u32),
+                false => 0.0,
+            };
+            if coverage < 1.0 {
+                let mut p = output.get_pixel(x, y);
+                scale_alpha(&mut p, coverage);
+                output.put_pixel(x, y, p);
+            }
+        }
+    }
+
+    Some(Arc::new(output))
+}
+
+/// Clear the pixels inside `bounds`, by the coverage of each pixel
+fn cutout_masked(
+    bounds: Rect,
+    img: Arc<DynamicImage>,
+    coverage: impl Fn(u32, u32) -> f64,
+) -> Option<Arc<DynamicImage>> {
+    let rect = rect::intersect(bounds, &img)?.expand();
+    let mut output = img.as_ref().clone();
+
+    for y in rect.y0 as u32..rect.y1 as u32 {
+        for x in rect.x0 as u32..rect.x1 as u32 {
+            let coverage = coverage(x, y);
+            if coverage > 0.0 {
+                let mut p = output.get_pixel(x, y);
+                scale_alpha(&mut p, 1.0 - coverage);
+                output.put_pixel(x, y, p);
+            }
+        }
+    }
+
+    Some(Arc::new(output))
+}
+
+fn scale_alpha(p: &mut image::Rgba<u8>, factor: f64) {
+    p.0[3] = (p.0[3] as f64 * factor).round() as u8;
+    if p.0[3] == 0 {
+        *p = image_utils::colors::TRANSPARENT;
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum Selection {
     Rect(Rect),
+    Ellipse(Ellipse),
+    /// A path filled with the even-odd rule
+    Path(BezPath),
+    /// The coverage of each pixel, also the result of combined selections
+    Mask(Mask),
 }
 
 impl_from! {
     Selection : [
-        Rect => Rect
+        Rect => Rect,
+        Ellipse => Ellipse,
+        BezPath => Path,
+        Mask => Mask
     ]
 }
 
+/// How a new selection is combined with the current one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Data)]
+pub enum SelectionMode {
+    Replace,
+    Add,
+    Subtract,
+    Intersect,
+}
+
+impl SelectionMode {
+    /// Combine `sel` into `base`, the result of the boolean modes is a mask
+    pub fn combine(self, base: Option<&Selection>, sel: Selection) -> Selection {
+        let base = match (self, base) {
+            (SelectionMode::Replace, _) | (SelectionMode::Add, None) => return sel,
+            (_, None) => return Rect::ZERO.into(),
+            (_, Some(base)) => base,
+        };
+        // Nothing to add or subtract, keep the base as is
+        if sel.area() == 0.0 && self != SelectionMode::Intersect {
+            return base.clone();
+        }
+
+        base.to_mask().combine(&sel.to_mask(), self).into()
+    }
+}
+
+/// A change to the shape of a selection, in pixels
+#[derive(Debug, Clone, Copy, PartialEq, Data)]
+pub enum SelectionModify {
+    Grow(u32),
+    Shrink(u32),
+    Border(u32),
+    Smooth(u32),
+    Feather(f64),
+}
+
 pub enum CopyMode {
     Shrink,
     Expand,
@@ -37,53 +151,109 @@ impl Selection {
     pub fn description(&self) -> String {
         match self {
             Selection::Rect(rt) => rt.description(),
+            Selection::Ellipse(it) => it.description(),
+            Selection::Path(it) => it.description(),
+            Selection::Mask(it) => it.description(),
         }
     }
 
     fn size(&self) -> Size {
         match self {
             Selection::Rect(rt) => rt.size(),
+            Selection::Ellipse(it) => it.size(),
+            Selection::Path(it) => SelectionShape::size(it),
+            Selection::Mask(it) => SelectionShape::size(it),
         }
     }
 
     pub fn area(&self) -> f64 {
         match self {
             Selection::Rect(rt) => rt.area(),
+            Selection::Ellipse(it) => it.area(),
+            Selection::Path(it) => it.area().abs(),
+            Selection::Mask(it) => it.area(),
         }
     }
 
     pub fn copy(&self, img: Arc<DynamicImage>, mode: CopyMode) -> Option<Arc<DynamicImage>> {
         match self {
             Selection::Rect(rect) => rect.copy(img, mode),
+            Selection::Ellipse(it) => it.copy(img, mode),
+            Selection::Path(it) => it.copy(img, mode),
+            Selection::Mask(it) => it.copy(img, mode),
         }
     }
 
     pub fn cutout(&self, img: Arc<DynamicImage>) -> Option<Arc<DynamicImage>> {
         match self {
             Selection::Rect(rect) => rect.cutout(img),
+            Selection::Ellipse(it) => it.cutout(img),
+            Selection::Path(it) => it.cutout(img),
+            Selection::Mask(it) => it.cutout(img),
         }
     }
 
-    pub fn shape(&self) -> impl Shape {
+    pub fn shape(&self) -> BezPath {
         match self {
-            Selection::Rect(rt) => *rt,
+            Selection::Rect(rt) => rt.to_path(0.1),
+            Selection::Ellipse(it) => druid::kurbo::Ellipse::from_rect(it.rect()).to_path(0.1),
+            Selection::Path(it) => it.clone(),
+            Selection::Mask(it) => it.outline(),
         }
     }
 
     pub fn contains(&self, pt: Point) -> bool {
         match self {
             Selection::Rect(rt) => rt.contains(pt),
+            Selection::Ellipse(it) => SelectionShape::contains(it, pt),
+            Selection::Path(it) => SelectionShape::contains(it, pt),
+            Selection::Mask(it) => SelectionShape::contains(it, pt),
+        }
+    }
+
+    /// Rasterize to the coverage of each pixel
+    pub fn to_mask(&self) -> Mask {
+        match self {
+            Selection::Rect(rt) => rt.to_mask(),
+            Selection::Ellipse(it) => it.to_mask(),
+            Selection::Path(it) => it.to_mask(),
+            Selection::Mask(it) => it.clone(),
         }
     }
 
+    pub fn modify(&self, modify: SelectionModify) -> Selection {
+        let mask = self.to_mask();
+        let mask = match modify {
+            SelectionModify::Grow(n) => mask.grow(n),
+            SelectionModify::Shrink(n) => mask.shrink(n),
+            SelectionModify::Border(n) => mask.border(n),
+            SelectionModify::Smooth(n) => mask.smooth(n),
+            SelectionModify::Feather(radius) => mask.feather(radius),
+        };
+        mask.into()
+    }
+
     pub fn transform(&self, offset: Vec2) -> Selection {
         match self {
             Selection::Rect(rt) => rt.transform(offset).into(),
+            Selection::Ellipse(it) => it.transform(offset).into(),
+            Selection::Path(it) => it.transform(offset).into(),
+            Selection::Mask(it) => it.transform(offset).into(),
+        }
+    }
+
+    /// The rect of all pixels covered by the selection
+    pub fn bounds(&self) -> Rect {
+        match self {
+            Selection::Rect(rt) => *rt,
+            Selection::Ellipse(it) => it.rect(),
+            Selection::Path(it) => it.bounding_box(),
+            Selection::Mask(it) => it.bounds(),
         }
     }
 
     pub fn position(&self) -> Point {
-        self.shape().bounding_box().origin()
+        self.bounds().origin()
     }
 }
 
@@ -91,6 +261,10 @@ impl Data for Selection {
     fn same(&self, other: &Self) -> bool {
         match (self, other) {
             (Selection::Rect(a), Selection::Rect(b)) => a == b,
+            (Selection::Ellipse(a), Selection::Ellipse(b)) => a.same(b),
+            (Selection::Path(a), Selection::Path(b)) => SelectionShape::same(a, b),
+            (Selection::Mask(a), Selection::Mask(b)) => a.same(b),
+            _ => false,
         }
     }
 }
@@ -99,12 +273,14 @@ impl Paintable for Selection {
     fn paint(&self, paint_ctx: &mut PaintCtx) {
         // Create a color
         let stroke_color = Color::rgb8(0xff, 0xff, 0xff);
+        // Keep the same width on screen whatever the zoom is
+        let width = 1.0 / paint_scale(paint_ctx);
 
         let mut style = StrokeStyle::new();
-        let dashes = vec![2.0, 2.0];
+        let dashes = vec![2.0 * width, 2.0 * width];
         style.set_dash(dashes, 0.0);
 
-        paint_ctx.render_ctx.stroke_styled(self.shape(), &stroke_color, 1.0, &style);
+        paint_ctx.render_ctx.stroke_styled(self.shape(), &stroke_color, width, &style);
     }
 
     fn paint_size(&self) -> Option<Size> {
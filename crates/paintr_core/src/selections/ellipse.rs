@@ -0,0 +1,161 @@
+use druid::{Point, Rect, Size, Vec2};
+use image::DynamicImage;
+use std::sync::Arc;
+
+use super::{copy_masked, cutout_masked, CopyMode, Mask, SelectionShape};
+
+/// Number of samples per axis of an anti-aliased pixel
+const SAMPLES: u32 = 4;
+
+/// An ellipse inscribed in a rect
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ellipse {
+    rect: Rect,
+    anti_alias: bool,
+}
+
+impl Ellipse {
+    pub fn new(rect: Rect, anti_alias: bool) -> Ellipse {
+        Ellipse { rect: rect.abs(), anti_alias }
+    }
+
+    pub fn rect(&self) -> Rect {
+        self.rect
+    }
+
+    pub fn anti_alias(&self) -> bool {
+        self.anti_alias
+    }
+
+    pub fn area(&self) -> f64 {
+        std::f64::consts::PI * self.rect.width() * self.rect.height() / 4.0
+    }
+
+    /// How much of the pixel at `(x, y)` is inside the ellipse, from 0.0 to 1.0
+    fn coverage(&self, x: i64, y: i64) -> f64 {
+        if !self.anti_alias {
+            let inside = SelectionShape::contains(self, Point::new(x as f64 + 0.5, y as f64 + 0.5));
+            return if inside { 1.0 } else { 0.0 };
+        }
+
+        let step = 1.0 / SAMPLES as f64;
+        let mut hits = 0;
+        for j in 0..SAMPLES {
+            for i in 0..SAMPLES {
+                let pt = Point::new(
+                    x as f64 + (i as f64 + 0.5) * step,
+                    y as f64 + (j as f64 + 0.5) * step,
+                );
+                if SelectionShape::contains(self, pt) {
+                    hits += 1;
+                }
+            }
+        }
+        hits as f64 / (SAMPLES * SAMPLES) as f64
+    }
+}
+
+impl SelectionShape for Ellipse {
+    fn description(&self) -> String {
+        self.rect.description()
+    }
+
+    fn size(&self) -> Size {
+        self.rect.size()
+    }
+
+    fn copy(&self, img: Arc<DynamicImage>, mode: CopyMode) -> Option<Arc<DynamicImage>> {
+        copy_masked(self.rect, img, mode, |x, y| self.coverage(x as i64, y as i64))
+    }
+
+    fn cutout(&self, img: Arc<DynamicImage>) -> Option<Arc<DynamicImage>> {
+        cutout_masked(self.rect, img, |x, y| self.coverage(x as i64, y as i64))
+    }
+
+    fn to_mask(&self) -> Mask {
+        Mask::from_fn(self.rect, |x, y| self.coverage(x, y))
+    }
+
+    fn contains(&self, pt: Point) -> bool {
+        let radii = self.rect.size().to_vec2() / 2.0;
+        if radii.x <= 0.0 || radii.y <= 0.0 {
+            return false;
+        }
+
+        let d = pt - self.rect.center();
+        Vec2::new(d.x / radii.x, d.y / radii.y).hypot2() <= 1.0
+    }
+
+    fn transform(&self, offset: Vec2) -> Self {
+        Ellipse { rect: self.rect + offset, anti_alias: self.anti_alias }
+    }
+
+    fn same(&self, other: &Self) -> bool {
+        self == other
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::image_utils::{
+        colors::{TRANSPARENT, WHITE},
+        make_color_img,
+    };
+    use image::GenericImageView;
+
+    #[test]
+    fn ellipse_contains_should_follow_shape() {
+        let ellipse = Ellipse::new(Rect::new(0.0, 0.0, 8.0, 4.0), false);
+        assert!(SelectionShape::contains(&ellipse, Point::new(4.0, 2.0)));
+        assert!(SelectionShape::contains(&ellipse, Point::new(7.5, 2.0)));
+        assert!(!SelectionShape::contains(&ellipse, Point::new(0.5, 0.5)));
+        assert!(!SelectionShape::contains(&ellipse, Point::new(7.5, 3.5)));
+    }
+
+    #[test]
+    fn ellipse_copy_should_works() {
+        let white = Arc::new(make_color_img(8, 8, WHITE));
+        let ellipse = Ellipse::new(Rect::new(0.0, 0.0, 8.0, 8.0), false);
+
+        let img = ellipse.copy(white.clone(), CopyMode::Shrink).unwrap();
+        assert_eq!(img.dimensions(), (8, 8));
+        assert_eq!(img.get_pixel(4, 4), WHITE);
+        assert_eq!(img.get_pixel(0, 0), TRANSPARENT);
+        assert_eq!(img.get_pixel(7, 7), TRANSPARENT);
+
+        let ellipse = ellipse.transform(Vec2::new(-4.0, -4.0));
+        let img = ellipse.copy(white, CopyMode::Expand).unwrap();
+        assert_eq!(img.dimensions(), (8, 8));
+        assert_eq!(img.get_pixel(5, 5), WHITE);
+        assert_eq!(img.get_pixel(1, 1), TRANSPARENT);
+        assert_eq!(img.get_pixel(7, 7), TRANSPARENT);
+    }
+
+    #[test]
+    fn ellipse_cutout_should_works() {
+        let white = Arc::new(make_color_img(8, 8, WHITE));
+        let ellipse = Ellipse::new(Rect::new(0.0, 0.0, 8.0, 8.0), false);
+
+        let img = ellipse.cutout(white).unwrap();
+        assert_eq!(img.dimensions(), (8, 8));
+        assert_eq!(img.get_pixel(4, 4), TRANSPARENT);
+        assert_eq!(img.get_pixel(0, 0), WHITE);
+        assert_eq!(img.get_pixel(7, 0), WHITE);
+    }
+
+    #[test]
+    fn ellipse_should_anti_alias_edges() {
+        let white = Arc::new(make_color_img(8, 8, WHITE));
+        let ellipse = Ellipse::new(Rect::new(0.0, 0.0, 8.0, 8.0), true);
+
+        let img = ellipse.copy(white.clone(), CopyMode::Shrink).unwrap();
+        let edge = img.get_pixel(1, 1).0[3];
+        assert!(edge > 0 && edge < 0xff, "{}", edge);
+        assert_eq!(img.get_pixel(4, 4), WHITE);
+        assert_eq!(img.get_pixel(0, 0), TRANSPARENT);
+
+        let img = ellipse.cutout(white).unwrap();
+        assert_eq!(img.get_pixel(1, 1).0[3], 0xff - edge);
+    }
+}
@@ -0,0 +1,712 @@
+use druid::kurbo::BezPath;
+use druid::{Data, Lens, Point, Rect, Size, Vec2};
+use image::{DynamicImage, GenericImageView, GrayImage, Rgba};
+use std::sync::Arc;
+
+use super::{copy_masked, cutout_masked, CopyMode, SelectionMode, SelectionShape};
+
+/// Coverage from which a pixel is outlined as inside the mask
+const THRESHOLD: u8 = 0x80;
+
+/// Settings of the magic wand
+#[derive(Debug, Clone, Copy, PartialEq, Data, Lens)]
+pub struct MagicWandSettings {
+    /// Maximum difference of a channel to the sampled color, from 0 to 255
+    pub tolerance: f64,
+    /// Only select the pixels connected to the sampled one
+    pub contiguous: bool,
+    /// Sample the merged image instead of the active layer
+    pub sample_merged: bool,
+}
+
+impl Default for MagicWandSettings {
+    fn default() -> MagicWandSettings {
+        MagicWandSettings { tolerance: 32.0, contiguous: true, sample_merged: false }
+    }
+}
+
+/// A selection by the coverage of each pixel, from 0 to 255
+#[derive(Debug, Clone)]
+pub struct Mask {
+    origin: Point,
+    width: u32,
+    height: u32,
+    data: Arc<Vec<u8>>,
+}
+
+/// The largest difference between the channels of two colors
+fn distance(a: Rgba<u8>, b: Rgba<u8>) -> u8 {
+    a.0.iter().zip(b.0.iter()).map(|(a, b)| a.max(b) - a.min(b)).max().unwrap_or(0)
+}
+
+/// Half widths of the rows of a disc of `radius`, from the center row outwards
+fn disc_rows(radius: usize) -> Vec<usize> {
+    (0..=radius)
+        .map(|dy| (0..=radius).rev().find(|dx| dx * dx + dy * dy <= radius * radius).unwrap_or(0))
+        .collect()
+}
+
+/// The maximums of the windows of `2 * radius + 1` values of `src`, by van Herk's algorithm
+fn max_filter(src: &[u8], radius: usize, out: &mut Vec<u8>) {
+    let (n, k) = (src.len(), 2 * radius + 1);
+    out.clear();
+    if n < k {
+        return;
+    }
+
+    // Maximums from the start and to the end of each block of `k` values
+    let (mut prefix, mut suffix) = (src.to_vec(), src.to_vec());
+    for i in (1..n).filter(|i| i % k != 0) {
+        prefix[i] = prefix[i].max(prefix[i - 1]);
+    }
+    for i in (0..n - 1).rev().filter(|i| (i + 1) % k != 0) {
+        suffix[i] = suffix[i].max(suffix[i + 1]);
+    }
+    out.extend((0..=n - k).map(|i| suffix[i].max(prefix[i + k - 1])));
+}
+
+/// The maximums over a disc of `radius` around the pixels, the pixels outside are `pad`
+///
+/// The disc is split into its rows, each one is a sliding maximum along the rows of the image.
+/// The result is larger by `radius` on each side.
+fn dilate(data: &[u8], width: usize, height: usize, radius: usize, pad: u8) -> Vec<u8> {
+    let (w, h) = (width + 2 * radius, height + 2 * radius);
+    let mut out = vec![0u8; w * h];
+    let mut padded = vec![pad; width + 4 * radius];
+    let mut filtered = vec![0u8; height * w];
+    let mut row = Vec::with_capacity(w);
+
+    for (dy, half) in disc_rows(radius).into_iter().enumerate() {
+        for y in 0..height {
+            padded[2 * radius..2 * radius + width].copy_from_slice(&data[y * width..][..width]);
+            max_filter(&padded[radius - half..][..w + 2 * half], half, &mut row);
+            filtered[y * w..][..w].copy_from_slice(&row);
+        }
+
+        for y in 0..h {
+            let out = &mut out[y * w..][..w];
+            for src in [y + dy, y.wrapping_sub(dy)].iter().take(if dy == 0 { 1 } else { 2 }) {
+                match src.checked_sub(radius).filter(|it| *it < height) {
+                    Some(src) => {
+                        let src = &filtered[src * w..][..w];
+                        out.iter_mut().zip(src).for_each(|(o, s)| *o = (*o).max(*s));
+                    }
+                    None => out.iter_mut().for_each(|o| *o = (*o).max(pad)),
+                }
+            }
+        }
+    }
+    out
+}
+
+/// The sums over a square of `radius` around the pixels, the pixels outside are zero
+fn box_sum(data: &[u8], width: usize, height: usize, radius: usize) -> Vec<u64> {
+    let window = |sums: &[u64], i: usize| {
+        sums[(i + radius + 1).min(sums.len() - 1)] - sums[i.saturating_sub(radius)]
+    };
+
+    let mut rows = vec![0u64; width * height];
+    let mut sums = vec![0u64; width.max(height) + 1];
+    for y in 0..height {
+        for x in 0..width {
+            sums[x + 1] = sums[x] + data[y * width + x] as u64;
+        }
+        for x in 0..width {
+            rows[y * width + x] = window(&sums[..=width], x);
+        }
+    }
+
+    let mut out = vec![0u64; width * height];
+    for x in 0..width {
+        for y in 0..height {
+            sums[y + 1] = sums[y] + rows[y * width + x];
+        }
+        for y in 0..height {
+            out[y * width + x] = window(&sums[..=height], y);
+        }
+    }
+    out
+}
+
+impl Mask {
+    /// Create a mask of `width` by `height` pixels at `origin`, cropped to the covered pixels
+    pub fn new(origin: Point, width: u32, height: u32, data: Vec<u8>) -> Mask {
+        assert_eq!(data.len(), (width * height) as usize);
+
+        let covered = |x: u32, y: u32| data[(y * width + x) as usize] > 0;
+        let (mut x0, mut y0, mut x1, mut y1) = (width, height, 0, 0);
+        for y in 0..height {
+            for x in 0..width {
+                if covered(x, y) {
+                    x0 = x0.min(x);
+                    y0 = y0.min(y);
+                    x1 = x1.max(x + 1);
+                    y1 = y1.max(y + 1);
+                }
+            }
+        }
+
+        if x0 >= x1 || y0 >= y1 {
+            return Mask { origin, width: 0, height: 0, data: Arc::new(Vec::new()) };
+        }
+
+        let mut cropped = Vec::with_capacity(((x1 - x0) * (y1 - y0)) as usize);
+        for y in y0..y1 {
+            let row = (y * width) as usize;
+            cropped.extend_from_slice(&data[row + x0 as usize..row + x1 as usize]);
+        }
+
+        Mask {
+            origin: origin + Vec2::new(x0 as f64, y0 as f64),
+            width: x1 - x0,
+            height: y1 - y0,
+            data: Arc::new(cropped),
+        }
+    }
+
+    /// Create a mask over the pixels of `bounds` by the `coverage` of each one, from 0.0 to 1.0
+    pub(super) fn from_fn(bounds: Rect, coverage: impl Fn(i64, i64) -> f64) -> Mask {
+        let bounds = bounds.expand();
+        let (x0, y0) = (bounds.x0 as i64, bounds.y0 as i64);
+        let (width, height) = (bounds.width() as u32, bounds.height() as u32);
+
+        let mut data = Vec::with_capacity((width * height) as usize);
+        for y in y0..y0 + height as i64 {
+            for x in x0..x0 + width as i64 {
+                data.push((coverage(x, y).clamp(0.0, 1.0) * 255.0).round() as u8);
+            }
+        }
+
+        Mask::new(bounds.origin(), width, height, data)
+    }
+
+    /// Combine with `other` pixel by pixel, `Replace` results in `other`
+    pub(super) fn combine(&self, other: &Mask, mode: SelectionMode) -> Mask {
+        let bounds = match mode {
+            SelectionMode::Replace => return other.clone(),
+            SelectionMode::Add => self.bounds().union(other.bounds()),
+            SelectionMode::Subtract => self.bounds(),
+            SelectionMode::Intersect => self.bounds().intersect(other.bounds()),
+        };
+
+        Mask::from_fn(bounds, |x, y| {
+            let (a, b) = (self.coverage(x, y), other.coverage(x, y));
+            match mode {
+                SelectionMode::Replace => b,
+                SelectionMode::Add => a.max(b),
+                SelectionMode::Subtract => a * (1.0 - b),
+                SelectionMode::Intersect => a.min(b),
+            }
+        })
+    }
+
+    /// The same mask with its pixels aligned to the pixels of the image
+    fn to_grid(&self) -> Mask {
+        if self.origin.x.fract() == 0.0 && self.origin.y.fract() == 0.0 {
+            return self.clone();
+        }
+        Mask::from_fn(self.bounds(), |x, y| self.coverage(x, y))
+    }
+
+    /// Expand by `radius` pixels
+    pub(super) fn grow(&self, radius: u32) -> Mask {
+        let mask = self.to_grid();
+        let (w, h, r) = (mask.width as usize, mask.height as usize, radius as usize);
+
+        let data = dilate(&mask.data, w, h, r, 0);
+        let origin = mask.origin - Vec2::new(r as f64, r as f64);
+        Mask::new(origin, (w + 2 * r) as u32, (h + 2 * r) as u32, data)
+    }
+
+    /// Contract by `radius` pixels
+    pub(super) fn shrink(&self, radius: u32) -> Mask {
+        let mask = self.to_grid();
+        let (w, h, r) = (mask.width as usize, mask.height as usize, radius as usize);
+
+        // The minimum is the maximum of the inverted coverage, and the outside is not selected
+        let inverted: Vec<u8> = mask.data.iter().map(|it| 255 - it).collect();
+        let dilated = dilate(&inverted, w, h, r, 255);
+        let data = (0..h)
+            .flat_map(|y| dilated[(y + r) * (w + 2 * r) + r..][..w].iter().map(|it| 255 - it))
+            .collect();
+        Mask::new(mask.origin, w as u32, h as u32, data)
+    }
+
+    /// A band of `width` pixels around the edge
+    pub(super) fn border(&self, width: u32) -> Mask {
+        let inner = self.shrink(width / 2);
+        self.grow(width - width / 2).combine(&inner, SelectionMode::Subtract)
+    }
+
+    /// Select the pixels mostly selected in the square of `radius` around them
+    pub(super) fn smooth(&self, radius: u32) -> Mask {
+        let mask = self.to_grid();
+        let (w, h, r) = (mask.width as usize, mask.height as usize, radius as usize);
+        let count = ((2 * r + 1) * (2 * r + 1)) as u64;
+
+        let data = box_sum(&mask.data, w, h, r)
+            .into_iter()
+            .map(|sum| match 2 * sum >= 255 * count {
+                true => 0xff,
+                false => 0,
+            })
+            .collect();
+        Mask::new(mask.origin, w as u32, h as u32, data)
+    }
+
+    /// Soften the edge with a gaussian blur of `radius` pixels
+    pub(super) fn feather(&self, radius: f64) -> Mask {
+        let sigma = radius / 2.0;
+        if sigma <= 0.0 {
+            return self.clone();
+        }
+
+        let extent = (sigma * 3.0).ceil() as i64;
+        let kernel: Vec<f64> =
+            (-extent..=extent).map(|i| (-(i * i) as f64 / (2.0 * sigma * sigma)).exp()).collect();
+        let total: f64 = kernel.iter().sum();
+        let kernel: Vec<f64> = kernel.into_iter().map(|k| k / total).collect();
+
+        let bounds = self.bounds().inflate(extent as f64, extent as f64).expand();
+        let (x0, y0) = (bounds.x0 as i64, bounds.y0 as i64);
+        let (w, h) = (bounds.width() as i64, bounds.height() as i64);
+
+        // Horizontal pass
+        let mut rows = vec![0.0; (w * h) as usize];
+        for y in 0..h {
+            for x in 0..w {
+                rows[(y * w + x) as usize] = (-extent..=extent)
+                    .zip(&kernel)
+                    .map(|(i, k)| k * self.coverage(x0 + x + i, y0 + y))
+                    .sum();
+            }
+        }
+
+        // Vertical pass
+        let row = |x: i64, y: i64| match y >= 0 && y < h {
+            true => rows[(y * w + x) as usize],
+            false => 0.0,
+        };
+        Mask::from_fn(bounds, |x, y| {
+            (-extent..=extent).zip(&kernel).map(|(i, k)| k * row(x - x0, y - y0 + i)).sum()
+        })
+    }
+
+    /// Select the pixels of `img` similar to the one at `seed`
+    pub fn magic_wand(img: &DynamicImage, seed: Point, settings: &MagicWandSettings) -> Mask {
+        let (width, height) = img.dimensions();
+        let mut data = vec![0u8; (width * height) as usize];
+
+        let (sx, sy) = (seed.x.floor(), seed.y.floor());
+        if sx < 0.0 || sy < 0.0 || sx >= width as f64 || sy >= height as f64 {
+            return Mask::new(Point::ORIGIN, width, height, data);
+        }
+
+        let target = img.get_pixel(sx as u32, sy as u32);
+        let tolerance = settings.tolerance.clamp(0.0, 255.0) as u8;
+        let similar = |x: u32, y: u32| distance(img.get_pixel(x, y), target) <= tolerance;
+
+        if !settings.contiguous {
+            for y in 0..height {
+                for x in 0..width {
+                    if similar(x, y) {
+                        data[(y * width + x) as usize] = 0xff;
+                    }
+                }
+            }
+            return Mask::new(Point::ORIGIN, width, height, data);
+        }
+
+        // 4-connected flood fill
+        let mut stack = vec![(sx as u32, sy as u32)];
+        while let Some((x, y)) = stack.pop() {
+            let idx = (y * width + x) as usize;
+            if data[idx] != 0 || !similar(x, y) {
+                continue;
+            }
+            data[idx] = 0xff;
+
+            if x > 0 {
+                stack.push((x - 1, y));
+            }
+            if x + 1 < width {
+                stack.push((x + 1, y));
+            }
+            if y > 0 {
+                stack.push((x, y - 1));
+            }
+            if y + 1 < height {
+                stack.push((x, y + 1));
+            }
+        }
+
+        Mask::new(Point::ORIGIN, width, height, data)
+    }
+
+    /// Select the pixels of `img` by their similarity to `color`
+    ///
+    /// Pixels within `fuzziness` are fully selected, and the coverage falls off linearly
+    /// to zero at twice of it. Only the exact color is selected if `fuzziness` is zero.
+    pub fn color_range(img: &DynamicImage, color: Rgba<u8>, fuzziness: f64) -> Mask {
+        let (width, height) = img.dimensions();
+        let fuzziness = fuzziness.clamp(0.0, 255.0);
+        let mut data = Vec::with_capacity((width * height) as usize);
+
+        for y in 0..height {
+            for x in 0..width {
+                let d = distance(img.get_pixel(x, y), color) as f64;
+                let coverage = if d <= fuzziness {
+                    1.0
+                } else if fuzziness > 0.0 {
+                    (2.0 - d / fuzziness).max(0.0)
+                } else {
+                    0.0
+                };
+                data.push((coverage * 255.0).round() as u8);
+            }
+        }
+
+        Mask::new(Point::ORIGIN, width, height, data)
+    }
+
+    /// The coverage of the pixels within `bounds()`
+    pub fn to_image(&self) -> GrayImage {
+        GrayImage::from_raw(self.width, self.height, self.data.to_vec())
+            .expect("Mask data must match its size")
+    }
+
+    pub fn bounds(&self) -> Rect {
+        Rect::from_origin_size(self.origin, (self.width as f64, self.height as f64))
+    }
+
+    /// Number of selected pixels, weighted by their coverage
+    pub fn area(&self) -> f64 {
+        self.data.iter().map(|it| *it as f64).sum::<f64>() / 255.0
+    }
+
+    /// Coverage of the mask pixel containing `pt`
+    fn value(&self, pt: Point) -> u8 {
+        let (x, y) = ((pt.x - self.origin.x).floor(), (pt.y - self.origin.y).floor());
+        if x < 0.0 || y < 0.0 || x >= self.width as f64 || y >= self.height as f64 {
+            return 0;
+        }
+        self.data[y as usize * self.width as usize + x as usize]
+    }
+
+    /// How much of the pixel at `(x, y)` is selected, from 0.0 to 1.0
+    fn coverage(&self, x: i64, y: i64) -> f64 {
+        self.value(Point::new(x as f64 + 0.5, y as f64 + 0.5)) as f64 / 255.0
+    }
+
+    /// The boundary between the pixels inside and outside, as separated segments
+    pub fn outline(&self) -> BezPath {
+        let (w, h) = (self.width as i64, self.height as i64);
+        let inside = |x: i64, y: i64| {
+            x >= 0 && y >= 0 && x < w && y < h && self.data[(y * w + x) as usize] >= THRESHOLD
+        };
+        let pt = |x: i64, y: i64| self.origin + Vec2::new(x as f64, y as f64);
+
+        let mut path = BezPath::new();
+        // Horizontal edges, between the rows `y - 1` and `y`
+        for y in 0..=h {
+            let mut start = None;
+            for x in 0..=w {
+                let edge = x < w && inside(x, y - 1) != inside(x, y);
+                match (edge, start) {
+                    (true, None) => start = Some(x),
+                    (false, Some(x0)) => {
+                        path.move_to(pt(x0, y));
+                        path.line_to(pt(x, y));
+                        start = None;
+                    }
+                    _ => (),
+                }
+            }
+        }
+        // Vertical edges, between the columns `x - 1` and `x`
+        for x in 0..=w {
+            let mut start = None;
+            for y in 0..=h {
+                let edge = y < h && inside(x - 1, y) != inside(x, y);
+                match (edge, start) {
+                    (true, None) => start = Some(y),
+                    (false, Some(y0)) => {
+                        path.move_to(pt(x, y0));
+                        path.line_to(pt(x, y));
+                        start = None;
+                    }
+                    _ => (),
+                }
+            }
+        }
+
+        path
+    }
+}
+
+impl SelectionShape for Mask {
+    fn description(&self) -> String {
+        self.bounds().description()
+    }
+
+    fn size(&self) -> Size {
+        self.bounds().size()
+    }
+
+    fn copy(&self, img: Arc<DynamicImage>, mode: CopyMode) -> Option<Arc<DynamicImage>> {
+        copy_masked(self.bounds(), img, mode, |x, y| self.coverage(x as i64, y as i64))
+    }
+
+    fn cutout(&self, img: Arc<DynamicImage>) -> Option<Arc<DynamicImage>> {
+        cutout_masked(self.bounds(), img, |x, y| self.coverage(x as i64, y as i64))
+    }
+
+    fn contains(&self, pt: Point) -> bool {
+        self.value(pt) >= THRESHOLD
+    }
+
+    fn to_mask(&self) -> Mask {
+        self.clone()
+    }
+
+    fn transform(&self, offset: Vec2) -> Self {
+        Mask { origin: self.origin + offset, ..self.clone() }
+    }
+
+    fn same(&self, other: &Self) -> bool {
+        self.origin == other.origin
+            && self.width == other.width
+            && Arc::ptr_eq(&self.data, &other.data)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::image_utils::{
+        colors::{BLACK, TRANSPARENT, WHITE},
+        make_color_img,
+    };
+    use druid::kurbo::Shape;
+    use image::GenericImage;
+
+    /// A white image with a black cross at the column and row 4
+    fn cross() -> DynamicImage {
+        let mut img = make_color_img(8, 8, WHITE);
+        for i in 0..8 {
+            img.put_pixel(4, i, BLACK);
+            img.put_pixel(i, 4, BLACK);
+        }
+        img
+    }
+
+    #[test]
+    fn magic_wand_should_fill_contiguous() {
+        let settings = MagicWandSettings::default();
+        let mask = Mask::magic_wand(&cross(), Point::new(1.5, 1.5), &settings);
+        assert_eq!(mask.bounds(), Rect::new(0.0, 0.0, 4.0, 4.0));
+        assert_eq!(mask.area(), 16.0);
+        assert!(SelectionShape::contains(&mask, Point::new(0.5, 0.5)));
+        assert!(!SelectionShape::contains(&mask, Point::new(5.5, 5.5)));
+
+        let settings = MagicWandSettings { contiguous: false, ..settings };
+        let mask = Mask::magic_wand(&cross(), Point::new(1.5, 1.5), &settings);
+        assert_eq!(mask.bounds(), Rect::new(0.0, 0.0, 8.0, 8.0));
+        assert_eq!(mask.area(), 49.0);
+        assert!(SelectionShape::contains(&mask, Point::new(5.5, 5.5)));
+        assert!(!SelectionShape::contains(&mask, Point::new(4.5, 4.5)));
+    }
+
+    #[test]
+    fn magic_wand_should_use_tolerance() {
+        let mut img = make_color_img(4, 1, Rgba([100, 100, 100, 0xff]));
+        img.put_pixel(1, 0, Rgba([110, 100, 100, 0xff]));
+        img.put_pixel(2, 0, Rgba([150, 100, 100, 0xff]));
+
+        let settings = MagicWandSettings { tolerance: 10.0, ..Default::default() };
+        let mask = Mask::magic_wand(&img, Point::new(0.0, 0.0), &settings);
+        assert_eq!(mask.area(), 2.0);
+
+        let settings = MagicWandSettings { tolerance: 0.0, contiguous: false, ..settings };
+        let mask = Mask::magic_wand(&img, Point::new(0.0, 0.0), &settings);
+        assert_eq!(mask.area(), 2.0);
+        assert_eq!(mask.bounds(), Rect::new(0.0, 0.0, 4.0, 1.0));
+
+        let mask = Mask::magic_wand(&img, Point::new(-1.0, 0.0), &settings);
+        assert_eq!(mask.area(), 0.0);
+    }
+
+    #[test]
+    fn color_range_should_fall_off_by_fuzziness() {
+        let mut img = make_color_img(3, 1, Rgba([100, 100, 100, 0xff]));
+        img.put_pixel(1, 0, Rgba([115, 100, 100, 0xff]));
+        img.put_pixel(2, 0, Rgba([200, 100, 100, 0xff]));
+
+        let mask = Mask::color_range(&img, Rgba([100, 100, 100, 0xff]), 10.0);
+        assert_eq!(mask.bounds(), Rect::new(0.0, 0.0, 2.0, 1.0));
+        assert_eq!(mask.coverage(0, 0), 1.0);
+        assert!(mask.coverage(1, 0) > 0.4 && mask.coverage(1, 0) < 0.6);
+        assert_eq!(mask.coverage(2, 0), 0.0);
+    }
+
+    #[test]
+    fn color_range_should_select_exact_color_without_fuzziness() {
+        let mut img = make_color_img(2, 1, Rgba([100, 100, 100, 0xff]));
+        img.put_pixel(1, 0, Rgba([101, 100, 100, 0xff]));
+
+        let mask = Mask::color_range(&img, Rgba([100, 100, 100, 0xff]), 0.0);
+        assert_eq!(mask.bounds(), Rect::new(0.0, 0.0, 1.0, 1.0));
+        assert_eq!(mask.coverage(0, 0), 1.0);
+    }
+
+    #[test]
+    fn mask_copy_and_cutout_should_works() {
+        let mask = Mask::magic_wand(&cross(), Point::new(1.5, 1.5), &Default::default());
+        let white = Arc::new(make_color_img(8, 8, WHITE));
+
+        let img = mask.copy(white.clone(), CopyMode::Shrink).unwrap();
+        assert_eq!(img.dimensions(), (4, 4));
+        assert_eq!(img.get_pixel(3, 3), WHITE);
+
+        let mask = mask.transform(Vec2::new(2.0, 2.0));
+        let img = mask.copy(white.clone(), CopyMode::Shrink).unwrap();
+        assert_eq!(img.dimensions(), (4, 4));
+        assert_eq!(img.get_pixel(0, 0), WHITE);
+
+        let img = mask.cutout(white).unwrap();
+        assert_eq!(img.get_pixel(2, 2), TRANSPARENT);
+        assert_eq!(img.get_pixel(5, 5), TRANSPARENT);
+        assert_eq!(img.get_pixel(6, 6), WHITE);
+        assert_eq!(img.get_pixel(1, 1), WHITE);
+    }
+
+    #[test]
+    fn selection_mode_should_combine_selections() {
+        use crate::{Selection, SelectionMode};
+
+        let a = Selection::from(Rect::new(0.0, 0.0, 4.0, 4.0));
+        let b = Selection::from(Rect::new(2.0, 2.0, 6.0, 6.0));
+
+        let sel = SelectionMode::Add.combine(Some(&a), b.clone());
+        assert_eq!(sel.area(), 28.0);
+        assert_eq!(sel.description(), Rect::new(0.0, 0.0, 6.0, 6.0).description());
+
+        let sel = SelectionMode::Subtract.combine(Some(&a), b.clone());
+        assert_eq!(sel.area(), 12.0);
+        assert!(sel.contains(Point::new(1.5, 1.5)));
+        assert!(!sel.contains(Point::new(3.5, 3.5)));
+
+        let sel = SelectionMode::Intersect.combine(Some(&a), b.clone());
+        assert_eq!(sel.area(), 4.0);
+        assert_eq!(sel.description(), Rect::new(2.0, 2.0, 4.0, 4.0).description());
+
+        let sel = SelectionMode::Replace.combine(Some(&a), b.clone());
+        assert_eq!(sel.area(), 16.0);
+        assert_eq!(SelectionMode::Subtract.combine(None, b).area(), 0.0);
+    }
+
+    #[test]
+    fn combined_mask_should_keep_coverage() {
+        let a = Mask::new(Point::new(-2.0, 0.0), 2, 1, vec![0xff, 0x80]);
+        let b = Mask::new(Point::ORIGIN, 1, 1, vec![0xff]);
+
+        let mask = a.combine(&b, SelectionMode::Add);
+        assert_eq!(mask.bounds(), Rect::new(-2.0, 0.0, 1.0, 1.0));
+        assert_eq!(mask.value(Point::new(-0.5, 0.5)), 0x80);
+
+        let mask =
+            a.combine(&Mask::new(Point::new(-1.0, 0.0), 1, 1, vec![0x80]), SelectionMode::Subtract);
+        assert_eq!(mask.value(Point::new(-1.5, 0.5)), 0xff);
+        assert_eq!(mask.value(Point::new(-0.5, 0.5)), 0x40);
+    }
+
+    #[test]
+    fn mask_should_grow_and_shrink() {
+        let mask = Rect::new(2.0, 2.0, 6.0, 6.0).to_mask();
+
+        let grown = mask.grow(2);
+        assert_eq!(grown.bounds(), Rect::new(0.0, 0.0, 8.0, 8.0));
+        // Corners are round
+        assert_eq!(grown.coverage(0, 0), 0.0);
+        assert_eq!(grown.coverage(0, 3), 1.0);
+
+        let shrunk = mask.shrink(1);
+        assert_eq!(shrunk.bounds(), Rect::new(3.0, 3.0, 5.0, 5.0));
+        assert_eq!(shrunk.area(), 4.0);
+
+        let border = mask.border(2);
+        assert_eq!(border.coverage(1, 3), 1.0);
+        assert_eq!(border.coverage(2, 3), 1.0);
+        assert_eq!(border.coverage(3, 3), 0.0);
+    }
+
+    #[test]
+    fn mask_modify_should_match_naive_filters() {
+        let data = (0..11 * 7).map(|i: u32| (i * 53 % 256) as u8).collect();
+        let mask = Mask::new(Point::new(1.5, -2.0), 11, 7, data);
+        let bounds = mask.bounds().inflate(4.0, 4.0).expand();
+        let pixels = |m: &Mask| -> Vec<u8> {
+            let (x0, x1, y0, y1) =
+                (bounds.x0 as i64, bounds.x1 as i64, bounds.y0 as i64, bounds.y1 as i64);
+            let pts = (y0..y1).flat_map(|y| (x0..x1).map(move |x| (x, y)));
+            pts.map(|(x, y)| (m.coverage(x, y) * 255.0).round() as u8).collect()
+        };
+        let naive = |r: i64, square: bool, f: &dyn Fn(Vec<f64>) -> f64| {
+            let inside = mask.bounds().expand();
+            Mask::from_fn(bounds.inflate(r as f64, r as f64), |x, y| {
+                let offsets = (-r..=r).flat_map(|dy| (-r..=r).map(move |dx| (dx, dy)));
+                let values = offsets
+                    .filter(|(dx, dy)| square || dx * dx + dy * dy <= r * r)
+                    .map(|(dx, dy)| mask.coverage(x + dx, y + dy))
+                    .collect();
+                match square && !inside.contains(Point::new(x as f64, y as f64)) {
+                    true => 0.0,
+                    false => f(values),
+                }
+            })
+        };
+
+        for r in 0..4 {
+            let grown = naive(r, false, &|it| it.into_iter().fold(0.0, f64::max));
+            assert_eq!(pixels(&mask.grow(r as u32)), pixels(&grown));
+            let shrunk = naive(r, false, &|it| it.into_iter().fold(1.0, f64::min));
+            assert_eq!(pixels(&mask.shrink(r as u32)), pixels(&shrunk));
+        }
+        let smooth = naive(1, true, &|it| (it.iter().sum::<f64>() / 9.0 >= 0.5) as u8 as f64);
+        assert_eq!(pixels(&mask.smooth(1)), pixels(&smooth));
+    }
+
+    #[test]
+    fn mask_smooth_should_remove_stray_pixels() {
+        // A square with a hole, and a stray pixel
+        let mut data = vec![0xff; 25];
+        data[12] = 0;
+        let mask = Mask::new(Point::ORIGIN, 5, 5, data)
+            .combine(&Mask::new(Point::new(8.0, 8.0), 1, 1, vec![0xff]), SelectionMode::Add);
+
+        let smooth = mask.smooth(1);
+        assert_eq!(smooth.coverage(8, 8), 0.0);
+        assert_eq!(smooth.coverage(2, 2), 1.0);
+        assert_eq!(smooth.coverage(1, 1), 1.0);
+        assert_eq!(smooth.bounds(), Rect::new(0.0, 0.0, 5.0, 5.0));
+    }
+
+    #[test]
+    fn mask_feather_should_soften_edges() {
+        let mask = Rect::new(0.0, 0.0, 8.0, 8.0).to_mask().feather(4.0);
+        assert_eq!(mask.bounds(), Rect::new(-6.0, -6.0, 14.0, 14.0));
+        assert!((mask.area() - 64.0).abs() < 0.5);
+        assert!(mask.coverage(4, 4) > 0.9);
+        assert!(mask.coverage(0, 4) > 0.4 && mask.coverage(0, 4) < 0.6);
+        assert!(mask.coverage(-3, 4) < 0.1);
+    }
+
+    #[test]
+    fn mask_outline_should_follow_boundary() {
+        let mask = Mask::new(Point::new(2.0, 2.0), 2, 2, vec![0xff, 0xff, 0xff, 0]);
+        let segments: Vec<_> = mask.outline().segments().collect();
+        // An L shape has 6 sides
+        assert_eq!(segments.len(), 6);
+        assert_eq!(mask.outline().bounding_box(), Rect::new(2.0, 2.0, 4.0, 4.0));
+    }
+}
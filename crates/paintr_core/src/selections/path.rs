@@ -0,0 +1,198 @@
+use druid::kurbo::{BezPath, PathEl, Shape};
+use druid::{Affine, Point, Rect, Size, Vec2};
+use image::DynamicImage;
+use std::sync::Arc;
+
+use super::{copy_masked, cutout_masked, rect::intersect, CopyMode, Mask, SelectionShape};
+
+const TOLERANCE: f64 = 0.1;
+
+/// Flatten the path into line segments, every sub path is closed
+fn edges(path: &BezPath) -> Vec<(Point, Point)> {
+    let mut edges = Vec::new();
+    let (mut start, mut last) = (Point::ORIGIN, Point::ORIGIN);
+
+    path.flatten(TOLERANCE, |el| match el {
+        PathEl::MoveTo(p) => {
+            if last != start {
+                edges.push((last, start));
+            }
+            start = p;
+            last = p;
+        }
+        PathEl::LineTo(p) => {
+            edges.push((last, p));
+            last = p;
+        }
+        PathEl::ClosePath => {
+            if last != start {
+                edges.push((last, start));
+            }
+            last = start;
+        }
+        _ => unreachable!("Flattened path only has lines"),
+    });
+    if last != start {
+        edges.push((last, start));
+    }
+
+    edges
+}
+
+/// The x of all crossings between the edges and the horizontal line at `y`
+fn crossings(edges: &[(Point, Point)], y: f64) -> Vec<f64> {
+    edges
+        .iter()
+        .filter(|(a, b)| (a.y <= y) != (b.y <= y))
+        .map(|(a, b)| a.x + (y - a.y) * (b.x - a.x) / (b.y - a.y))
+        .collect()
+}
+
+/// Pixels inside the path by the even-odd rule, tested at pixel centers
+struct Raster {
+    bounds: Rect,
+    width: usize,
+    mask: Vec<bool>,
+}
+
+impl Raster {
+    fn new(path: &BezPath, bounds: Rect) -> Raster {
+        let edges = edges(path);
+        let (width, height) = (bounds.width() as usize, bounds.height() as usize);
+        let mut mask = vec![false; width * height];
+
+        for row in 0..height {
+            let mut xs = crossings(&edges, bounds.y0 + row as f64 + 0.5);
+            xs.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+            for span in xs.chunks_exact(2) {
+                // Pixel centers within the span
+                let x0 = ((span[0] - bounds.x0 - 0.5).ceil().max(0.0) as usize).min(width);
+                let x1 = ((span[1] - bounds.x0 - 0.5).ceil().max(0.0) as usize).min(width);
+                for it in &mut mask[row * width + x0..row * width + x1] {
+                    *it = true;
+                }
+            }
+        }
+
+        Raster { bounds, width, mask }
+    }
+
+    fn coverage(&self, x: i64, y: i64) -> f64 {
+        let (x, y) = (x as f64 - self.bounds.x0, y as f64 - self.bounds.y0);
+        if x < 0.0 || y < 0.0 || x >= self.bounds.width() || y >= self.bounds.height() {
+            return 0.0;
+        }
+
+        match self.mask[y as usize * self.width + x as usize] {
+            true => 1.0,
+            false => 0.0,
+        }
+    }
+}
+
+impl SelectionShape for BezPath {
+    fn description(&self) -> String {
+        self.bounding_box().description()
+    }
+
+    fn size(&self) -> Size {
+        self.bounding_box().size()
+    }
+
+    fn copy(&self, img: Arc<DynamicImage>, mode: CopyMode) -> Option<Arc<DynamicImage>> {
+        let bounds = self.bounding_box();
+        let raster = Raster::new(self, intersect(bounds, &img)?.expand());
+
+        copy_masked(bounds, img, mode, |x, y| raster.coverage(x as i64, y as i64))
+    }
+
+    fn cutout(&self, img: Arc<DynamicImage>) -> Option<Arc<DynamicImage>> {
+        let bounds = self.bounding_box();
+        let raster = Raster::new(self, intersect(bounds, &img)?.expand());
+
+        cutout_masked(bounds, img, |x, y| raster.coverage(x as i64, y as i64))
+    }
+
+    fn to_mask(&self) -> Mask {
+        let bounds = self.bounding_box().expand();
+        let raster = Raster::new(self, bounds);
+
+        Mask::from_fn(bounds, |x, y| raster.coverage(x, y))
+    }
+
+    fn contains(&self, pt: Point) -> bool {
+        let count = crossings(&edges(self), pt.y).into_iter().filter(|x| *x < pt.x).count();
+        count % 2 == 1
+    }
+
+    fn transform(&self, offset: Vec2) -> Self {
+        Affine::translate(offset) * self.clone()
+    }
+
+    fn same(&self, other: &Self) -> bool {
+        self.elements() == other.elements()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::image_utils::{
+        colors::{TRANSPARENT, WHITE},
+        make_color_img,
+    };
+    use image::GenericImageView;
+
+    fn polygon(pts: &[(f64, f64)]) -> BezPath {
+        let mut path = BezPath::new();
+        path.move_to(pts[0]);
+        for p in &pts[1..] {
+            path.line_to(*p);
+        }
+        path.close_path();
+        path
+    }
+
+    #[test]
+    fn path_contains_should_use_even_odd() {
+        let triangle = polygon(&[(0.0, 0.0), (8.0, 0.0), (0.0, 8.0)]);
+        assert!(SelectionShape::contains(&triangle, Point::new(1.0, 1.0)));
+        assert!(!SelectionShape::contains(&triangle, Point::new(7.0, 7.0)));
+
+        // A hole made by a sub path inside another one
+        let mut path = polygon(&[(0.0, 0.0), (8.0, 0.0), (8.0, 8.0), (0.0, 8.0)]);
+        path.extend(polygon(&[(2.0, 2.0), (6.0, 2.0), (6.0, 6.0), (2.0, 6.0)]));
+        assert!(SelectionShape::contains(&path, Point::new(1.0, 1.0)));
+        assert!(!SelectionShape::contains(&path, Point::new(4.0, 4.0)));
+    }
+
+    #[test]
+    fn path_copy_should_works() {
+        let white = Arc::new(make_color_img(8, 8, WHITE));
+        let triangle = polygon(&[(0.0, 0.0), (8.0, 0.0), (0.0, 8.0)]);
+
+        let img = triangle.copy(white.clone(), CopyMode::Shrink).unwrap();
+        assert_eq!(img.dimensions(), (8, 8));
+        assert_eq!(img.get_pixel(1, 1), WHITE);
+        assert_eq!(img.get_pixel(6, 6), TRANSPARENT);
+
+        let img = triangle.transform(Vec2::new(-4.0, 0.0)).copy(white, CopyMode::Expand).unwrap();
+        assert_eq!(img.dimensions(), (8, 8));
+        assert_eq!(img.get_pixel(4, 1), WHITE);
+        assert_eq!(img.get_pixel(1, 1), TRANSPARENT);
+        assert_eq!(img.get_pixel(6, 6), TRANSPARENT);
+    }
+
+    #[test]
+    fn path_cutout_should_works() {
+        let white = Arc::new(make_color_img(8, 8, WHITE));
+        let mut path = polygon(&[(0.0, 0.0), (8.0, 0.0), (8.0, 8.0), (0.0, 8.0)]);
+        path.extend(polygon(&[(2.0, 2.0), (6.0, 2.0), (6.0, 6.0), (2.0, 6.0)]));
+
+        let img = path.cutout(white).unwrap();
+        assert_eq!(img.get_pixel(0, 0), TRANSPARENT);
+        assert_eq!(img.get_pixel(7, 7), TRANSPARENT);
+        assert_eq!(img.get_pixel(4, 4), WHITE);
+    }
+}
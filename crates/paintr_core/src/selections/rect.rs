@@ -4,7 +4,7 @@ use image::{DynamicImage, GenericImage, GenericImageView};
 use imageproc::rect::Rect as ImRect;
 use std::sync::Arc;
 
-use super::{CopyMode, SelectionShape};
+use super::{CopyMode, Mask, SelectionShape};
 
 impl SelectionShape for Rect {
     fn description(&self) -> String {
@@ -71,6 +71,15 @@ impl SelectionShape for Rect {
         self.contains(pt)
     }
 
+    fn to_mask(&self) -> Mask {
+        Mask::from_fn(*self, |x, y| {
+            match self.contains(Point::new(x as f64 + 0.5, y as f64 + 0.5)) {
+                true => 1.0,
+                false => 0.0,
+            }
+        })
+    }
+
     fn transform(&self, offset: Vec2) -> Self {
         let new_origin = (self.origin().to_vec2() + offset).to_point();
         Rect::from_origin_size(new_origin, self.size())
@@ -81,7 +90,7 @@ impl SelectionShape for Rect {
     }
 }
 
-fn intersect(rt: Rect, img: &DynamicImage) -> Option<Rect> {
+pub(super) fn intersect(rt: Rect, img: &DynamicImage) -> Option<Rect> {
     let img_dims = img.dimensions();
     let bound = Rect::from_origin_size(Point::ORIGIN, (img_dims.0 as f64, img_dims.1 as f64));
     let rect = bound.intersect(rt);
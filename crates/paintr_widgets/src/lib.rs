@@ -1,19 +1,40 @@
-use druid::{Cursor, Data, Lens};
-use paintr_core::{CanvasData, Edit, EditDesc, EditKind, UndoHistory};
+use druid::{Color, Cursor, Data, Lens};
+use paintr_core::{
+    BrushSettings, CanvasData, Edit, EditDesc, EditKind, MagicWandSettings, UndoHistory,
+};
 
 pub mod theme_ext;
+mod viewport;
 pub mod widgets;
 
+pub use viewport::Viewport;
+
 #[derive(Debug, Clone, Data, Lens)]
 pub struct EditorState<T> {
     pub canvas: Option<CanvasData>,
     pub history: UndoHistory<CanvasData>,
     pub tool: T,
+    pub view: Viewport,
+    pub brush: BrushSettings,
+    pub anti_alias: bool,
+    pub wand: MagicWandSettings,
+    pub foreground: Color,
+    pub background: Color,
     pub is_editing: bool,
     pub cursor: Option<Cursor>,
 }
 
 impl<T> EditorState<T> {
+    pub fn swap_colors(&mut self) {
+        std::mem::swap(&mut self.foreground, &mut self.background);
+    }
+
+    /// Reset to black foreground and white background
+    pub fn reset_colors(&mut self) {
+        self.foreground = Color::BLACK;
+        self.background = Color::WHITE;
+    }
+
     pub fn do_edit(&mut self, edit: impl Edit<CanvasData> + 'static, kind: EditKind) -> bool {
         self.is_editing = kind == EditKind::Mergeable;
 
@@ -26,6 +47,17 @@ impl<T> EditorState<T> {
         }
     }
 
+    /// Revert the edit in progress, without leaving it in the history
+    pub fn do_cancel(&mut self) -> Option<EditDesc> {
+        if !self.is_editing {
+            return None;
+        }
+        self.is_editing = false;
+
+        let (history, canvas) = (&mut self.history, self.canvas.as_mut()?);
+        history.cancel(canvas)
+    }
+
     pub fn do_undo(&mut self) -> Option<EditDesc> {
         if self.is_editing {
             return None;
@@ -42,4 +74,56 @@ impl<T> EditorState<T> {
         let (history, canvas) = (&mut self.history, self.canvas.as_mut()?);
         history.redo(canvas)
     }
+
+    /// Switch to the previous or next sibling branch of the current step
+    pub fn do_switch_branch(&mut self, forward: bool) -> Option<EditDesc> {
+        if self.is_editing {
+            return None;
+        }
+
+        let (history, canvas) = (&mut self.history, self.canvas.as_mut()?);
+        history.switch_branch(canvas, forward)
+    }
+
+    /// Undo or redo until `position` steps of the history are applied
+    pub fn do_goto(&mut self, position: usize) -> bool {
+        if self.is_editing {
+            return false;
+        }
+
+        match (&mut self.history, self.canvas.as_mut()) {
+            (history, Some(canvas)) => history.goto(canvas, position),
+            _ => false,
+        }
+    }
+
+    pub fn do_take_snapshot(&mut self, name: impl Into<String>) -> bool {
+        match &self.canvas {
+            Some(canvas) => {
+                self.history.take_snapshot(name, canvas);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn do_restore_snapshot(&mut self, index: usize) -> Option<EditDesc> {
+        if self.is_editing {
+            return None;
+        }
+
+        let (history, canvas) = (&mut self.history, self.canvas.as_mut()?);
+        history.restore_snapshot(canvas, index)
+    }
+
+    /// Whether the canvas has changes since it was opened or saved
+    pub fn is_dirty(&self) -> bool {
+        self.canvas.is_some() && self.history.is_dirty()
+    }
+
+    pub fn mark_saved(&mut self) {
+        if let Some(canvas) = &self.canvas {
+            self.history.mark_saved(canvas);
+        }
+    }
 }
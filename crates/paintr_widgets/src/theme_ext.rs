@@ -5,8 +5,24 @@ pub const PAINTR_TOGGLE_ON: Key<Color> = Key::new("paintr_toggle_on");
 pub const PAINTR_TOGGLE_OFF: Key<Color> = Key::new("paintr_toggle_off");
 pub const PAINTR_TOGGLE_FOREGROND: Key<Color> = Key::new("paintr_toggle_foreground");
 
+/// Size of a checkerboard cell on screen, in pixels
+pub const PAINTR_CHECKER_SIZE: Key<f64> = Key::new("paintr_checker_size");
+pub const PAINTR_CHECKER_LIGHT: Key<Color> = Key::new("paintr_checker_light");
+pub const PAINTR_CHECKER_DARK: Key<Color> = Key::new("paintr_checker_dark");
+pub const PAINTR_CANVAS_BORDER: Key<Color> = Key::new("paintr_canvas_border");
+pub const PAINTR_CANVAS_SHADOW: Key<Color> = Key::new("paintr_canvas_shadow");
+/// Color over the area outside of the canvas
+pub const PAINTR_PASTEBOARD: Key<Color> = Key::new("paintr_pasteboard");
+
 pub fn init(env: &mut Env) {
     env.set(PAINTR_TOGGLE_FOREGROND, Color::rgb(0.0, 0.0, 0.0));
     env.set(PAINTR_TOGGLE_ON, Color::rgb(0.3, 0.3, 0.3));
     env.set(PAINTR_TOGGLE_OFF, Color::rgb(0.8, 0.8, 0.8));
+
+    env.set(PAINTR_CHECKER_SIZE, 8.0);
+    env.set(PAINTR_CHECKER_LIGHT, Color::rgb(1.0, 1.0, 1.0));
+    env.set(PAINTR_CHECKER_DARK, Color::rgb(0.8, 0.8, 0.8));
+    env.set(PAINTR_CANVAS_BORDER, Color::rgb(0.1, 0.1, 0.1));
+    env.set(PAINTR_CANVAS_SHADOW, Color::rgba(0.0, 0.0, 0.0, 0.5));
+    env.set(PAINTR_PASTEBOARD, Color::rgba(0.0, 0.0, 0.0, 0.3));
 }
@@ -0,0 +1,153 @@
+use druid::{Affine, Data, Point, Rect, Size, Vec2};
+
+const ZOOM_LEVELS: [f64; 22] = [
+    0.01,
+    0.02,
+    0.03,
+    0.05,
+    1.0 / 12.0,
+    0.125,
+    1.0 / 6.0,
+    0.25,
+    1.0 / 3.0,
+    0.5,
+    2.0 / 3.0,
+    1.0,
+    2.0,
+    3.0,
+    4.0,
+    5.0,
+    6.0,
+    8.0,
+    12.0,
+    16.0,
+    24.0,
+    32.0,
+];
+
+/// The view transform of the editor
+///
+/// It maps the document coordinates to the view coordinates, by `zoom` then `offset`.
+#[derive(Debug, Clone, Copy, PartialEq, Data)]
+pub struct Viewport {
+    zoom: f64,
+    offset: Vec2,
+}
+
+impl Default for Viewport {
+    fn default() -> Viewport {
+        Viewport { zoom: 1.0, offset: Vec2::ZERO }
+    }
+}
+
+impl Viewport {
+    pub const MIN_ZOOM: f64 = 0.01;
+    pub const MAX_ZOOM: f64 = 32.0;
+
+    pub fn zoom(&self) -> f64 {
+        self.zoom
+    }
+
+    pub fn affine(&self) -> Affine {
+        Affine::translate(self.offset) * Affine::scale(self.zoom)
+    }
+
+    pub fn to_doc(&self, pt: Point) -> Point {
+        ((pt.to_vec2() - self.offset) / self.zoom).to_point()
+    }
+
+    pub fn to_view(&self, pt: Point) -> Point {
+        (pt.to_vec2() * self.zoom + self.offset).to_point()
+    }
+
+    pub fn pan(&mut self, delta: Vec2) {
+        self.offset += delta;
+    }
+
+    /// Set the zoom, while `anchor` (in view coordinates) stays at the same document point
+    pub fn zoom_around(&mut self, zoom: f64, anchor: Point) {
+        let doc = self.to_doc(anchor);
+        self.zoom = zoom.clamp(Self::MIN_ZOOM, Self::MAX_ZOOM);
+        self.offset = anchor.to_vec2() - doc.to_vec2() * self.zoom;
+    }
+
+    /// Zoom to the next preset level
+    pub fn zoom_in(&mut self, anchor: Point) {
+        let zoom = ZOOM_LEVELS.iter().find(|it| **it > self.zoom * 1.001);
+        self.zoom_around(zoom.copied().unwrap_or(Self::MAX_ZOOM), anchor);
+    }
+
+    /// Zoom to the previous preset level
+    pub fn zoom_out(&mut self, anchor: Point) {
+        let zoom = ZOOM_LEVELS.iter().rev().find(|it| **it < self.zoom / 1.001);
+        self.zoom_around(zoom.copied().unwrap_or(Self::MIN_ZOOM), anchor);
+    }
+
+    /// Zoom and center `rect` (in document coordinates) to a view of `size`
+    pub fn fit(&mut self, rect: Rect, size: Size) {
+        if rect.area() <= 0.0 || size.width <= 0.0 || size.height <= 0.0 {
+            return;
+        }
+
+        let zoom = (size.width / rect.width()).min(size.height / rect.height());
+        self.zoom = zoom.clamp(Self::MIN_ZOOM, Self::MAX_ZOOM);
+        self.offset = size.to_vec2() / 2.0 - rect.center().to_vec2() * self.zoom;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn viewport_should_convert_coordinates() {
+        let mut view = Viewport::default();
+        view.pan(Vec2::new(10.0, 20.0));
+        view.zoom_around(2.0, Point::new(10.0, 20.0));
+
+        let pt = Point::new(3.0, 4.0);
+        assert_eq!(view.to_view(pt), Point::new(16.0, 28.0));
+        assert_eq!(view.to_doc(view.to_view(pt)), pt);
+        assert_eq!(view.affine() * pt, view.to_view(pt));
+    }
+
+    #[test]
+    fn zoom_should_keep_anchor() {
+        let mut view = Viewport::default();
+        let anchor = Point::new(40.0, 30.0);
+        let doc = view.to_doc(anchor);
+
+        view.zoom_in(anchor);
+        assert_eq!(view.zoom(), 2.0);
+        assert_eq!(view.to_doc(anchor), doc);
+
+        view.zoom_out(anchor);
+        view.zoom_out(anchor);
+        assert!((view.zoom() - 2.0 / 3.0).abs() < 1e-9);
+        assert!((view.to_doc(anchor) - doc).hypot() < 1e-9);
+    }
+
+    #[test]
+    fn zoom_should_be_clamped() {
+        let mut view = Viewport::default();
+        view.zoom_around(100.0, Point::ORIGIN);
+        assert_eq!(view.zoom(), Viewport::MAX_ZOOM);
+        view.zoom_in(Point::ORIGIN);
+        assert_eq!(view.zoom(), Viewport::MAX_ZOOM);
+
+        view.zoom_around(0.0, Point::ORIGIN);
+        assert_eq!(view.zoom(), Viewport::MIN_ZOOM);
+        view.zoom_out(Point::ORIGIN);
+        assert_eq!(view.zoom(), Viewport::MIN_ZOOM);
+    }
+
+    #[test]
+    fn fit_should_center_rect() {
+        let mut view = Viewport::default();
+        view.fit(Rect::new(0.0, 0.0, 400.0, 100.0), Size::new(200.0, 200.0));
+
+        assert_eq!(view.zoom(), 0.5);
+        assert_eq!(view.to_view(Point::new(0.0, 0.0)), Point::new(0.0, 75.0));
+        assert_eq!(view.to_view(Point::new(400.0, 100.0)), Point::new(200.0, 125.0));
+    }
+}
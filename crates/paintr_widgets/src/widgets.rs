@@ -1,6 +1,8 @@
 mod canvas;
+mod color_picker;
 mod conditional;
 mod editor;
+mod history;
 mod modal;
 mod named;
 mod painter;
@@ -8,8 +10,10 @@ mod radio;
 
 pub mod notif_bar;
 
+pub use color_picker::ColorPicker;
 pub use conditional::Conditional;
-pub use editor::{Editor, Tool, ToolCtx, ToolKind};
+pub use editor::{Editor, Tool, ToolCtx, ToolKind, Zoom, ZOOM_ACTION};
+pub use history::HistoryPanel;
 pub use modal::{Modal, ModalContainer};
 pub use named::Named;
 pub use painter::Painter;
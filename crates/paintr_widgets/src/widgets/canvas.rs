@@ -1,15 +1,29 @@
+use druid::kurbo::{BezPath, Shape};
 use druid::{
-    BoxConstraints, Data, Env, Event, EventCtx, LayoutCtx, LifeCycle, LifeCycleCtx, PaintCtx, Size,
-    UpdateCtx, Widget,
+    BoxConstraints, Data, Env, Event, EventCtx, LayoutCtx, LifeCycle, LifeCycleCtx, PaintCtx,
+    Point, Rect, RenderContext, Size, UpdateCtx, Vec2, Widget,
 };
 use paintr_core::{CanvasData, Paintable};
 
+use crate::theme_ext::{
+    PAINTR_CANVAS_BORDER, PAINTR_CANVAS_SHADOW, PAINTR_CHECKER_DARK, PAINTR_CHECKER_LIGHT,
+    PAINTR_CHECKER_SIZE, PAINTR_PASTEBOARD,
+};
+use crate::Viewport;
+
 #[derive(Debug)]
-pub struct Canvas {}
+pub struct Canvas {
+    /// The view transform of the editor which paints the canvas
+    view: Viewport,
+}
 
 impl Canvas {
     pub fn new() -> Canvas {
-        Canvas {}
+        Canvas { view: Viewport::default() }
+    }
+
+    pub fn set_view(&mut self, view: Viewport) {
+        self.view = view;
     }
 }
 
@@ -43,11 +57,65 @@ impl Widget<DataType> for Canvas {
         data.as_ref().and_then(|data| data.paint_size()).unwrap_or_else(|| bc.max())
     }
 
-    fn paint(&mut self, paint_ctx: &mut PaintCtx, data: &DataType, _env: &Env) {
-        if let Some(canvas) = &data {
-            canvas.paint(paint_ctx);
+    fn paint(&mut self, paint_ctx: &mut PaintCtx, data: &DataType, env: &Env) {
+        let canvas = match data {
+            Some(it) => it,
+            None => return,
+        };
+        let bounds = match canvas.paint_size() {
+            Some(size) => size.to_rect(),
+            None => return,
+        };
+
+        // The canvas is painted with the view transform, so sizes on screen are scaled back
+        let scale = self.view.zoom();
+        let visible = self.view.affine().inverse().transform_rect_bbox(paint_ctx.size().to_rect());
+
+        paint_ctx.fill(visible, &env.get(PAINTR_PASTEBOARD));
+        paint_ctx.blurred_rect(
+            bounds + Vec2::new(2.0, 2.0) / scale,
+            4.0 / scale,
+            &env.get(PAINTR_CANVAS_SHADOW),
+        );
+
+        paint_checkerboard(
+            paint_ctx,
+            bounds.intersect(visible),
+            env.get(PAINTR_CHECKER_SIZE) / scale,
+            env,
+        );
+        canvas.paint(paint_ctx);
+
+        let border = 1.0 / scale;
+        paint_ctx.stroke(
+            bounds.inflate(border / 2.0, border / 2.0),
+            &env.get(PAINTR_CANVAS_BORDER),
+            border,
+        );
+    }
+}
+
+/// Paint the checkerboard inside `rect`, the cells are aligned to the origin
+fn paint_checkerboard(paint_ctx: &mut PaintCtx, rect: Rect, cell: f64, env: &Env) {
+    if rect.area() <= 0.0 || cell <= 0.0 {
+        return;
+    }
+    paint_ctx.fill(rect, &env.get(PAINTR_CHECKER_LIGHT));
+
+    let (x0, y0) = ((rect.x0 / cell).floor() as i64, (rect.y0 / cell).floor() as i64);
+    let (x1, y1) = ((rect.x1 / cell).ceil() as i64, (rect.y1 / cell).ceil() as i64);
+
+    let mut path = BezPath::new();
+    for y in y0..y1 {
+        // Start from the first dark cell of the row
+        let first = x0 + (x0 + y).rem_euclid(2);
+        for x in (first..x1).step_by(2) {
+            let origin = Point::new(x as f64 * cell, y as f64 * cell);
+            let cell = Rect::from_origin_size(origin, (cell, cell)).intersect(rect);
+            path.extend(cell.path_elements(0.1));
         }
     }
+    paint_ctx.fill(path, &env.get(PAINTR_CHECKER_DARK));
 }
 
 fn is_same(a: &DataType, b: &DataType) -> bool {
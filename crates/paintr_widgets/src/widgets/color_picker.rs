@@ -0,0 +1,432 @@
+//! A color picker with a HSV square, hue and alpha sliders and text entries
+
+use std::fmt;
+use std::str::FromStr;
+
+use druid::kurbo::{Circle, Line};
+use druid::lens::{self, Lens};
+use druid::widget::{Flex, Label, Parse, TextBox, WidgetExt};
+use druid::{
+    BoxConstraints, Color, Data, Env, Event, EventCtx, LayoutCtx, LifeCycle, LifeCycleCtx,
+    LinearGradient, PaintCtx, Point, RenderContext, Size, UnitPoint, UpdateCtx, Widget, WidgetPod,
+};
+
+const SQUARE_SIZE: f64 = 150.0;
+const BAR_SIZE: f64 = 16.0;
+const SPACING: f64 = 6.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Data)]
+struct Hsva {
+    /// Hue in degrees, from 0.0 to 360.0
+    hue: f64,
+    saturation: f64,
+    value: f64,
+    alpha: f64,
+}
+
+impl Hsva {
+    /// Convert from a color, `hue` is kept if the color has no hue (e.g. gray)
+    fn from_color(color: &Color, hue: f64) -> Hsva {
+        let (r, g, b, alpha) = color.as_rgba();
+        let max = r.max(g).max(b);
+        let d = max - r.min(g).min(b);
+
+        let hue = if d <= 0.0 {
+            hue
+        } else if max == r {
+            60.0 * ((g - b) / d).rem_euclid(6.0)
+        } else if max == g {
+            60.0 * ((b - r) / d + 2.0)
+        } else {
+            60.0 * ((r - g) / d + 4.0)
+        };
+        let saturation = if max <= 0.0 { 0.0 } else { d / max };
+
+        Hsva { hue, saturation, value: max, alpha }
+    }
+
+    fn to_color(&self) -> Color {
+        let c = self.value * self.saturation;
+        let h = (self.hue / 60.0).rem_euclid(6.0);
+        let x = c * (1.0 - (h % 2.0 - 1.0).abs());
+
+        let (r, g, b) = match h as u32 {
+            0 => (c, x, 0.0),
+            1 => (x, c, 0.0),
+            2 => (0.0, c, x),
+            3 => (0.0, x, c),
+            4 => (x, 0.0, c),
+            _ => (c, 0.0, x),
+        };
+        let m = self.value - c;
+        Color::rgba(r + m, g + m, b + m, self.alpha)
+    }
+
+    fn pure_hue(&self) -> Color {
+        Hsva { hue: self.hue, saturation: 1.0, value: 1.0, alpha: 1.0 }.to_color()
+    }
+}
+
+/// A color which is displayed and parsed as `#rrggbb` or `#rrggbbaa`
+#[derive(Debug, Clone, Data)]
+struct HexColor(Color);
+
+impl fmt::Display for HexColor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (r, g, b, a) = self.0.as_rgba8();
+        match a {
+            0xff => write!(f, "#{:02x}{:02x}{:02x}", r, g, b),
+            _ => write!(f, "#{:02x}{:02x}{:02x}{:02x}", r, g, b, a),
+        }
+    }
+}
+
+impl FromStr for HexColor {
+    type Err = std::num::ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().trim_start_matches('#');
+        // Make sure an incomplete input fails to parse
+        let s = match s.len() {
+            6 => format!("{}ff", s),
+            8 => s.to_owned(),
+            _ => format!("{}_", s),
+        };
+        let rgba = u32::from_str_radix(&s, 16)?;
+        Ok(HexColor(Color::from_rgba32_u32(rgba)))
+    }
+}
+
+fn hex_lens() -> impl Lens<Hsva, Option<HexColor>> {
+    lens::Map::new(
+        |data: &Hsva| Some(HexColor(data.to_color())),
+        |data: &mut Hsva, hex: Option<HexColor>| {
+            if let Some(HexColor(color)) = hex {
+                if !color.same(&data.to_color()) {
+                    *data = Hsva::from_color(&color, data.hue);
+                }
+            }
+        },
+    )
+}
+
+fn component_lens(index: usize) -> impl Lens<Hsva, Option<u8>> {
+    let components = |data: &Hsva| {
+        let (r, g, b, a) = data.to_color().as_rgba8();
+        [r, g, b, a]
+    };
+
+    lens::Map::new(
+        move |data: &Hsva| Some(components(data)[index]),
+        move |data: &mut Hsva, value: Option<u8>| {
+            let mut rgba = components(data);
+            match value {
+                Some(value) if value != rgba[index] => {
+                    rgba[index] = value;
+                    let color = Color::rgba8(rgba[0], rgba[1], rgba[2], rgba[3]);
+                    *data = Hsva::from_color(&color, data.hue);
+                }
+                _ => (),
+            }
+        },
+    )
+}
+
+/// The square to pick saturation (horizontal) and value (vertical)
+struct SaturationValue;
+
+impl SaturationValue {
+    fn pick(size: Size, pos: Point, data: &mut Hsva) {
+        data.saturation = (pos.x / size.width).clamp(0.0, 1.0);
+        data.value = 1.0 - (pos.y / size.height).clamp(0.0, 1.0);
+    }
+}
+
+impl Widget<Hsva> for SaturationValue {
+    fn event(&mut self, ctx: &mut EventCtx, event: &Event, data: &mut Hsva, _env: &Env) {
+        match event {
+            Event::MouseDown(me) => {
+                ctx.set_active(true);
+                Self::pick(ctx.size(), me.pos, data);
+            }
+            Event::MouseMove(me) if ctx.is_active() => Self::pick(ctx.size(), me.pos, data),
+            Event::MouseUp(_) => ctx.set_active(false),
+            _ => (),
+        }
+    }
+
+    fn lifecycle(&mut self, _ctx: &mut LifeCycleCtx, _event: &LifeCycle, _data: &Hsva, _env: &Env) {
+    }
+
+    fn update(&mut self, ctx: &mut UpdateCtx, old_data: &Hsva, data: &Hsva, _env: &Env) {
+        if !old_data.same(data) {
+            ctx.request_paint();
+        }
+    }
+
+    fn layout(
+        &mut self,
+        _ctx: &mut LayoutCtx,
+        bc: &BoxConstraints,
+        _data: &Hsva,
+        _env: &Env,
+    ) -> Size {
+        bc.constrain((SQUARE_SIZE, SQUARE_SIZE))
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, data: &Hsva, _env: &Env) {
+        let size = ctx.size();
+        let rect = size.to_rect();
+
+        let white = LinearGradient::new(
+            UnitPoint::LEFT,
+            UnitPoint::RIGHT,
+            (Color::WHITE, Color::WHITE.with_alpha(0.0)),
+        );
+        let black = LinearGradient::new(
+            UnitPoint::TOP,
+            UnitPoint::BOTTOM,
+            (Color::BLACK.with_alpha(0.0), Color::BLACK),
+        );
+        ctx.fill(rect, &data.pure_hue());
+        ctx.fill(rect, &white);
+        ctx.fill(rect, &black);
+
+        let center = Point::new(data.saturation * size.width, (1.0 - data.value) * size.height);
+        ctx.stroke(Circle::new(center, 5.0), &Color::BLACK, 1.0);
+        ctx.stroke(Circle::new(center, 4.0), &Color::WHITE, 1.0);
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Channel {
+    /// A vertical bar of hue
+    Hue,
+    /// A horizontal bar of alpha
+    Alpha,
+}
+
+struct ChannelBar(Channel);
+
+impl ChannelBar {
+    fn pick(&self, size: Size, pos: Point, data: &mut Hsva) {
+        match self.0 {
+            Channel::Hue => data.hue = (pos.y / size.height).clamp(0.0, 1.0) * 360.0,
+            Channel::Alpha => data.alpha = (pos.x / size.width).clamp(0.0, 1.0),
+        }
+    }
+}
+
+impl Widget<Hsva> for ChannelBar {
+    fn event(&mut self, ctx: &mut EventCtx, event: &Event, data: &mut Hsva, _env: &Env) {
+        match event {
+            Event::MouseDown(me) => {
+                ctx.set_active(true);
+                self.pick(ctx.size(), me.pos, data);
+            }
+            Event::MouseMove(me) if ctx.is_active() => self.pick(ctx.size(), me.pos, data),
+            Event::MouseUp(_) => ctx.set_active(false),
+            _ => (),
+        }
+    }
+
+    fn lifecycle(&mut self, _ctx: &mut LifeCycleCtx, _event: &LifeCycle, _data: &Hsva, _env: &Env) {
+    }
+
+    fn update(&mut self, ctx: &mut UpdateCtx, old_data: &Hsva, data: &Hsva, _env: &Env) {
+        if !old_data.same(data) {
+            ctx.request_paint();
+        }
+    }
+
+    fn layout(
+        &mut self,
+        _ctx: &mut LayoutCtx,
+        bc: &BoxConstraints,
+        _data: &Hsva,
+        _env: &Env,
+    ) -> Size {
+        match self.0 {
+            Channel::Hue => bc.constrain((BAR_SIZE, SQUARE_SIZE)),
+            Channel::Alpha => bc.constrain((SQUARE_SIZE + SPACING + BAR_SIZE, BAR_SIZE)),
+        }
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, data: &Hsva, _env: &Env) {
+        let size = ctx.size();
+        let rect = size.to_rect();
+
+        let marker = match self.0 {
+            Channel::Hue => {
+                let stops: Vec<_> =
+                    (0..=6).map(|i| Hsva { hue: i as f64 * 60.0, ..*data }.pure_hue()).collect();
+                ctx.fill(rect, &LinearGradient::new(UnitPoint::TOP, UnitPoint::BOTTOM, &stops[..]));
+
+                let y = data.hue / 360.0 * size.height;
+                Line::new((0.0, y), (size.width, y))
+            }
+            Channel::Alpha => {
+                let color = Hsva { alpha: 1.0, ..*data }.to_color();
+                let gradient = LinearGradient::new(
+                    UnitPoint::LEFT,
+                    UnitPoint::RIGHT,
+                    (color.clone().with_alpha(0.0), color),
+                );
+                ctx.fill(rect, &Color::grey(0.8));
+                ctx.fill(rect, &gradient);
+
+                let x = data.alpha * size.width;
+                Line::new((x, 0.0), (x, size.height))
+            }
+        };
+
+        ctx.stroke(marker, &Color::BLACK, 3.0);
+        ctx.stroke(marker, &Color::WHITE, 1.0);
+    }
+}
+
+/// A color picker
+///
+/// The hue is kept while the color has no hue, e.g. when saturation drops to zero.
+pub struct ColorPicker {
+    hsva: Hsva,
+    inner: WidgetPod<Hsva, Box<dyn Widget<Hsva>>>,
+}
+
+impl ColorPicker {
+    pub fn new() -> ColorPicker {
+        let inner = WidgetPod::new(Self::build()).boxed();
+        ColorPicker { hsva: Hsva::from_color(&Color::BLACK, 0.0), inner }
+    }
+
+    fn build() -> impl Widget<Hsva> {
+        let entry = |label: &str, lens| {
+            Flex::row()
+                .with_child(Label::new(label.to_owned()))
+                .with_child(Parse::new(TextBox::new()).lens(lens).fix_width(34.0))
+        };
+
+        let components = Flex::row()
+            .with_child(entry("R", component_lens(0)))
+            .with_child(entry("G", component_lens(1)))
+            .with_child(entry("B", component_lens(2)))
+            .with_child(entry("A", component_lens(3)));
+
+        let hex = Flex::row()
+            .with_child(Label::new("#"))
+            .with_flex_child(Parse::new(TextBox::new()).lens(hex_lens()).expand_width(), 1.0);
+
+        Flex::column()
+            .with_child(
+                Flex::row()
+                    .with_child(SaturationValue)
+                    .with_spacer(SPACING)
+                    .with_child(ChannelBar(Channel::Hue)),
+            )
+            .with_spacer(SPACING)
+            .with_child(ChannelBar(Channel::Alpha))
+            .with_spacer(SPACING)
+            .with_child(hex.fix_width(SQUARE_SIZE + SPACING + BAR_SIZE))
+            .with_child(components)
+            .padding(5.0)
+    }
+}
+
+impl Default for ColorPicker {
+    fn default() -> ColorPicker {
+        ColorPicker::new()
+    }
+}
+
+impl Widget<Color> for ColorPicker {
+    fn event(&mut self, ctx: &mut EventCtx, event: &Event, data: &mut Color, env: &Env) {
+        let old = self.hsva;
+        self.inner.event(ctx, event, &mut self.hsva, env);
+
+        if old != self.hsva {
+            // The color may stay the same while the hue is changed
+            ctx.request_update();
+
+            let color = self.hsva.to_color();
+            if !color.same(data) {
+                *data = color;
+            }
+        }
+    }
+
+    fn lifecycle(&mut self, ctx: &mut LifeCycleCtx, event: &LifeCycle, data: &Color, env: &Env) {
+        if let LifeCycle::WidgetAdded = event {
+            self.hsva = Hsva::from_color(data, 0.0);
+        }
+        self.inner.lifecycle(ctx, event, &self.hsva, env);
+    }
+
+    fn update(&mut self, ctx: &mut UpdateCtx, _old_data: &Color, data: &Color, env: &Env) {
+        // Only take the color from outside, otherwise the precision of hsv is lost
+        if !self.hsva.to_color().same(data) {
+            self.hsva = Hsva::from_color(data, self.hsva.hue);
+        }
+        self.inner.update(ctx, &self.hsva, env);
+    }
+
+    fn layout(
+        &mut self,
+        ctx: &mut LayoutCtx,
+        bc: &BoxConstraints,
+        _data: &Color,
+        env: &Env,
+    ) -> Size {
+        let size = self.inner.layout(ctx, bc, &self.hsva, env);
+        self.inner.set_layout_rect(ctx, &self.hsva, env, size.to_rect());
+        size
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, _data: &Color, env: &Env) {
+        self.inner.paint(ctx, &self.hsva, env);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn hsva_should_round_trip() {
+        let colors = [
+            Color::rgba8(0xff, 0x00, 0x00, 0xff),
+            Color::rgba8(0x12, 0x34, 0x56, 0x78),
+            Color::rgba8(0xff, 0xc9, 0x22, 0xff),
+            Color::rgba8(0x80, 0x80, 0x80, 0x00),
+        ];
+
+        for color in colors.iter() {
+            let hsva = Hsva::from_color(color, 0.0);
+            assert_eq!(hsva.to_color().as_rgba8(), color.as_rgba8());
+        }
+    }
+
+    #[test]
+    fn hsva_should_keep_hue_of_gray() {
+        let hsva = Hsva::from_color(&Color::grey8(0x80), 120.0);
+        assert_eq!(hsva.hue, 120.0);
+        assert_eq!(hsva.saturation, 0.0);
+
+        let hsva = Hsva::from_color(&Color::rgb8(0, 0xff, 0), 0.0);
+        assert_eq!(hsva.hue, 120.0);
+    }
+
+    #[test]
+    fn hex_color_should_parse() {
+        let hex: HexColor = "#ffc922".parse().unwrap();
+        assert_eq!(hex.0.as_rgba8(), (0xff, 0xc9, 0x22, 0xff));
+        assert_eq!(hex.to_string(), "#ffc922");
+
+        let hex: HexColor = "12345678".parse().unwrap();
+        assert_eq!(hex.0.as_rgba8(), (0x12, 0x34, 0x56, 0x78));
+        assert_eq!(hex.to_string(), "#12345678");
+
+        assert!("#fff".parse::<HexColor>().is_err());
+        assert!("#ffc92".parse::<HexColor>().is_err());
+        assert!("#gggggg".parse::<HexColor>().is_err());
+    }
+}
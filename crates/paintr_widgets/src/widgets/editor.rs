@@ -1,14 +1,27 @@
 use std::any::Any;
 
+use druid::kurbo::Shape;
 use druid::{
-    BoxConstraints, Env, Event, EventCtx, LayoutCtx, LifeCycle, LifeCycleCtx, PaintCtx, Size,
-    UpdateCtx, Widget,
+    BoxConstraints, Cursor, Data, Env, Event, EventCtx, KbKey, LayoutCtx, LifeCycle, LifeCycleCtx,
+    MouseButton, MouseEvent, PaintCtx, Point, RenderContext, Selector, Size, UpdateCtx, Widget,
 };
 
 use super::canvas::Canvas;
-use crate::EditorState;
+use crate::{EditorState, Viewport};
 use paintr_core::Paintable;
 
+/// Change the zoom of the editor
+pub const ZOOM_ACTION: Selector<Zoom> = Selector::new("paintr-widgets.editor.zoom");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Zoom {
+    In,
+    Out,
+    FitToWindow,
+    ActualSize,
+    Selection,
+}
+
 pub trait ToolCtx {
     fn into_any(self: Box<Self>) -> Box<dyn Any>;
 }
@@ -54,11 +67,121 @@ pub trait ToolKind: Copy {
 pub struct Editor {
     tool_ctx: Option<Box<dyn ToolCtx>>,
     canvas: Canvas,
+    space_down: bool,
+    /// The cursor of the tool, restored when space is released
+    tool_cursor: Option<Cursor>,
+    /// The last mouse position while panning
+    panning: Option<Point>,
 }
 
 impl Editor {
     pub fn new() -> Editor {
-        Editor { canvas: Canvas::new(), tool_ctx: None }
+        Editor {
+            canvas: Canvas::new(),
+            tool_ctx: None,
+            space_down: false,
+            tool_cursor: None,
+            panning: None,
+        }
+    }
+
+    fn zoom<T>(&self, size: Size, zoom: Zoom, data: &mut EditorState<T>) {
+        let center = size.to_rect().center();
+        let canvas = match &data.canvas {
+            Some(it) => it,
+            None => return,
+        };
+
+        match zoom {
+            Zoom::In => data.view.zoom_in(center),
+            Zoom::Out => data.view.zoom_out(center),
+            Zoom::ActualSize => data.view.zoom_around(1.0, center),
+            Zoom::FitToWindow => {
+                if let Some(doc_size) = canvas.paint_size() {
+                    data.view.fit(doc_size.to_rect(), size);
+                }
+            }
+            Zoom::Selection => {
+                if let Some(sel) = canvas.selection() {
+                    data.view.fit(sel.bounds(), size);
+                }
+            }
+        }
+    }
+
+    /// Handle zooming and panning, returns true if the event is consumed
+    fn view_event<T>(
+        &mut self,
+        ctx: &mut EventCtx,
+        event: &Event,
+        data: &mut EditorState<T>,
+    ) -> bool {
+        match event {
+            Event::Command(cmd) if cmd.is(ZOOM_ACTION) => {
+                self.zoom(ctx.size(), *cmd.get_unchecked(ZOOM_ACTION), data);
+            }
+            Event::Wheel(me) if me.mods.ctrl() => {
+                if me.wheel_delta.y < 0.0 {
+                    data.view.zoom_in(me.pos);
+                } else if me.wheel_delta.y > 0.0 {
+                    data.view.zoom_out(me.pos);
+                }
+            }
+            Event::Wheel(me) => data.view.pan(-me.wheel_delta),
+            Event::KeyDown(key) if key.key == KbKey::Character(" ".to_string()) => {
+                if !self.space_down {
+                    self.space_down = true;
+                    self.tool_cursor = data.cursor.replace(Cursor::OpenHand);
+                    ctx.set_cursor(&Cursor::OpenHand);
+                }
+                return true;
+            }
+            Event::KeyUp(key) if key.key == KbKey::Character(" ".to_string()) => {
+                if self.space_down {
+                    self.space_down = false;
+                    data.cursor = self.tool_cursor.take();
+                    match &data.cursor {
+                        Some(cursor) => ctx.set_cursor(cursor),
+                        None => ctx.clear_cursor(),
+                    }
+                }
+                return true;
+            }
+            Event::MouseDown(me)
+                if self.tool_ctx.is_none()
+                    && (self.space_down || me.button == MouseButton::Middle) =>
+            {
+                ctx.set_active(true);
+                self.panning = Some(me.pos);
+                return true;
+            }
+            Event::MouseMove(me) if self.panning.is_some() => {
+                let last = self.panning.replace(me.pos).unwrap_or(me.pos);
+                data.view.pan(me.pos - last);
+            }
+            Event::MouseUp(_) if self.panning.is_some() => {
+                self.panning = None;
+                ctx.set_active(false);
+                return true;
+            }
+            _ => return false,
+        }
+
+        ctx.request_paint();
+        ctx.set_handled();
+        true
+    }
+}
+
+/// Convert the position of mouse events into document coordinates
+fn to_doc_event(event: &Event, view: &Viewport) -> Event {
+    let convert = |me: &MouseEvent| MouseEvent { pos: view.to_doc(me.pos), ..me.clone() };
+
+    match event {
+        Event::MouseDown(me) => Event::MouseDown(convert(me)),
+        Event::MouseUp(me) => Event::MouseUp(convert(me)),
+        Event::MouseMove(me) => Event::MouseMove(convert(me)),
+        _ => event.clone(),
     }
 }
 
@@ -76,10 +199,19 @@ where
     }
 
     fn event(&mut self, ctx: &mut EventCtx, event: &Event, data: &mut EditorState<T>, env: &Env) {
+        if let Event::MouseDown(_) = event {
+            ctx.request_focus();
+        }
+        if self.view_event(ctx, event, data) {
+            return;
+        }
+
+        // Tools work in document coordinates
+        let event = to_doc_event(event, &data.view);
         let tool = data.tool;
-        tool.event(ctx, event, data, &mut self.tool_ctx);
+        tool.event(ctx, &event, data, &mut self.tool_ctx);
 
-        self.canvas.event(ctx, event, &mut data.canvas, env);
+        self.canvas.event(ctx, &event, &mut data.canvas, env);
     }
 
     fn update(
@@ -89,6 +221,9 @@ where
         data: &EditorState<T>,
         env: &Env,
     ) {
+        if !old_data.view.same(&data.view) {
+            ctx.request_paint();
+        }
         self.canvas.update(ctx, &old_data.canvas, &data.canvas, env)
     }
 
@@ -99,16 +234,25 @@ where
         data: &EditorState<T>,
         env: &Env,
     ) -> Size {
-        self.canvas.layout(layout_ctx, bc, &data.canvas, env)
+        // The canvas is scrolled by the view, so just take all the space
+        self.canvas.layout(layout_ctx, bc, &data.canvas, env);
+        bc.max()
     }
 
     fn paint(&mut self, paint_ctx: &mut PaintCtx, data: &EditorState<T>, env: &Env) {
-        self.canvas.paint(paint_ctx, &data.canvas, env);
+        let rect = paint_ctx.size().to_rect();
+
+        paint_ctx.with_save(|paint_ctx| {
+            paint_ctx.clip(rect);
+            paint_ctx.transform(data.view.affine());
+            self.canvas.set_view(data.view);
+            self.canvas.paint(paint_ctx, &data.canvas, env);
 
-        if let Some(canvas) = &data.canvas {
-            if let Some(selection) = canvas.selection().as_ref() {
-                selection.paint(paint_ctx);
+            if let Some(canvas) = &data.canvas {
+                if let Some(selection) = canvas.selection().as_ref() {
+                    selection.paint(paint_ctx);
+                }
             }
-        }
+        });
     }
 }
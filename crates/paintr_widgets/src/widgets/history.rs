@@ -0,0 +1,166 @@
+//! History panel
+//!
+//! A widget listing the snapshots and the undo steps of the editor
+use druid::widget::{Flex, Label, WidgetExt};
+use druid::{
+    BoxConstraints, Color, Data, Env, Event, EventCtx, LayoutCtx, LifeCycle, LifeCycleCtx,
+    PaintCtx, Point, Size, UpdateCtx, Widget, WidgetPod,
+};
+
+use crate::EditorState;
+use paintr_core::HistoryEntry;
+
+/// Everything shown by the panel, the rows are rebuilt when it changes
+#[derive(Debug, Clone, PartialEq, Default)]
+struct Rows {
+    snapshots: Vec<String>,
+    name: String,
+    entries: Vec<String>,
+    position: usize,
+}
+
+impl Rows {
+    fn new<T>(data: &EditorState<T>) -> Rows {
+        let canvas = match &data.canvas {
+            Some(it) => it,
+            None => return Rows::default(),
+        };
+
+        Rows {
+            snapshots: data.history.snapshots().map(String::from).collect(),
+            name: canvas.path().file_name().unwrap_or_default().to_string_lossy().into(),
+            entries: data.history.entries().iter().map(entry_text).collect(),
+            position: data.history.position(),
+        }
+    }
+}
+
+/// The description of the step, with its branch if it has siblings
+fn entry_text(entry: &HistoryEntry) -> String {
+    match entry.branches() {
+        1 => entry.desc().to_string(),
+        n => format!("{}  [{}/{}]", entry.desc(), entry.branch() + 1, n),
+    }
+}
+
+pub struct HistoryPanel<T> {
+    inner: WidgetPod<EditorState<T>, Box<dyn Widget<EditorState<T>>>>,
+    rows: Option<Rows>,
+}
+
+impl<T: Data> Default for HistoryPanel<T> {
+    fn default() -> HistoryPanel<T> {
+        HistoryPanel::new()
+    }
+}
+
+impl<T: Data> HistoryPanel<T> {
+    pub fn new() -> HistoryPanel<T> {
+        HistoryPanel { inner: WidgetPod::new(Flex::column()).boxed(), rows: None }
+    }
+
+    /// Rebuild the rows if they are changed, returns true if rebuilt
+    fn rebuild(&mut self, data: &EditorState<T>) -> bool {
+        let rows = Rows::new(data);
+        if self.rows.as_ref() == Some(&rows) {
+            return false;
+        }
+
+        let mut col = Flex::column();
+        for (index, name) in rows.snapshots.iter().enumerate() {
+            col.add_child(row(name, false, false, move |data: &mut EditorState<T>| {
+                data.do_restore_snapshot(index);
+            }));
+        }
+        if !rows.snapshots.is_empty() {
+            col.add_spacer(5.0);
+        }
+
+        // The first row is the state before all steps
+        let steps = std::iter::once(&rows.name).chain(rows.entries.iter());
+        if data.canvas.is_some() {
+            for (position, desc) in steps.enumerate() {
+                let (current, undone) = (position == rows.position, position > rows.position);
+                col.add_child(row(desc, current, undone, move |data: &mut EditorState<T>| {
+                    data.do_goto(position);
+                }));
+            }
+        }
+
+        self.inner = WidgetPod::new(col).boxed();
+        self.rows = Some(rows);
+        true
+    }
+}
+
+fn row<T: Data>(
+    text: &str,
+    current: bool,
+    undone: bool,
+    on_click: impl Fn(&mut EditorState<T>) + 'static,
+) -> impl Widget<EditorState<T>> {
+    let color = match undone {
+        true => Color::grey(0.5),
+        false => Color::WHITE,
+    };
+    let background = match current {
+        true => Color::rgb8(0x11, 0x93, 0x92),
+        false => Color::TRANSPARENT,
+    };
+
+    Label::new(text.to_owned())
+        .with_text_color(color)
+        .padding((8.0, 3.0))
+        .expand_width()
+        .background(background)
+        .on_click(move |_, data: &mut EditorState<T>, _| on_click(data))
+}
+
+impl<T: Data> Widget<EditorState<T>> for HistoryPanel<T> {
+    fn lifecycle(
+        &mut self,
+        ctx: &mut LifeCycleCtx,
+        event: &LifeCycle,
+        data: &EditorState<T>,
+        env: &Env,
+    ) {
+        if let LifeCycle::WidgetAdded = event {
+            self.rebuild(data);
+        }
+        self.inner.lifecycle(ctx, event, data, env);
+    }
+
+    fn event(&mut self, ctx: &mut EventCtx, event: &Event, data: &mut EditorState<T>, env: &Env) {
+        self.inner.event(ctx, event, data, env);
+    }
+
+    fn update(
+        &mut self,
+        ctx: &mut UpdateCtx,
+        _old_data: &EditorState<T>,
+        data: &EditorState<T>,
+        env: &Env,
+    ) {
+        if self.rebuild(data) {
+            ctx.children_changed();
+        } else {
+            self.inner.update(ctx, data, env);
+        }
+    }
+
+    fn layout(
+        &mut self,
+        ctx: &mut LayoutCtx,
+        bc: &BoxConstraints,
+        data: &EditorState<T>,
+        env: &Env,
+    ) -> Size {
+        let size = self.inner.layout(ctx, bc, data, env);
+        self.inner.set_origin(ctx, data, env, Point::ORIGIN);
+        size
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, data: &EditorState<T>, env: &Env) {
+        self.inner.paint(ctx, data, env);
+    }
+}
@@ -46,6 +46,7 @@ pub struct Notification {
 #[derive(Debug, Clone, Eq, PartialEq, Hash, Data)]
 pub enum NotificationKind {
     Info,
+    Warning,
     Error,
 }
 
@@ -54,6 +55,10 @@ impl Notification {
         Notification { kind: NotificationKind::Info, msg: Arc::new(s.into()) }
     }
 
+    pub fn warning(s: impl Into<String>) -> Notification {
+        Notification { kind: NotificationKind::Warning, msg: Arc::new(s.into()) }
+    }
+
     pub fn error(s: impl Into<String>) -> Notification {
         Notification { kind: NotificationKind::Error, msg: Arc::new(s.into()) }
     }
@@ -71,6 +76,7 @@ impl<T: Data, L: lens::Lens<T, NotificationsData> + 'static + Clone> Notificatio
                         let rt = Rect::from_origin_size(Point::ORIGIN, paint_ctx.size());
                         let color = match data.kind {
                             NotificationKind::Info => Color::grey(0.3),
+                            NotificationKind::Warning => Color::rgb(0.6, 0.4, 0.0),
                             NotificationKind::Error => Color::rgb(0.6, 0.0, 0.0),
                         };
 
@@ -2,17 +2,100 @@ menu-file-menu = File
 menu-file-new = New File
 menu-file-new-clipboard = New File From Clipboard
 menu-file-open = Open File...
+menu-file-open-recent = Open Recent
+menu-file-recent-item = { $path }
+menu-file-clear-recent = Clear Recent
+menu-file-save = Save
 menu-file-save-as = Save As...
+menu-file-export-as = Export As...
 menu-file-exit = Exit
 
 menu-edit-menu = Edit
 menu-edit-undo = Undo
 menu-edit-redo = Redo
+menu-edit-previous-branch = Previous Branch
+menu-edit-next-branch = Next Branch
 menu-edit-copy = Copy
 menu-edit-paste = Paste
+menu-edit-fill-foreground = Fill with Foreground Color
+menu-edit-fill-background = Fill with Background Color
+menu-edit-select-color-range = Select Color Range...
+menu-edit-swap-colors = Swap Colors
+menu-edit-reset-colors = Reset Colors
+
+menu-select-menu = Select
+menu-select-all = All
+menu-select-deselect = Deselect
+menu-select-invert = Inverse
+menu-select-grow = Grow...
+menu-select-shrink = Shrink...
+menu-select-border = Border...
+menu-select-smooth = Smooth...
+menu-select-feather = Feather...
+
+menu-layer-menu = Layer
+menu-layer-new = New Layer
+menu-layer-duplicate = Duplicate Layer
+menu-layer-delete = Delete Layer
+menu-layer-merge-down = Merge Down
+menu-layer-raise = Raise Layer
+menu-layer-lower = Lower Layer
+menu-layer-select-above = Select Layer Above
+menu-layer-select-below = Select Layer Below
+menu-layer-hide = Hide Layer
+menu-layer-lock-pixels = Lock Pixels
+menu-layer-lock-position = Lock Position
+menu-layer-properties = Layer Properties...
+menu-layer-blend-mode = Blend Mode
+
+menu-blend-normal = Normal
+menu-blend-multiply = Multiply
+menu-blend-screen = Screen
+menu-blend-overlay = Overlay
+menu-blend-darken = Darken
+menu-blend-lighten = Lighten
+menu-blend-color-dodge = Color Dodge
+menu-blend-color-burn = Color Burn
+menu-blend-hard-light = Hard Light
+menu-blend-soft-light = Soft Light
+menu-blend-difference = Difference
+menu-blend-exclusion = Exclusion
+menu-blend-hue = Hue
+menu-blend-saturation = Saturation
+menu-blend-color = Color
+menu-blend-luminosity = Luminosity
+
+menu-view-menu = View
+menu-view-zoom-in = Zoom In
+menu-view-zoom-out = Zoom Out
+menu-view-zoom-fit = Fit to Window
+menu-view-zoom-actual = Actual Size
+menu-view-zoom-selection = Zoom to Selection
+menu-view-history = History
 
 menu-about-menu = About
 menu-about-test = Test
 
+brush-size = Size
+brush-hardness = Hardness
+brush-spacing = Spacing
+brush-flow = Flow
+brush-opacity = Opacity
+
+selection-anti-alias = Anti-alias
+
+magic-wand-tolerance = Tolerance
+magic-wand-contiguous = Contiguous
+magic-wand-sample-merged = Sample Merged
+
+color-swap = Swap
+color-reset = Reset
+
+history-title = History
+history-new-snapshot = New Snapshot
+history-new-document = New Document
+
+export-options-title = { $format } Options
+
 paintr-front-page-welcome = Paintr, a simple painter
 paint-app-name = Paintr
\ No newline at end of file